@@ -0,0 +1,78 @@
+use crate::vec3::{Point3, point3};
+use crate::ray::Ray;
+
+// axis aligned bounding box, as in "The Next Week", section 3.4
+#[derive(Debug, Clone)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Self {
+        Aabb{minimum, maximum}
+    }
+
+    // slab test, Andrew Kensler's version
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.dir.axis(axis);
+            let mut t0 = (self.minimum.axis(axis) - ray.origin.axis(axis)) * inv_d;
+            let mut t1 = (self.maximum.axis(axis) - ray.origin.axis(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false
+            }
+        }
+        true
+    }
+
+    // widens the box along axes thinner than `delta`, so that flat objects
+    // (triangles or quads lying in an axis plane) still get hit by the slab test
+    pub fn pad(&self, delta: f64) -> Aabb {
+        let mut minimum = self.minimum.clone();
+        let mut maximum = self.maximum.clone();
+        for (lo, hi) in [(&mut minimum.x, &mut maximum.x),
+                         (&mut minimum.y, &mut maximum.y),
+                         (&mut minimum.z, &mut maximum.z)] {
+            if *hi - *lo < delta {
+                *lo -= delta / 2.0;
+                *hi += delta / 2.0;
+            }
+        }
+        Aabb{minimum, maximum}
+    }
+
+    pub fn centroid(&self, axis: usize) -> f64 {
+        0.5 * (self.minimum.axis(axis) + self.maximum.axis(axis))
+    }
+
+    // index of the axis along which the box is widest: 0 -> x, 1 -> y, 2 -> z
+    pub fn longest_axis(&self) -> usize {
+        let extent = &self.maximum - &self.minimum;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+    let small = point3(box0.minimum.x.min(box1.minimum.x),
+                       box0.minimum.y.min(box1.minimum.y),
+                       box0.minimum.z.min(box1.minimum.z));
+    let big = point3(box0.maximum.x.max(box1.maximum.x),
+                     box0.maximum.y.max(box1.maximum.y),
+                     box0.maximum.z.max(box1.maximum.z));
+    Aabb::new(small, big)
+}
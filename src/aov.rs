@@ -0,0 +1,327 @@
+// arbitrary output variables: what the camera rays hit first, saved next to
+// the color image for compositing (and for guiding a denoiser)
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec,
+                   Vec2, WritableImage};
+use image::{Rgb, Rgb32FImage, RgbImage};
+
+use crate::hittable::HitRecord;
+use crate::integrator::SceneView;
+use crate::ray::Ray;
+use crate::rtweekend::{INF, Shared, hash_unif};
+use crate::vec3::{Color, Point3, Vec3, vec3_};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    // distance along the camera ray to the first hit
+    Depth,
+    // surface normal on the side facing the camera, in world space
+    Normal,
+    // surface color without lighting, the background's color for misses
+    Albedo,
+    // hit point, in world space
+    Position,
+    // 1-based index of the scene's top level object, 0 for the background
+    ObjectId,
+    // 1-based, materials numbered in the order they first show up in the image
+    MaterialId,
+    // camera rays traced for the pixel, fewer where adaptive sampling stopped early
+    Samples
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Samples => "samples"
+        }
+    }
+
+    // EXR channel names, "<layer>.<channel>"
+    fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Samples => &["count"]
+        }
+    }
+
+    // stored as integers in EXR files
+    fn is_integer(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId | Aov::Samples)
+    }
+}
+
+
+// the first hits of one pixel's camera rays: sums until `AovBuffers::new`
+#[derive(Clone, Default)]
+pub struct AovPixel {
+    depth: f64,
+    normal: Vec3,
+    albedo: Color,
+    position: Point3,
+    object_id: u32,
+    // address of the material of the first sample that hit, 0 before that
+    material: usize,
+    hits: u32,
+    samples: u32
+}
+
+impl AovPixel {
+    // intersects `ray` once more without drawing random numbers, so the color
+    // image is the same with or without AOVs
+    pub fn add_sample(&mut self, ray: &Ray, scene: &SceneView) {
+        let mut rec = HitRecord::default();
+        if !scene.world.hit(ray, 0.001, INF, &mut rec) {
+            self.albedo += &scene.background.color(ray);
+            return
+        }
+        self.hits += 1;
+        self.depth += rec.t * ray.dir.length();
+        self.normal += &rec.normal;
+        self.albedo += &rec.material.albedo(&rec);
+        self.position += &rec.p;
+        if self.material == 0 {
+            self.object_id = rec.object_id;
+            self.material = Shared::as_ptr(&rec.material) as *const () as usize;
+        }
+    }
+
+    // how many samples the pixel got, whether or not they went through add_sample
+    pub fn set_samples(&mut self, samples: u32) {
+        self.samples = samples;
+    }
+}
+
+
+// finished AOVs of a whole image, rows from the top
+pub struct AovBuffers {
+    width: u32,
+    height: u32,
+    pixels: Vec<AovPixel>,
+    material_ids: Vec<u32>
+}
+
+impl AovBuffers {
+    pub fn new(width: u32, height: u32, pixels: Vec<AovPixel>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        let mut numbering = HashMap::new();
+        let material_ids = pixels.iter().map(|p| match p.material {
+            0 => 0,
+            key => {
+                let next = numbering.len() as u32 + 1;
+                *numbering.entry(key).or_insert(next)
+            }
+        }).collect();
+        AovBuffers{width, height, pixels, material_ids}
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.samples as u64).sum()
+    }
+
+    // the value of `aov` at pixel `k` (row major), one entry per channel
+    pub fn value(&self, aov: Aov, k: usize) -> Vec3 {
+        let p = &self.pixels[k];
+        let per_hit = if p.hits > 0 { 1.0 / p.hits as f64 } else { 0.0 };
+        let per_sample = if p.samples > 0 { 1.0 / p.samples as f64 } else { 0.0 };
+        match aov {
+            Aov::Depth => vec3_(p.depth * per_hit, 0., 0.),
+            Aov::Normal => {
+                let n = &p.normal * per_hit;
+                if n.length() > 0.0 { n.unit_vector() } else { n }
+            },
+            Aov::Albedo => &p.albedo * per_sample,
+            Aov::Position => &p.position * per_hit,
+            Aov::ObjectId => vec3_(p.object_id as f64, 0., 0.),
+            Aov::MaterialId => vec3_(self.material_ids[k] as f64, 0., 0.),
+            Aov::Samples => vec3_(p.samples as f64, 0., 0.)
+        }
+    }
+
+    // the raw values; single channel AOVs go to all three channels
+    pub fn image(&self, aov: Aov) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.height, |i, j| {
+            let v = self.value(aov, (j * self.width + i) as usize);
+            if aov.channels().len() == 1 {
+                Rgb([v.x as f32; 3])
+            } else {
+                Rgb([v.x as f32, v.y as f32, v.z as f32])
+            }
+        })
+    }
+
+    // something to look at in an 8-bit file: normals mapped from [-1, 1],
+    // distances scaled by their maximum, a color per ID and a heatmap of the
+    // sample counts
+    pub fn preview(&self, aov: Aov) -> RgbImage {
+        let n = (self.width * self.height) as usize;
+        let values: Vec<Vec3> = (0..n).map(|k| self.value(aov, k)).collect();
+        let max = values.iter().map(|v| v.x.abs().max(v.y.abs()).max(v.z.abs())).fold(1e-12, f64::max);
+        let to_u8 = |x: f64| (255.0 * x.clamp(0.0, 1.0)).round() as u8;
+        RgbImage::from_fn(self.width, self.height, |i, j| {
+            let v = &values[(j * self.width + i) as usize];
+            let rgb = match aov {
+                Aov::Normal => [(v.x + 1.0) / 2.0, (v.y + 1.0) / 2.0, (v.z + 1.0) / 2.0],
+                Aov::Albedo => [v.x, v.y, v.z],
+                Aov::Position => [v.x.abs() / max, v.y.abs() / max, v.z.abs() / max],
+                Aov::Depth => [v.x / max; 3],
+                Aov::Samples => heatmap(v.x / max),
+                Aov::ObjectId | Aov::MaterialId if v.x == 0.0 => [0.0; 3],
+                Aov::ObjectId | Aov::MaterialId =>
+                    [hash_unif(&[v.x, 1.0]), hash_unif(&[v.x, 2.0]), hash_unif(&[v.x, 3.0])]
+            };
+            Rgb([to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2])])
+        })
+    }
+}
+
+
+// dark blue through cyan, green and yellow to red for `x` from 0 to 1
+fn heatmap(x: f64) -> [f64; 3] {
+    const STOPS: [[f64; 3]; 5] = [[0.0, 0.0, 0.5], [0.0, 0.8, 1.0], [0.2, 0.9, 0.2], [1.0, 0.9, 0.0], [0.9, 0.0, 0.0]];
+    let t = x.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let k = (t as usize).min(STOPS.len() - 2);
+    let f = t - k as f64;
+    [0, 1, 2].map(|c| (1.0 - f) * STOPS[k][c] + f * STOPS[k + 1][c])
+}
+
+// `path` with ".<aov>" before the extension: out/box.png -> out/box.depth.png
+pub fn aov_path(path: &str, aov: Aov) -> String {
+    let p = std::path::Path::new(path);
+    match (p.file_stem().and_then(|s| s.to_str()), p.extension().and_then(|s| s.to_str())) {
+        (Some(stem), Some(ext)) => p.with_file_name(format!("{}.{}.{}", stem, aov.name(), ext))
+                                    .to_string_lossy().into_owned(),
+        _ => format!("{}.{}", path, aov.name())
+    }
+}
+
+// one EXR with the color as R, G, B and every AOV as channels of its own layer
+pub fn write_layered_exr(path: &str, img: &Rgb32FImage, aovs: &AovBuffers, list: &[Aov]) -> exr::error::UnitResult {
+    let n = (img.width() * img.height()) as usize;
+    let mut channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = SmallVec::new();
+    for (c, name) in ["R", "G", "B"].iter().enumerate() {
+        let samples = img.pixels().map(|p| p[c]).collect();
+        channels.push(AnyChannel::new(*name, FlatSamples::F32(samples)));
+    }
+    for aov in list {
+        let values: Vec<Vec3> = (0..n).map(|k| aovs.value(*aov, k)).collect();
+        for (c, name) in aov.channels().iter().enumerate() {
+            let component = |v: &Vec3| [v.x, v.y, v.z][c];
+            let samples = if aov.is_integer() {
+                FlatSamples::U32(values.iter().map(|v| component(v) as u32).collect())
+            } else {
+                FlatSamples::F32(values.iter().map(|v| component(v) as f32).collect())
+            };
+            channels.push(AnyChannel::new(format!("{}.{}", aov.name(), name).as_str(), samples));
+        }
+    }
+
+    let size = Vec2(img.width() as usize, img.height() as usize);
+    let layer = Layer::new(size, LayerAttributes::named(""), Encoding::FAST_LOSSLESS, AnyChannels::sort(channels));
+    Image::from_layer(layer).write().to_file(path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HittableList, ObjectTag, hittable_list};
+    use crate::integrator::Background;
+    use crate::material::{Lambertian, Metal};
+    use crate::sphere::Sphere;
+    use crate::vec3::{color, point3};
+
+    // two spheres side by side in front of a camera at the origin looking down -z
+    fn two_balls() -> HittableList {
+        let red = Lambertian::new(0.8, 0.1, 0.1);
+        let steel = Metal::new(&color(0.5, 0.5, 0.5), 0.0);
+        let objects = vec![Sphere::new(point3(-1., 0., -3.), 0.9, red.clone()),
+                           Sphere::new(point3(1., 0., -3.), 0.9, steel),
+                           Sphere::new(point3(0., 100., -3.), 1.0, red)];
+        hittable_list(objects.into_iter().enumerate().map(|(k, o)| ObjectTag::new(o, k as u32 + 1) as _).collect())
+    }
+
+    fn pixel(world: &HittableList, dirs: &[Vec3]) -> AovPixel {
+        let lights = hittable_list(vec![]);
+        let background = Background::Solid(color(0.2, 0.3, 0.4));
+        let scene = SceneView{world, lights: &lights, background: &background};
+        let mut p = AovPixel::default();
+        for d in dirs {
+            p.add_sample(&Ray{origin: point3(0., 0., 0.), dir: d.clone(), time: 0.0, wavelengths: None}, &scene);
+        }
+        p.set_samples(dirs.len() as u32);
+        p
+    }
+
+    #[test]
+    fn test_first_hit_values() {
+        let world = two_balls();
+        let towards_red = vec3_(-1., 0., -3.);
+        let miss = vec3_(0., -1., 0.);
+        let pixels = vec![pixel(&world, &[towards_red.clone(), 2.0 * &towards_red]),
+                          pixel(&world, &[vec3_(1., 0., -3.), miss.clone()]),
+                          pixel(&world, &[miss])];
+        let aovs = AovBuffers::new(3, 1, pixels);
+
+        // the ray direction's length doesn't matter
+        let dist = 10f64.sqrt() - 0.9;
+        assert!((aovs.value(Aov::Depth, 0).x - dist).abs() < 1e-9);
+        assert!((aovs.value(Aov::Normal, 0) - (-&towards_red).unit_vector()).length() < 1e-9);
+        assert!((aovs.value(Aov::Position, 0) - dist * towards_red.unit_vector()).length() < 1e-9);
+        assert_eq!(aovs.value(Aov::Albedo, 0), color(0.8, 0.1, 0.1));
+        // half the samples see the background
+        assert!((aovs.value(Aov::Albedo, 1) - color(0.35, 0.4, 0.45)).length() < 1e-9);
+        assert_eq!(aovs.value(Aov::Depth, 2).x, 0.0);
+        assert_eq!(aovs.value(Aov::Samples, 1).x, 2.0);
+
+        let ids = |aov| (0..3).map(|k| aovs.value(aov, k).x as u32).collect::<Vec<_>>();
+        assert_eq!(ids(Aov::ObjectId), vec![1, 2, 0]);
+        assert_eq!(ids(Aov::MaterialId), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_aov_path() {
+        assert_eq!(aov_path("generated_imgs/box.png", Aov::Depth), "generated_imgs/box.depth.png");
+        assert_eq!(aov_path("box.exr", Aov::ObjectId), "box.object_id.exr");
+        assert_eq!(aov_path("box", Aov::Normal), "box.normal");
+    }
+
+    #[test]
+    fn test_layered_exr() {
+        let world = two_balls();
+        let pixels = (0..6).map(|k| pixel(&world, &[vec3_(k as f64 - 2.5, 0., -3.)])).collect();
+        let aovs = AovBuffers::new(3, 2, pixels);
+        let img = Rgb32FImage::from_fn(3, 2, |i, j| Rgb([i as f32, j as f32, 7.5]));
+        let list = [Aov::Depth, Aov::Normal, Aov::ObjectId];
+
+        let path = std::env::temp_dir().join("rust_tracing_aov_test.exr");
+        let path = path.to_str().unwrap();
+        write_layered_exr(path, &img, &aovs, &list).unwrap();
+        let back = exr::prelude::read_all_flat_layers_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let channels = &back.layer_data[0].channel_data.list;
+        let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+        assert_eq!(names, ["B", "G", "R", "depth.Z", "normal.X", "normal.Y", "normal.Z", "object_id.id"]);
+        let get = |name: &str| &channels.iter().find(|c| c.name.to_string() == name).unwrap().sample_data;
+        assert_eq!(get("R").value_by_flat_index(4).to_f32(), 1.0);
+        assert_eq!(get("G").value_by_flat_index(4).to_f32(), 1.0);
+        assert!((get("depth.Z").value_by_flat_index(1).to_f32() as f64 - aovs.value(Aov::Depth, 1).x).abs() < 1e-6);
+        let expected: Vec<u32> = (0..6).map(|k| aovs.value(Aov::ObjectId, k).x as u32).collect();
+        assert!(expected.contains(&1) && expected.contains(&2));
+        match get("object_id.id") {
+            FlatSamples::U32(ids) => assert_eq!(ids, &expected),
+            _ => panic!("IDs should be stored as integers")
+        }
+    }
+}
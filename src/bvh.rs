@@ -0,0 +1,116 @@
+use std::cmp::Ordering;
+
+use crate::aabb::{Aabb, surrounding_box};
+use crate::hittable::{HitRecord, Hittable, HittableList, SharedHittable};
+use crate::ray::Ray;
+use crate::rtweekend::Shared;
+use crate::vec3::point3;
+
+// bounding volume hierarchy, "The Next Week" section 3.
+// Objects are split at the median centroid along the longest axis of the
+// centroids' bounding box, so hit() costs O(log n) instead of O(n).
+pub struct BvhNode {
+    left: SharedHittable,
+    right: SharedHittable,
+    bbox: Aabb
+}
+
+impl BvhNode {
+    pub fn new(list: &HittableList) -> Shared<BvhNode> {
+        let objects: Vec<(SharedHittable, Aabb)> = list.objects.iter()
+            .map(|obj| {
+                let bbox = obj.bounding_box()
+                    .expect("BvhNode::new: object without bounding box");
+                (obj.clone(), bbox)
+            })
+            .collect();
+
+        if objects.is_empty() {
+            panic!("BvhNode::new: empty HittableList")
+        }
+        Shared::new(Self::build(objects))
+    }
+
+    fn build(mut objects: Vec<(SharedHittable, Aabb)>) -> BvhNode {
+        let (left, right): (SharedHittable, SharedHittable) = match objects.len() {
+            1 => (objects[0].0.clone(), objects[0].0.clone()),
+            2 => (objects[0].0.clone(), objects[1].0.clone()),
+            n => {
+                let axis = centroid_bounds(&objects).longest_axis();
+                objects.sort_by(|a, b| {
+                    a.1.centroid(axis).partial_cmp(&b.1.centroid(axis))
+                        .unwrap_or(Ordering::Equal)
+                });
+                let upper = objects.split_off(n / 2);
+                (Shared::new(Self::build(objects)), Shared::new(Self::build(upper)))
+            }
+        };
+
+        // both children have boxes: we only build from objects that do
+        let bbox = surrounding_box(&left.bounding_box().unwrap(),
+                                   &right.bounding_box().unwrap());
+        BvhNode{left, right, bbox}
+    }
+}
+
+fn centroid_bounds(objects: &[(SharedHittable, Aabb)]) -> Aabb {
+    let points = objects.iter().map(|(_, bbox)| {
+        let c = point3(bbox.centroid(0), bbox.centroid(1), bbox.centroid(2));
+        Aabb::new(c.clone(), c)
+    });
+    points.reduce(|acc, b| surrounding_box(&acc, &b)).unwrap()
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return false
+        }
+
+        let hit_left = self.left.hit(ray, t_min, t_max, rec);
+        let hit_right = self.right.hit(ray, t_min,
+                                       if hit_left { rec.t } else { t_max }, rec);
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox.clone())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::hittable_list;
+    use crate::rtweekend::{INF, rng_from_seed};
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn test_bvh_matches_list() {
+        let mut rng = rng_from_seed(1);
+        let mut world = hittable_list(vec![]);
+        for _ in 0..200 {
+            let center = Vec3::rand_unif(&mut rng, -10.0, 10.0);
+            world.add(Sphere::new_cr(center, 0.5));
+        }
+        let bvh = BvhNode::new(&world);
+
+        for _ in 0..2000 {
+            let dir = Vec3::random_unit_vector(&mut rng);
+            let ray = Ray::new(&point3(0., 0., 0.), &dir);
+
+            let mut rec_list = HitRecord::default();
+            let mut rec_bvh = HitRecord::default();
+            let hit_list = world.hit(&ray, 0.001, INF, &mut rec_list);
+            let hit_bvh = bvh.hit(&ray, 0.001, INF, &mut rec_bvh);
+
+            assert_eq!(hit_list, hit_bvh);
+            if hit_list {
+                assert_eq!(rec_list.t, rec_bvh.t);
+                assert_eq!(rec_list.normal, rec_bvh.normal);
+            }
+        }
+    }
+}
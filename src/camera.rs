@@ -1,6 +1,5 @@
-use rand::rngs::ThreadRng;
-
-use crate::rtweekend::degrees_to_radians;
+use crate::rtweekend::{RtRng, degrees_to_radians};
+use crate::sampler::TIME_DIM;
 // listing 27
 use crate::vec3::{Vec3, Point3, point3, vec3_};
 use crate::ray::Ray;
@@ -82,7 +81,9 @@ impl Camera {
         Ray {
           origin: self.origin.clone(),
           dir: &self.lower_left_corner + u * &self.horizontal
-               + v * &self.vertical - &self.origin
+               + v * &self.vertical - &self.origin,
+          time: 0.0,
+          wavelengths: None
         }
     }
 
@@ -96,8 +97,12 @@ pub struct CameraWithFocus {
     pub vertical: Vec3,
     pub u: Vec3,
     pub v: Vec3,
+    #[allow(dead_code)]
     pub w: Vec3,
     pub lens_radius: f64,  // listing 68
+    // "The Next Week", section 2: rays are sent at random times in [time0, time1]
+    pub time0: f64,
+    pub time1: f64
 }
 
 
@@ -118,16 +123,57 @@ impl CameraWithFocus {
         CameraWithFocus {
             lower_left_corner: lookfrom - (&horizontal / 2.0) - &(&vertical / 2.0) - focus_dist * &w,
             horizontal, vertical, u, v, w, lens_radius: aperture/2.0,
-            origin: lookfrom.clone()
+            origin: lookfrom.clone(),
+            time0: 0.0, time1: 0.0
         }
 
     }
-    pub fn get_ray(&self, s: f64, t: f64, rng: &mut ThreadRng) -> Ray {
+
+    pub fn with_shutter(self, time0: f64, time1: f64) -> Self {
+        CameraWithFocus{time0, time1, ..self}
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, rng: &mut RtRng) -> Ray {
         let rd = self.lens_radius * Vec3::random_in_disk_1(rng);
         let offset = rd.x * &self.u + rd.y * &self.v;
-        return Ray{
+        // no random number drawn for a closed shutter, so still scenes don't change
+        let time = if self.time1 > self.time0 {
+            self.time0 + (self.time1 - self.time0) * rng.unif_at(TIME_DIM)
+        } else {
+            self.time0
+        };
+        Ray{
             origin: (&self.origin + &offset),
-            dir: &self.lower_left_corner + s * &self.horizontal + t * &self.vertical - &self.origin - &offset
+            dir: &self.lower_left_corner + s * &self.horizontal + t * &self.vertical - &self.origin - &offset,
+            time,
+            wavelengths: None
         }
     }
-}
\ No newline at end of file
+}
+
+
+// everything needed to set up a CameraWithFocus except the aspect ratio,
+// which comes with the image size
+#[derive(Debug, Clone)]
+pub struct CameraParams {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov_deg: f64,
+    pub aperture: f64,
+    // None: focus on lookat
+    pub focus_dist: Option<f64>,
+    // equal for an instantaneous exposure
+    pub shutter_open: f64,
+    pub shutter_close: f64
+}
+
+impl CameraParams {
+    pub fn camera(&self, aspect_ratio: f64) -> CameraWithFocus {
+        let focus_dist = self.focus_dist
+            .unwrap_or_else(|| (&self.lookfrom - &self.lookat).length());
+        CameraWithFocus::new(&self.lookfrom, &self.lookat, self.vup.clone(),
+                             self.vfov_deg, aspect_ratio, self.aperture, focus_dist)
+            .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
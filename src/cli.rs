@@ -0,0 +1,578 @@
+// command line interface: which scene to render and how
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use clap::error::ErrorKind;
+
+use crate::aov::Aov;
+use crate::camera::CameraParams;
+use crate::display::{DisplaySettings, ToneMap};
+use crate::environment::EnvironmentMap;
+use crate::integrator::{Background, Integrator, RayColorFn, ray_color_nee};
+use crate::output::{Output, OutputFormat};
+use crate::render::{Adaptive, RenderParams, available_threads};
+use crate::rtweekend::Shared;
+use crate::sampler::SamplerKind;
+use crate::scene_file::RenderSettings;
+use crate::scenes::{Scene, all_scenes};
+use crate::sky::Sky;
+use crate::vec3::{Vec3, vec3_};
+
+#[derive(Parser, Debug)]
+#[command(name = "rust-tracing", about = "Renders the scenes from \"Ray Tracing in One Weekend\"")]
+pub struct Cli {
+    /// Scene to render, see the list below
+    #[arg(short, long, default_value = "marble_v1")]
+    pub scene: String,
+
+    /// Render a scene description file (TOML) instead of a registered scene
+    #[arg(short = 'f', long, value_name = "PATH", conflicts_with = "scene")]
+    pub scene_file: Option<String>,
+
+    /// Print the registered scenes and exit
+    #[arg(long)]
+    pub list_scenes: bool,
+
+    /// Render one of the book's early listings (1, 7, 9-24 or 30-68) instead of a scene
+    #[arg(long, value_name = "N", allow_hyphen_values = true)]
+    pub listing: Option<i32>,
+
+    /// Image width in pixels, at least 2 [default: set by the scene]
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub width: Option<u32>,
+
+    /// Width / height, as a number or a fraction like 16/9 [default: set by the scene]
+    #[arg(short, long, value_parser = parse_ratio)]
+    pub aspect_ratio: Option<f64>,
+
+    /// Samples per pixel, the most a pixel gets with --adaptive [default: set by the scene]
+    #[arg(long = "spp", value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    pub samples_per_pixel: Option<i32>,
+
+    /// Stop sampling a pixel once its noise is below --adaptive-threshold
+    /// [default: false, or set by the scene file]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub adaptive: Option<bool>,
+
+    /// Samples every pixel gets with --adaptive [default: 16]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    pub min_spp: Option<i32>,
+
+    /// Standard error of a pixel's mean luminance, relative to the mean, at
+    /// which --adaptive stops sampling it [default: 0.02]
+    #[arg(long, value_name = "ERROR")]
+    pub adaptive_threshold: Option<f64>,
+
+    /// Where the random numbers of each sample come from: drawn independently,
+    /// or spread evenly over the samples of a pixel [default: independent, or
+    /// set by the scene file]
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerKind>,
+
+    /// Light the scene with an equirectangular HDR image (.hdr or .exr)
+    /// instead of its own background, rendered with next event estimation
+    /// (see --integrator)
+    #[arg(long, value_name = "PATH")]
+    pub environment: Option<String>,
+
+    /// Turn --environment about the up axis
+    #[arg(long, value_name = "DEGREES", default_value_t = 0.0, allow_hyphen_values = true)]
+    pub environment_rotation: f64,
+
+    /// Scale the brightness of --environment
+    #[arg(long, value_name = "SCALE", default_value_t = 1.0)]
+    pub environment_intensity: f64,
+
+    /// Light the scene with a daylight sky and sun instead of its own
+    /// background, rendered with next event estimation (see --integrator)
+    #[arg(long, conflicts_with = "environment")]
+    pub sky: bool,
+
+    /// Height of the --sky sun above the horizon, 0 to 90
+    #[arg(long, value_name = "DEGREES", default_value_t = 45.0)]
+    pub sun_elevation: f64,
+
+    /// Direction of the --sky sun, from -z towards +x
+    #[arg(long, value_name = "DEGREES", default_value_t = 0.0, allow_hyphen_values = true)]
+    pub sun_azimuth: f64,
+
+    /// Haze of the --sky, from 2 (very clear) to 10
+    #[arg(long, value_name = "T", default_value_t = 3.0)]
+    pub turbidity: f64,
+
+    /// Maximum number of bounces per path [default: 50]
+    #[arg(long)]
+    pub max_depth: Option<i32>,
+
+    /// How paths are traced: the scene's own recursive integrator (always
+    /// next event estimation with --environment or --sky, so that their light
+    /// is sampled), or a loop with Russian roulette that also prints bounce
+    /// statistics
+    #[arg(long, value_enum, default_value_t = IntegratorChoice::Recursive)]
+    pub integrator: IntegratorChoice,
+
+    /// Bounces before Russian roulette may end a path (iterative integrator)
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub rr_min_bounces: i32,
+
+    /// Filter the noise out of the finished image, guided by its normals,
+    /// albedo and depth [default: false, or set by the scene file]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub denoise: Option<bool>,
+
+    /// Trace paths at random wavelengths instead of in RGB, so that glass
+    /// with a dispersive index of refraction splits light into colors
+    /// [default: false, or set by the scene file]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub spectral: Option<bool>,
+
+    /// Camera position
+    #[arg(long, value_parser = parse_vec3, value_name = "X,Y,Z", allow_hyphen_values = true)]
+    pub lookfrom: Option<Vec3>,
+
+    /// Point the camera looks at
+    #[arg(long, value_parser = parse_vec3, value_name = "X,Y,Z", allow_hyphen_values = true)]
+    pub lookat: Option<Vec3>,
+
+    /// Camera "up" direction
+    #[arg(long, value_parser = parse_vec3, value_name = "X,Y,Z", allow_hyphen_values = true)]
+    pub vup: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_name = "DEGREES")]
+    pub vfov: Option<f64>,
+
+    /// Lens aperture (0 for a pinhole camera)
+    #[arg(long)]
+    pub aperture: Option<f64>,
+
+    /// Distance to the plane in focus [default: distance from lookfrom to lookat]
+    #[arg(long)]
+    pub focus_dist: Option<f64>,
+
+    /// Times the shutter opens and closes, moving objects blur in between [default: set by the scene]
+    #[arg(long, value_parser = parse_shutter, value_name = "OPEN,CLOSE", allow_hyphen_values = true)]
+    pub shutter: Option<(f64, f64)>,
+
+    /// Output image; .exr, .hdr and .pfm keep the linear, unclamped colors,
+    /// .png and .jpg are 8-bit [default: generated_imgs/<scene>.<format>]
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Output format [default: from the --output extension, else png]
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Exposure in stops for 8-bit output: +1 doubles the brightness
+    #[arg(long, value_name = "STOPS", default_value_t = 0.0, allow_hyphen_values = true)]
+    pub exposure: f64,
+
+    /// Tone mapping operator for 8-bit output, applied after the exposure
+    #[arg(long, value_enum, default_value_t = ToneMap::Clamp)]
+    pub tone_map: ToneMap,
+
+    /// Value mapped to white by reinhard-extended and hable [default: the brightest value, 11.2 for hable]
+    #[arg(long)]
+    pub white: Option<f64>,
+
+    /// Dither 8-bit output to hide banding in smooth gradients (also listings 1 and 7)
+    #[arg(long)]
+    pub dither: bool,
+
+    /// Extra passes from the first hit of each camera ray: layers of the EXR
+    /// file, else files next to the image named <image>.<aov>.<ext>
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "AOV,...")]
+    pub aov: Vec<Aov>,
+
+    /// Write the AOVs of an EXR image to files of their own as well
+    #[arg(long)]
+    pub aov_separate: bool,
+
+    /// Seed for all random numbers; the same seed gives the same image
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Number of render threads [default: one per core]
+    #[arg(short = 'j', long)]
+    pub threads: Option<usize>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorChoice {
+    Recursive,
+    Iterative
+}
+
+pub fn parse_args() -> Cli {
+    let matches = Cli::command().after_help(scenes_help()).get_matches();
+    Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+// exits with a clap-style usage error
+pub fn exit_with_error(msg: &str) -> ! {
+    Cli::command().error(ErrorKind::InvalidValue, msg).exit()
+}
+
+pub fn scenes_help() -> String {
+    let scenes = all_scenes();
+    let width = scenes.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    let mut help = String::from("Scenes:\n");
+    for scene in scenes {
+        help += &format!("  {:width$}  {}\n", scene.name, scene.description, width = width);
+    }
+    help
+}
+
+impl Cli {
+    pub fn render_params(&self, aspect_ratio: f64, img_width: u32, samples_per_pixel: i32) -> RenderParams {
+        let mut rp = RenderParams::new(self.aspect_ratio.unwrap_or(aspect_ratio),
+                                       self.width.unwrap_or(img_width),
+                                       self.samples_per_pixel.unwrap_or(samples_per_pixel));
+        rp.depth = self.max_depth.unwrap_or(rp.depth);
+        rp.seed = self.seed;
+        rp.n_threads = self.threads.unwrap_or_else(available_threads);
+        rp.denoise = self.denoise.unwrap_or(false);
+        rp.spectral = self.spectral.unwrap_or(false);
+        rp.sampler = self.sampler.unwrap_or_default();
+        if self.adaptive.unwrap_or(false) {
+            rp.adaptive = Some(self.adaptive_params(Adaptive::default()));
+        }
+        check_image_size(&rp).unwrap_or_else(|err| exit_with_error(&err));
+        rp
+    }
+
+    // `defaults` with what was given on the command line replaced
+    fn adaptive_params(&self, defaults: Adaptive) -> Adaptive {
+        Adaptive{min_samples: self.min_spp.unwrap_or(defaults.min_samples),
+                 threshold: self.adaptive_threshold.unwrap_or(defaults.threshold)}
+    }
+
+    pub fn scene_render_params(&self, scene: &Scene) -> RenderParams {
+        self.render_params(scene.aspect_ratio, scene.img_width, scene.samples_per_pixel)
+    }
+
+    // settings from the scene file, unless given on the command line
+    pub fn file_render_params(&self, settings: &RenderSettings) -> RenderParams {
+        let mut rp = self.render_params(settings.aspect_ratio.unwrap_or(16.0 / 9.0),
+                                        settings.width.unwrap_or(400),
+                                        settings.samples_per_pixel.unwrap_or(100));
+        if self.max_depth.is_none() {
+            rp.depth = settings.max_depth.unwrap_or(rp.depth);
+        }
+        if self.denoise.is_none() {
+            rp.denoise = settings.denoise.unwrap_or(false);
+        }
+        if self.sampler.is_none() {
+            rp.sampler = settings.sampler.unwrap_or_default();
+        }
+        if self.spectral.is_none() {
+            rp.spectral = settings.spectral.unwrap_or(false);
+        }
+        if self.adaptive.is_none() && settings.adaptive.unwrap_or(false) {
+            let defaults = Adaptive::default();
+            rp.adaptive = Some(self.adaptive_params(Adaptive{
+                min_samples: settings.min_samples_per_pixel.unwrap_or(defaults.min_samples),
+                threshold: settings.adaptive_threshold.unwrap_or(defaults.threshold)
+            }));
+        }
+        rp
+    }
+
+    // --environment or --sky if given, else the scene's own
+    pub fn background(&self, scene_background: &Background) -> Background {
+        match &self.environment {
+            None if self.sky => {
+                let sky = Sky::new(self.sun_elevation, self.sun_azimuth, self.turbidity)
+                    .unwrap_or_else(|err| exit_with_error(&format!("--sky: {}", err)));
+                Background::Sky(Shared::new(sky))
+            },
+            None => scene_background.clone(),
+            Some(path) => {
+                let env = EnvironmentMap::load(path, self.environment_rotation, self.environment_intensity)
+                    .unwrap_or_else(|err| exit_with_error(&format!("{}: {}", path, err)));
+                Background::Environment(Shared::new(env))
+            }
+        }
+    }
+
+    // the scene's camera, with whatever was given on the command line replaced
+    pub fn camera_params(&self, cam: &CameraParams) -> CameraParams {
+        CameraParams{
+            lookfrom: self.lookfrom.clone().unwrap_or_else(|| cam.lookfrom.clone()),
+            lookat: self.lookat.clone().unwrap_or_else(|| cam.lookat.clone()),
+            vup: self.vup.clone().unwrap_or_else(|| cam.vup.clone()),
+            vfov_deg: self.vfov.unwrap_or(cam.vfov_deg),
+            aperture: self.aperture.unwrap_or(cam.aperture),
+            focus_dist: self.focus_dist.or(cam.focus_dist),
+            shutter_open: self.shutter.map_or(cam.shutter_open, |s| s.0),
+            shutter_close: self.shutter.map_or(cam.shutter_close, |s| s.1)
+        }
+    }
+
+    // the recursive one is `scene_default`, or ray_color_nee with an
+    // --environment or --sky, whose light that scene's integrator may not sample
+    pub fn integrator(&self, scene_default: RayColorFn) -> Integrator {
+        match self.integrator {
+            IntegratorChoice::Recursive if self.environment.is_some() || self.sky => Integrator::Recursive(ray_color_nee),
+            IntegratorChoice::Recursive => Integrator::Recursive(scene_default),
+            IntegratorChoice::Iterative => Integrator::Iterative{min_bounces: self.rr_min_bounces}
+        }
+    }
+
+    pub fn output(&self, default_name: &str) -> Output {
+        Output{path: self.output_path(default_name), format: self.output_format(), display: self.display(),
+               aovs: self.aov.clone(), aov_separate: self.aov_separate}
+    }
+
+    pub fn display(&self) -> DisplaySettings {
+        DisplaySettings{exposure: self.exposure, tone_map: self.tone_map, white: self.white, dither: self.dither}
+    }
+
+    pub fn output_path(&self, default_name: &str) -> String {
+        self.output.clone().unwrap_or_else(|| {
+            let ext = self.format.unwrap_or(OutputFormat::Png).extension();
+            format!("generated_imgs/{}.{}", default_name, ext)
+        })
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.format.unwrap_or_else(|| {
+            let path = self.output.as_deref().unwrap_or("");
+            if path.is_empty() {
+                return OutputFormat::Png
+            }
+            OutputFormat::from_path(path).unwrap_or_else(|| exit_with_error(&format!(
+                "can't tell the format of `{}` from its extension, use --format", path)))
+        })
+    }
+}
+
+// for sizes that come from a scene or scene file, or are only too small
+// once the aspect ratio is applied; pixel centers are spread over
+// width - 1 and height - 1 steps, so either needs 2 pixels
+fn check_image_size(rp: &RenderParams) -> Result<(), String> {
+    if rp.img_width < 2 {
+        Err(format!("the image width is {}, it needs at least 2 pixels", rp.img_width))
+    } else if rp.img_height < 2 {
+        Err(format!("a width of {} at aspect ratio {} leaves a height of {}, it needs at least 2 pixels",
+                    rp.img_width, rp.aspect_ratio, rp.img_height))
+    } else if rp.samples_per_pixel < 1 {
+        Err(format!("samples per pixel is {}, it needs at least 1", rp.samples_per_pixel))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_ratio(arg: &str) -> Result<f64, String> {
+    let ratio = match arg.split_once('/') {
+        Some((num, den)) => {
+            let num: f64 = num.trim().parse().map_err(|_| format!("`{}` is not a number", num))?;
+            let den: f64 = den.trim().parse().map_err(|_| format!("`{}` is not a number", den))?;
+            num / den
+        },
+        None => arg.trim().parse().map_err(|_| format!("`{}` is not a number", arg))?
+    };
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(format!("`{}` is not a positive aspect ratio", arg))
+    }
+}
+
+fn parse_vec3(arg: &str) -> Result<Vec3, String> {
+    let coords = arg.split(',')
+        .map(|x| x.trim().parse::<f64>().map_err(|_| format!("`{}` is not a number", x)))
+        .collect::<Result<Vec<f64>, String>>()?;
+    match coords[..] {
+        [x, y, z] => Ok(vec3_(x, y, z)),
+        _ => Err(format!("expected three comma separated numbers, got `{}`", arg))
+    }
+}
+
+fn parse_shutter(arg: &str) -> Result<(f64, f64), String> {
+    let (open, close) = arg.split_once(',')
+        .ok_or_else(|| format!("expected two comma separated times, got `{}`", arg))?;
+    let open: f64 = open.trim().parse().map_err(|_| format!("`{}` is not a number", open))?;
+    let close: f64 = close.trim().parse().map_err(|_| format!("`{}` is not a number", close))?;
+    if close < open {
+        return Err(format!("the shutter closes ({}) before it opens ({})", close, open))
+    }
+    Ok((open, close))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("16/9"), Ok(16.0 / 9.0));
+        assert_eq!(parse_ratio("1.5"), Ok(1.5));
+        assert!(parse_ratio("3/0").is_err());
+        assert!(parse_ratio("wide").is_err());
+    }
+
+    #[test]
+    fn test_parse_vec3() {
+        assert_eq!(parse_vec3("13,2,-3"), Ok(vec3_(13., 2., -3.)));
+        assert!(parse_vec3("1,2").is_err());
+        assert!(parse_vec3("1,x,2").is_err());
+    }
+
+    #[test]
+    fn test_parse_shutter() {
+        assert_eq!(parse_shutter("0,0.5"), Ok((0.0, 0.5)));
+        assert!(parse_shutter("1").is_err());
+        assert!(parse_shutter("1,0").is_err());
+    }
+
+    #[test]
+    fn test_cli_overrides_scene_defaults() {
+        let cli = Cli::try_parse_from(["rust-tracing", "--scene", "many_sphere_world_70",
+                                       "-w", "300", "--spp", "8", "--lookfrom", "-1,2,3"]).unwrap();
+        let scene = crate::scenes::find_scene(&cli.scene).unwrap();
+        let rp = cli.scene_render_params(&scene);
+        assert_eq!(rp.img_width, 300);
+        assert_eq!(rp.img_height, 200);
+        assert_eq!(rp.samples_per_pixel, 8);
+        let cam = cli.camera_params(&scene.camera);
+        assert_eq!(cam.lookfrom, vec3_(-1., 2., 3.));
+        assert_eq!(cam.focus_dist, Some(10.0));
+        assert!(matches!(cli.integrator(scene.ray_color), Integrator::Recursive(_)));
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--integrator", "iterative", "--rr-min-bounces", "5"]).unwrap();
+        assert!(matches!(cli.integrator(scene.ray_color), Integrator::Iterative{min_bounces: 5}));
+        assert!(Cli::try_parse_from(["rust-tracing", "--integrator", "bidirectional"]).is_err());
+    }
+
+    #[test]
+    fn test_image_size_limits() {
+        assert!(Cli::try_parse_from(["rust-tracing", "-w", "1"]).is_err());
+        assert!(Cli::try_parse_from(["rust-tracing", "--spp", "0"]).is_err());
+        assert!(Cli::try_parse_from(["rust-tracing", "-w", "2", "--spp", "1"]).is_ok());
+
+        assert!(check_image_size(&RenderParams::new(1.0, 2, 1)).is_ok());
+        let msg = check_image_size(&RenderParams::new(16.0 / 6.0, 4, 1)).unwrap_err();
+        assert!(msg.contains("leaves a height of 1"), "{}", msg);
+        // a scene file's settings don't go through clap
+        assert!(check_image_size(&RenderParams::new(1.0, 0, 10)).is_err());
+        assert!(check_image_size(&RenderParams::new(1.0, 100, 0)).is_err());
+    }
+
+    #[test]
+    fn test_denoise_switch() {
+        let file_settings = RenderSettings{denoise: Some(true), ..Default::default()};
+        let cli = Cli::try_parse_from(["rust-tracing"]).unwrap();
+        assert!(!cli.render_params(1.0, 100, 10).denoise);
+        assert!(cli.file_render_params(&file_settings).denoise);
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--denoise"]).unwrap();
+        assert!(cli.render_params(1.0, 100, 10).denoise);
+        let cli = Cli::try_parse_from(["rust-tracing", "--denoise", "false"]).unwrap();
+        assert!(!cli.file_render_params(&file_settings).denoise);
+    }
+
+    #[test]
+    fn test_spectral_switch() {
+        let file_settings = RenderSettings{spectral: Some(true), ..Default::default()};
+        let cli = Cli::try_parse_from(["rust-tracing"]).unwrap();
+        assert!(!cli.render_params(1.0, 100, 10).spectral);
+        assert!(cli.file_render_params(&file_settings).spectral);
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--spectral"]).unwrap();
+        assert!(cli.render_params(1.0, 100, 10).spectral);
+        let cli = Cli::try_parse_from(["rust-tracing", "--spectral", "false"]).unwrap();
+        assert!(!cli.file_render_params(&file_settings).spectral);
+    }
+
+    #[test]
+    fn test_sampler_choice() {
+        let file_settings: RenderSettings = toml::from_str("sampler = \"blue-noise\"").unwrap();
+        let cli = Cli::try_parse_from(["rust-tracing"]).unwrap();
+        assert_eq!(cli.render_params(1.0, 100, 10).sampler, SamplerKind::Independent);
+        assert_eq!(cli.file_render_params(&file_settings).sampler, SamplerKind::BlueNoise);
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--sampler", "sobol"]).unwrap();
+        assert_eq!(cli.file_render_params(&file_settings).sampler, SamplerKind::Sobol);
+        assert!(Cli::try_parse_from(["rust-tracing", "--sampler", "poisson"]).is_err());
+    }
+
+    #[test]
+    fn test_environment_options() {
+        let cli = Cli::try_parse_from(["rust-tracing"]).unwrap();
+        assert!(matches!(cli.background(&Background::Gradient), Background::Gradient));
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--environment", "sky.exr", "--environment-rotation", "-90",
+                                       "--environment-intensity", "0.5"]).unwrap();
+        assert_eq!((cli.environment.as_deref(), cli.environment_rotation, cli.environment_intensity),
+                   (Some("sky.exr"), -90.0, 0.5));
+        let scene = crate::scenes::find_scene("two_sphere_world").unwrap();
+        assert!(matches!(cli.integrator(scene.ray_color), Integrator::Recursive(f) if std::ptr::fn_addr_eq(f, ray_color_nee as RayColorFn)));
+    }
+
+    #[test]
+    fn test_sky_options() {
+        let cli = Cli::try_parse_from(["rust-tracing", "--sky", "--sun-elevation", "10", "--sun-azimuth", "-60",
+                                       "--turbidity", "4"]).unwrap();
+        assert_eq!((cli.sun_elevation, cli.sun_azimuth, cli.turbidity), (10.0, -60.0, 4.0));
+        assert!(matches!(cli.background(&Background::Gradient), Background::Sky(_)));
+        let scene = crate::scenes::find_scene("many_sphere_world_70").unwrap();
+        assert!(matches!(cli.integrator(scene.ray_color), Integrator::Recursive(f) if std::ptr::fn_addr_eq(f, ray_color_nee as RayColorFn)));
+        assert!(Cli::try_parse_from(["rust-tracing", "--sky", "--environment", "sky.hdr"]).is_err());
+    }
+
+    #[test]
+    fn test_adaptive_settings() {
+        let cli = Cli::try_parse_from(["rust-tracing", "--min-spp", "4"]).unwrap();
+        assert_eq!(cli.render_params(1.0, 100, 10).adaptive, None);
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--adaptive", "--adaptive-threshold", "0.05"]).unwrap();
+        assert_eq!(cli.render_params(1.0, 100, 10).adaptive, Some(Adaptive{min_samples: 16, threshold: 0.05}));
+
+        // the command line wins over the scene file
+        let file_settings = RenderSettings{adaptive: Some(true), min_samples_per_pixel: Some(8),
+                                           adaptive_threshold: Some(0.1), ..Default::default()};
+        let cli = Cli::try_parse_from(["rust-tracing", "--min-spp", "32"]).unwrap();
+        assert_eq!(cli.file_render_params(&file_settings).adaptive, Some(Adaptive{min_samples: 32, threshold: 0.1}));
+        let cli = Cli::try_parse_from(["rust-tracing", "--adaptive", "false"]).unwrap();
+        assert_eq!(cli.file_render_params(&file_settings).adaptive, None);
+        assert!(Cli::try_parse_from(["rust-tracing", "--min-spp", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_output_format() {
+        let cli = Cli::try_parse_from(["rust-tracing"]).unwrap();
+        assert_eq!((cli.output_path("box"), cli.output_format()), ("generated_imgs/box.png".to_string(), OutputFormat::Png));
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--format", "exr"]).unwrap();
+        assert_eq!((cli.output_path("box"), cli.output_format()), ("generated_imgs/box.exr".to_string(), OutputFormat::Exr));
+
+        let cli = Cli::try_parse_from(["rust-tracing", "-o", "/tmp/box.HDR"]).unwrap();
+        assert_eq!(cli.output_format(), OutputFormat::Hdr);
+
+        // an explicit format wins over the extension
+        let cli = Cli::try_parse_from(["rust-tracing", "-o", "box.raw", "--format", "pfm"]).unwrap();
+        assert_eq!((cli.output_path("box"), cli.output_format()), ("box.raw".to_string(), OutputFormat::Pfm));
+    }
+
+    #[test]
+    fn test_display_settings() {
+        let display = Cli::try_parse_from(["rust-tracing"]).unwrap().display();
+        assert_eq!((display.exposure, display.tone_map, display.white, display.dither), (0.0, ToneMap::Clamp, None, false));
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--exposure", "-1.5", "--tone-map", "reinhard-extended",
+                                       "--white", "8", "--dither"]).unwrap();
+        let display = cli.output("box").display;
+        assert_eq!((display.exposure, display.tone_map, display.white, display.dither),
+                   (-1.5, ToneMap::ReinhardExtended, Some(8.0), true));
+    }
+
+    #[test]
+    fn test_aov_list() {
+        let output = Cli::try_parse_from(["rust-tracing"]).unwrap().output("box");
+        assert!(output.aovs.is_empty());
+
+        let cli = Cli::try_parse_from(["rust-tracing", "--aov", "depth,normal", "--aov", "object-id"]).unwrap();
+        assert_eq!(cli.output("box").aovs, vec![Aov::Depth, Aov::Normal, Aov::ObjectId]);
+        assert!(Cli::try_parse_from(["rust-tracing", "--aov", "motion"]).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+// edge-avoiding à-trous wavelet filter, Dammertz et al. 2010: a 5x5 B-spline
+// kernel applied again and again with the taps twice as far apart each time,
+// every tap weighted down where its color, normal or depth differ from the
+// center pixel's. The color is divided by the albedo first, so textures are
+// kept and only the lighting is smoothed.
+use image::{Rgb, Rgb32FImage};
+
+use crate::aov::{Aov, AovBuffers};
+use crate::render::{available_threads, render_pixels};
+use crate::vec3::{Color, Vec3, color};
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// keeps black albedo (lights seen from the back, black backgrounds) invertible
+const ALBEDO_EPS: f64 = 1e-3;
+
+#[derive(Clone, Debug)]
+pub struct Denoiser {
+    // passes; the last one reaches 2 * 2^(iterations - 1) pixels away
+    pub iterations: u32,
+    // how different two colors may be on a log scale, halved every pass
+    pub sigma_color: f64,
+    // on the squared distance between unit normals
+    pub sigma_normal: f64,
+    // on the depth difference relative to the depth
+    pub sigma_depth: f64,
+    // like --threads for rendering
+    pub n_threads: usize
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser{iterations: 5, sigma_color: 0.8, sigma_normal: 0.1, sigma_depth: 0.02,
+                 n_threads: available_threads()}
+    }
+}
+
+impl Denoiser {
+    // `aovs` needs the normal, albedo and depth of the image
+    pub fn denoise(&self, img: &Rgb32FImage, aovs: &AovBuffers) -> Rgb32FImage {
+        let (width, height) = (img.width() as i64, img.height() as i64);
+        let n = (width * height) as usize;
+        let normal: Vec<Vec3> = (0..n).map(|k| aovs.value(Aov::Normal, k)).collect();
+        let depth: Vec<f64> = (0..n).map(|k| aovs.value(Aov::Depth, k).x).collect();
+        let albedo: Vec<Color> = (0..n).map(|k| {
+            let a = aovs.value(Aov::Albedo, k);
+            color(a.x + ALBEDO_EPS, a.y + ALBEDO_EPS, a.z + ALBEDO_EPS)
+        }).collect();
+
+        let mut light: Vec<Color> = img.pixels().zip(&albedo).map(|(p, a)| {
+            color(p[0] as f64 / a.x, p[1] as f64 / a.y, p[2] as f64 / a.z)
+        }).collect();
+
+        for pass in 0..self.iterations {
+            let step = 1i64 << pass;
+            let sigma_color = self.sigma_color / (1u64 << pass) as f64;
+            let mapped: Vec<Color> = light.iter().map(compress).collect();
+            // no random numbers here, render_pixels only spreads the rows over threads
+            light = render_pixels(img.width(), img.height(), 0, self.n_threads, |i, j, _| {
+                let (i, j) = (i as i64, j as i64);
+                let k = (j * width + i) as usize;
+                let mut sum = color(0., 0., 0.);
+                let mut weights = 0.0;
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    let y = j + (dy as i64 - 2) * step;
+                    if y < 0 || y >= height {
+                        continue
+                    }
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let x = i + (dx as i64 - 2) * step;
+                        if x < 0 || x >= width {
+                            continue
+                        }
+                        let q = (y * width + x) as usize;
+                        let w_color = (-(&mapped[k] - &mapped[q]).length_squared() / (sigma_color * sigma_color)).exp();
+                        let w_normal = (-(&normal[k] - &normal[q]).length_squared() / self.sigma_normal).exp();
+                        let relative_depth = (depth[k] - depth[q]).abs() / (depth[k].max(depth[q]).max(1e-9) * step as f64);
+                        let w_depth = (-relative_depth / self.sigma_depth).exp();
+                        let w = kx * ky * w_color * w_normal * w_depth;
+                        sum += &(w * &light[q]);
+                        weights += w;
+                    }
+                }
+                // the center tap always has weight > 0
+                &sum / weights
+            });
+        }
+
+        Rgb32FImage::from_fn(img.width(), img.height(), |i, j| {
+            let k = (j * img.width() + i) as usize;
+            let c = &light[k] * &albedo[k];
+            Rgb([c.x as f32, c.y as f32, c.z as f32])
+        })
+    }
+}
+
+// edges between HDR values are compared on a log scale, so that a light
+// stays apart from the much darker surface around it
+fn compress(c: &Color) -> Color {
+    color(c.x.max(0.0).ln_1p(), c.y.max(0.0).ln_1p(), c.z.max(0.0).ln_1p())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::bvh::BvhNode;
+    use crate::integrator::{Integrator, PathStats, SceneView};
+    use crate::render::{RenderParams, render_scene};
+    use crate::rtweekend::rng_from_seed;
+    use crate::scenes::find_scene;
+
+    fn render(name: &str, spp: i32, seed: u64) -> (Rgb32FImage, AovBuffers) {
+        let scene = find_scene(name).unwrap();
+        let mut rp = RenderParams::new(scene.aspect_ratio, 64, spp);
+        rp.seed = seed;
+        let world = BvhNode::new(&(scene.build)(&mut rng_from_seed(0)));
+        let lights = (scene.lights)();
+        let view = SceneView{world: world.as_ref(), lights: &lights, background: &scene.background};
+        let camera = scene.camera.camera(rp.aspect_ratio);
+        render_scene(&view, &camera, Integrator::Recursive(scene.ray_color), &rp, true,
+                     &Mutex::new(PathStats::default()))
+    }
+
+    // over the pixels no brighter than 1 in both `noisy` and `reference`: the
+    // handful half covering a light, or with a firefly, would make up most of
+    // the error otherwise, and no filter is meant to change them
+    fn mse(a: &Rgb32FImage, noisy: &Rgb32FImage, reference: &Rgb32FImage) -> f64 {
+        let in_range = |p: &Rgb<f32>| p.0.iter().all(|c| *c <= 1.0);
+        let errors: Vec<f64> = a.pixels().zip(noisy.pixels()).zip(reference.pixels())
+            .filter(|((_, n), r)| in_range(n) && in_range(r))
+            .flat_map(|((p, _), r)| (0..3).map(move |c| (p[c] as f64 - r[c] as f64).powi(2)))
+            .collect();
+        errors.iter().sum::<f64>() / errors.len() as f64
+    }
+
+    #[test]
+    fn test_denoised_is_closer_to_reference() {
+        // diffuse walls lit by an area light, diffuse spheres under the sky
+        for name in ["cornell_box", "two_sphere_world"] {
+            let (reference, _) = render(name, 256, 1);
+            let (noisy, aovs) = render(name, 8, 2);
+            let denoised = Denoiser::default().denoise(&noisy, &aovs);
+            let (before, after) = (mse(&noisy, &noisy, &reference), mse(&denoised, &noisy, &reference));
+            assert!(after < 0.7 * before, "{}: {} -> {}", name, before, after);
+        }
+    }
+
+    #[test]
+    fn test_same_result_for_any_thread_count() {
+        let (noisy, aovs) = render("two_sphere_world", 4, 3);
+        let denoise = |n_threads| Denoiser{n_threads, ..Default::default()}.denoise(&noisy, &aovs);
+        assert!(denoise(1) == denoise(5));
+    }
+}
@@ -0,0 +1,177 @@
+// from linear radiance to 8-bit pixels: exposure, a tone mapping operator
+// squeezing [0, inf) into [0, 1], the sRGB transfer function and rounding,
+// optionally dithered
+use clap::ValueEnum;
+use image::{ImageBuffer, Rgb32FImage, RgbImage};
+
+use crate::rtweekend::{clamp, hash_unif};
+use crate::vec3_img::{Rgb32, Rgb8};
+
+// applied to each channel on its own
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    // cut off at 1, what the renderer always did
+    Clamp,
+    // x / (1 + x)
+    Reinhard,
+    // Reinhard reaching 1 at the white point instead of at infinity
+    ReinhardExtended,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+    // John Hable's Uncharted 2 filmic curve
+    Hable
+}
+
+#[derive(Clone, Debug)]
+pub struct DisplaySettings {
+    // in stops: colors are scaled by 2^exposure first
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    // the value that maps to 1 for reinhard-extended (default: the brightest
+    // channel in the image) and hable (default: 11.2)
+    pub white: Option<f64>,
+    // add triangular noise of one step before rounding, against banding
+    pub dither: bool
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings{exposure: 0.0, tone_map: ToneMap::Clamp, white: None, dither: false}
+    }
+}
+
+impl DisplaySettings {
+    pub fn to_rgb8_image(&self, img: &Rgb32FImage) -> RgbImage {
+        let white = match (self.white, self.tone_map) {
+            (Some(white), _) => white,
+            (None, ToneMap::ReinhardExtended) => {
+                let brightest = img.pixels().flat_map(|p| p.0).fold(0.0f32, f32::max) as f64;
+                (brightest * self.exposure.exp2()).max(1e-6)
+            },
+            (None, _) => HABLE_WHITE
+        };
+        ImageBuffer::from_fn(img.width(), img.height(), |i, j| self.pixel(img.get_pixel(i, j), white, i, j))
+    }
+
+    fn pixel(&self, p: &Rgb32, white: f64, i: u32, j: u32) -> Rgb8 {
+        let scale = self.exposure.exp2();
+        let mut out = [0u8; 3];
+        for c in 0..3 {
+            let mapped = tone_map(self.tone_map, p[c] as f64 * scale, white);
+            out[c] = self.quantize(srgb_oetf(mapped), i, j, c);
+        }
+        image::Rgb(out)
+    }
+
+    // `v`, already display referred in [0, 1], to 0..=255
+    pub fn quantize(&self, v: f64, i: u32, j: u32, channel: usize) -> u8 {
+        let noise = if self.dither {
+            // difference of two uniforms: triangular on (-1, 1), the same for a pixel on every run
+            let key = [i as f64, j as f64, channel as f64];
+            hash_unif(&[key[0], key[1], key[2], 1.0]) - hash_unif(&[key[0], key[1], key[2], 2.0])
+        } else {
+            0.0
+        };
+        clamp((255.0 * clamp(v, 0.0, 1.0) + noise).round(), 0.0, 255.0) as u8
+    }
+}
+
+const HABLE_WHITE: f64 = 11.2;
+
+pub fn tone_map(op: ToneMap, x: f64, white: f64) -> f64 {
+    let x = x.max(0.0);
+    let mapped = match op {
+        ToneMap::Clamp => x,
+        ToneMap::Reinhard => x / (1.0 + x),
+        ToneMap::ReinhardExtended => x * (1.0 + x / (white * white)) / (1.0 + x),
+        ToneMap::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        // with the exposure bias of 2 from the original talk
+        ToneMap::Hable => hable_partial(2.0 * x) / hable_partial(white)
+    };
+    clamp(mapped, 0.0, 1.0)
+}
+
+fn hable_partial(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// linear to sRGB encoded, IEC 61966-2-1
+pub fn srgb_oetf(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// sRGB encoded to linear, the inverse of srgb_oetf
+pub fn srgb_eotf(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for k in 0..=1000 {
+            let x = k as f64 / 1000.0;
+            assert!((srgb_eotf(srgb_oetf(x)) - x).abs() < 1e-12);
+        }
+        // the two pieces meet
+        assert!((12.92 * 0.0031308 - (1.055 * 0.0031308f64.powf(1.0 / 2.4) - 0.055)).abs() < 1e-6);
+        assert!((srgb_oetf(0.5) - 0.735357).abs() < 1e-6);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_operators() {
+        let ops = [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::ReinhardExtended, ToneMap::Aces, ToneMap::Hable];
+        for op in ops {
+            assert_eq!(tone_map(op, 0.0, 4.0), 0.0, "{:?}", op);
+            // never decreasing, never above 1
+            let mut last = 0.0;
+            for k in 1..400 {
+                let y = tone_map(op, k as f64 * 0.05, 4.0);
+                assert!(y >= last && y <= 1.0, "{:?} at {}", op, k);
+                last = y;
+            }
+        }
+        assert_eq!(tone_map(ToneMap::Clamp, 3.0, 4.0), 1.0);
+        assert_eq!(tone_map(ToneMap::Reinhard, 1.0, 4.0), 0.5);
+        assert!((tone_map(ToneMap::ReinhardExtended, 4.0, 4.0) - 1.0).abs() < 1e-12);
+        assert!((tone_map(ToneMap::Hable, HABLE_WHITE / 2.0, HABLE_WHITE) - 1.0).abs() < 1e-12);
+        // highlights keep some separation instead of all clipping to white
+        assert!(tone_map(ToneMap::Aces, 2.0, 4.0) < tone_map(ToneMap::Aces, 4.0, 4.0));
+    }
+
+    #[test]
+    fn test_exposure_and_dither() {
+        let img = Rgb32FImage::from_fn(64, 1, |i, _| Rgb([i as f32 / 64.0, 0.25, 8.0]));
+        let plain = DisplaySettings::default().to_rgb8_image(&img);
+        // over-bright values clip instead of wrapping around
+        assert!(plain.pixels().all(|p| p[2] == 255));
+        assert_eq!(plain.get_pixel(0, 0)[1], (255.0 * srgb_oetf(0.25)).round() as u8);
+
+        let darker = DisplaySettings{exposure: -1.0, ..Default::default()}.to_rgb8_image(&img);
+        assert_eq!(darker.get_pixel(0, 0)[1], (255.0 * srgb_oetf(0.125)).round() as u8);
+
+        // dithering moves values by at most one step, and keeps their average
+        let flat = Rgb32FImage::from_pixel(100, 100, Rgb([0.3, 0.3, 0.3]));
+        let dithered = DisplaySettings{dither: true, ..Default::default()}.to_rgb8_image(&flat);
+        let exact = 255.0 * srgb_oetf(0.3);
+        let values: Vec<f64> = dithered.pixels().map(|p| p[0] as f64).collect();
+        assert!(values.iter().all(|v| (v - exact).abs() < 1.5));
+        assert!(values.iter().any(|v| *v != values[0]));
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((mean - exact).abs() < 0.05, "{} vs {}", mean, exact);
+    }
+}
@@ -0,0 +1,235 @@
+// light arriving from every direction, looked up in a latitude-longitude
+// (equirectangular) HDR image: +y is up, the center of the image lies
+// towards -z. Light samples pick a texel in proportion to its luminance
+// times the solid angle it covers, so a small bright sun is found by the
+// light samples instead of by the odd lucky bounce.
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::Rgb32FImage;
+use image::codecs::hdr::HdrDecoder;
+
+use crate::rtweekend::{RtRng, clamp};
+use crate::spectrum::luminance;
+use crate::vec3::{Color, Vec3, color, vec3_};
+
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+    // about +y, in radians
+    rotation: f64,
+    intensity: f64,
+    // sampling weights summed over the rows so far, from 0 to 1
+    row_cdf: Vec<f64>,
+    // the same within each row, width + 1 values per row
+    column_cdf: Vec<f64>
+}
+
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EnvironmentMap({}x{}, rotation {}°, intensity {})",
+               self.width, self.height, self.rotation.to_degrees(), self.intensity)
+    }
+}
+
+impl EnvironmentMap {
+    // turned by `rotation_deg` about +y, and scaled by `intensity`
+    pub fn new(img: &Rgb32FImage, rotation_deg: f64, intensity: f64) -> Self {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let texels: Vec<Color> = img.pixels().map(|p| color(p[0] as f64, p[1] as f64, p[2] as f64)).collect();
+
+        // black maps are sampled like a uniform one
+        let all_black = texels.iter().all(|c| luminance(c) <= 0.0);
+        let mut row_cdf = vec![0.0; height + 1];
+        let mut column_cdf = vec![0.0; (width + 1) * height];
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            let row = &mut column_cdf[y * (width + 1)..(y + 1) * (width + 1)];
+            for x in 0..width {
+                let weight = if all_black { 1.0 } else { luminance(&texels[y * width + x]).max(0.0) };
+                row[x + 1] = row[x] + weight * sin_theta;
+            }
+            let row_sum = row[width];
+            if row_sum > 0.0 {
+                row.iter_mut().for_each(|c| *c /= row_sum);
+            }
+            row_cdf[y + 1] = row_cdf[y] + row_sum;
+        }
+        let total = row_cdf[height];
+        row_cdf.iter_mut().for_each(|c| *c /= total);
+
+        EnvironmentMap{width, height, texels, rotation: rotation_deg.to_radians(), intensity, row_cdf, column_cdf}
+    }
+
+    // .hdr or .exr
+    pub fn load(path: &str, rotation_deg: f64, intensity: f64) -> Result<Self, image::ImageError> {
+        let is_hdr = Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+        let img = if is_hdr {
+            // the generic decoder would tone map to 8 bits
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path).map_err(image::ImageError::IoError)?))?;
+            let (width, height) = (decoder.metadata().width, decoder.metadata().height);
+            let pixels = decoder.read_image_hdr()?;
+            Rgb32FImage::from_vec(width, height, pixels.iter().flat_map(|p| p.0).collect()).unwrap()
+        } else {
+            image::open(path)?.into_rgb32f()
+        };
+        Ok(Self::new(&img, rotation_deg, intensity))
+    }
+
+    pub fn color(&self, direction: &Vec3) -> Color {
+        let (x, y) = self.texel(direction);
+        self.intensity * &self.texels[y * self.width + x]
+    }
+
+    // a direction towards the bright parts of the map
+    pub fn sample(&self, rng: &mut RtRng) -> Vec3 {
+        let (a, b) = rng.unif_2d();
+        let (y, dv) = sample_cdf(&self.row_cdf, a);
+        let row = &self.column_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let (x, du) = sample_cdf(row, b);
+        self.direction((x as f64 + du) / self.width as f64, (y as f64 + dv) / self.height as f64)
+    }
+
+    // density of `sample`, per unit solid angle
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        let (x, y) = self.texel(direction);
+        let sin_theta = (1.0 - direction.unit_vector().y.powi(2)).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0
+        }
+        let row = &self.column_cdf[y * (self.width + 1)..];
+        let p_texel = (self.row_cdf[y + 1] - self.row_cdf[y]) * (row[x + 1] - row[x]);
+        // per unit of u and v, then (u, v) -> direction covers 2 pi^2 sin(theta) of solid angle
+        p_texel * (self.width * self.height) as f64 / (2.0 * PI * PI * sin_theta)
+    }
+
+    // (u, v) in [0, 1)^2, v = 0 at the top
+    fn texel(&self, direction: &Vec3) -> (usize, usize) {
+        let d = direction.unit_vector();
+        let theta = clamp(d.y, -1.0, 1.0).acos();
+        let phi = d.x.atan2(-d.z);
+        let u = ((phi + PI - self.rotation) / (2.0 * PI)).rem_euclid(1.0);
+        let v = theta / PI;
+        (((u * self.width as f64) as usize).min(self.width - 1),
+         ((v * self.height as f64) as usize).min(self.height - 1))
+    }
+
+    fn direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = 2.0 * PI * u - PI + self.rotation;
+        let theta = PI * v;
+        vec3_(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+}
+
+// the bucket of `cdf` (n + 1 values from 0 to 1) that `a` falls in, and
+// where in it
+fn sample_cdf(cdf: &[f64], a: f64) -> (usize, f64) {
+    let n = cdf.len() - 1;
+    let k = cdf.partition_point(|c| *c <= a).clamp(1, n) - 1;
+    let width = cdf[k + 1] - cdf[k];
+    let offset = if width > 0.0 { ((a - cdf[k]) / width).clamp(0.0, 1.0 - f64::EPSILON) } else { 0.5 };
+    (k, offset)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use image::Rgb;
+
+    use super::*;
+    use crate::hittable::hittable_list;
+    use crate::integrator::{Background, Integrator, PathStats, SceneView, ray_color_49, ray_color_nee};
+    use crate::camera::CameraWithFocus;
+    use crate::material::Lambertian;
+    use crate::render::{RenderParams, render_scene};
+    use crate::rtweekend::{Shared, rng_from_seed};
+    use crate::sphere::Sphere;
+    use crate::vec3::point3;
+
+    // dim blue sky with a sun 10000 times brighter in one texel
+    fn sun_map(rotation_deg: f64) -> EnvironmentMap {
+        let mut img = Rgb32FImage::from_pixel(64, 32, Rgb([0.1, 0.2, 0.4]));
+        img.put_pixel(40, 10, Rgb([2000., 1800., 1500.]));
+        EnvironmentMap::new(&img, rotation_deg, 1.0)
+    }
+
+    #[test]
+    fn test_directions_and_lookup() {
+        let env = sun_map(0.0);
+        // the center of the image is towards -z, the top is +y
+        assert_eq!(env.texel(&vec3_(0., 0., -1.)), (32, 16));
+        assert_eq!(env.texel(&vec3_(0., 1., 0.)).1, 0);
+        let sun = env.direction(40.5 / 64.0, 10.5 / 32.0);
+        assert_eq!(env.texel(&sun), (40, 10));
+        assert!(env.color(&sun).x > 1000.0);
+
+        // turned by 90 degrees, the sun is found 16 texels further on
+        let turned = sun_map(90.0);
+        assert_eq!(turned.texel(&turned.direction(40.5 / 64.0, 10.5 / 32.0)), (40, 10));
+        assert_eq!(turned.texel(&sun), (24, 10));
+    }
+
+    #[test]
+    fn test_sampling_matches_pdf() {
+        let env = sun_map(30.0);
+        let texels = || (0..32).flat_map(|y| (0..64).map(move |x| (x, y)));
+        let solid_angle = |y: usize| 2.0 * PI * PI * (PI * (y as f64 + 0.5) / 32.0).sin() / (64.0 * 32.0);
+        // the pdf integrates to 1 over the sphere
+        let integral: f64 = texels().map(|(x, y)| {
+            env.pdf(&env.direction((x as f64 + 0.5) / 64.0, (y as f64 + 0.5) / 32.0)) * solid_angle(y)
+        }).sum();
+        assert!((integral - 1.0).abs() < 1e-9, "{}", integral);
+
+        // E[f / pdf] over samples is the integral of f, here of the luminance
+        let mut rng = rng_from_seed(1);
+        let n = 100_000;
+        let expected: f64 = texels().map(|(x, y)| luminance(&env.texels[y * 64 + x]) * solid_angle(y)).sum();
+        let estimate: f64 = (0..n).map(|_| {
+            let dir = env.sample(&mut rng);
+            luminance(&env.color(&dir)) / env.pdf(&dir)
+        }).sum::<f64>() / n as f64;
+        assert!((estimate / expected - 1.0).abs() < 0.01, "{} vs {}", estimate, expected);
+        // and most samples go to the sun
+        let to_sun = (0..1000).filter(|_| env.color(&env.sample(&mut rng)).x > 1000.0).count();
+        assert!(to_sun > 700, "{}", to_sun);
+    }
+
+    fn render(background: &Background, integrator: Integrator, spp: i32) -> Rgb32FImage {
+        let world = hittable_list(vec![
+            Sphere::new(point3(0., 0., -1.), 0.5, Lambertian::new(0.7, 0.7, 0.7)),
+            Sphere::new(point3(0., -100.5, -1.), 100.0, Lambertian::new(0.5, 0.5, 0.5))
+        ]);
+        let lights = hittable_list(vec![]);
+        let view = SceneView{world: &world, lights: &lights, background};
+        let camera = CameraWithFocus::new(&point3(0., 0.5, 1.), &point3(0., 0., -1.), vec3_(0., 1., 0.),
+                                          60.0, 1.0, 0.0, 1.0);
+        let rp = RenderParams::new(1.0, 16, spp);
+        render_scene(&view, &camera, integrator, &rp, false, &Mutex::new(PathStats::default())).0
+    }
+
+    #[test]
+    fn test_sun_is_sampled_without_fireflies() {
+        let background = Background::Environment(Shared::new(sun_map(0.0)));
+        let mse = |a: &Rgb32FImage, b: &Rgb32FImage| -> f64 {
+            a.pixels().zip(b.pixels()).flat_map(|(p, q)| (0..3).map(move |c| (p[c] as f64 - q[c] as f64).powi(2)))
+                .sum::<f64>() / (3 * a.width() * a.height()) as f64
+        };
+        let reference = render(&background, Integrator::Recursive(ray_color_nee), 1024);
+        let sampled = render(&background, Integrator::Recursive(ray_color_nee), 16);
+        let unsampled = render(&background, Integrator::Recursive(ray_color_49), 16);
+        let (sampled, unsampled) = (mse(&sampled, &reference), mse(&unsampled, &reference));
+        assert!(sampled < 0.1 * unsampled, "{} vs {}", sampled, unsampled);
+
+        // both see the same sky on average
+        let mean = |img: &Rgb32FImage| img.pixels().map(|p| p[0] as f64).sum::<f64>() / 256.0;
+        let bsdf_only = render(&background, Integrator::Recursive(ray_color_49), 1024);
+        assert!((mean(&bsdf_only) / mean(&reference) - 1.0).abs() < 0.05,
+                "{} vs {}", mean(&bsdf_only), mean(&reference));
+    }
+}
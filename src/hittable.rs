@@ -1,8 +1,9 @@
 
-use crate::vec3::{Point3, Vec3};
+use crate::vec3::{Point3, Vec3, vec3_};
 use crate::ray::Ray;
-use crate::rtweekend::Shared;
+use crate::rtweekend::{Shared, RtRng, random_unif_1};
 use crate::material::{Material, Lambertian};
+use crate::aabb::{Aabb, surrounding_box};
 
 #[derive(Clone)]
 pub struct HitRecord {
@@ -10,7 +11,15 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub material: Shared<dyn Material>,
     pub t: f64,
-    pub front_face: bool
+    // surface coordinates of the hit point, in [0, 1]
+    pub u: f64,
+    pub v: f64,
+    // dp/du, the direction anisotropic materials are stretched along; zero
+    // where u doesn't change along the surface
+    pub tangent: Vec3,
+    pub front_face: bool,
+    // set by ObjectTag, 0 for untagged objects
+    pub object_id: u32
 }
 
 impl Default for HitRecord {
@@ -19,7 +28,11 @@ impl Default for HitRecord {
              normal: Vec3::default(),
              material: Lambertian::new(1., 1., 1.),
              t: 0.,
-             front_face: false}
+             u: 0.,
+             v: 0.,
+             tangent: Vec3::default(),
+             front_face: false,
+             object_id: 0}
     }
 }
 
@@ -32,11 +45,25 @@ impl HitRecord {
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+    // None for objects without a finite extent
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // for objects that can be sampled as lights, "The Rest of Your Life"
+    // section 12: density per steradian of random(origin) drawing `direction`,
+    // 0 when the ray from `origin` misses the object
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // direction from `origin` towards a random point of the object
+    fn random(&self, _origin: &Point3, _rng: &mut RtRng) -> Vec3 {
+        vec3_(1., 0., 0.)
+    }
 }
 
-type SharedHittable = Shared<dyn Hittable>;
+pub type SharedHittable = Shared<dyn Hittable>;
 
 
 pub struct HittableList {
@@ -55,8 +82,8 @@ pub fn hittable_single(object: SharedHittable) -> HittableList {
     HittableList{ objects: vec![object] }
 }
 
-pub fn hittable_list(objects: &Vec<SharedHittable>) -> HittableList {
-    HittableList{ objects: objects.clone() }
+pub fn hittable_list(objects: Vec<SharedHittable>) -> HittableList {
+    HittableList{ objects }
 }
 
 impl Hittable for HittableList {
@@ -76,6 +103,70 @@ impl Hittable for HittableList {
             }
         }
 
-        return hit_anything;
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut output_box: Option<Aabb> = None;
+
+        for object in &self.objects {
+            let obj_box = object.bounding_box()?;
+            output_box = match output_box {
+                None => Some(obj_box),
+                Some(b) => Some(surrounding_box(&b, &obj_box))
+            }
+        }
+        output_box
+    }
+
+    // one member picked at random, so the density is the average of theirs
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0
+        }
+        let sum: f64 = self.objects.iter().map(|o| o.pdf_value(origin, direction)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut RtRng) -> Vec3 {
+        let n = self.objects.len();
+        let k = ((random_unif_1(rng) * n as f64) as usize).min(n - 1);
+        self.objects[k].random(origin, rng)
+    }
+}
+
+
+// marks the hits on `object` with `id`, for the object ID output
+pub struct ObjectTag {
+    object: SharedHittable,
+    id: u32
+}
+
+impl ObjectTag {
+    pub fn new(object: SharedHittable, id: u32) -> Shared<ObjectTag> {
+        Shared::new(ObjectTag{object, id})
+    }
+}
+
+impl Hittable for ObjectTag {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if self.object.hit(ray, t_min, t_max, rec) {
+            rec.object_id = self.id;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut RtRng) -> Vec3 {
+        self.object.random(origin, rng)
     }
-}
\ No newline at end of file
+}
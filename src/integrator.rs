@@ -0,0 +1,655 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use crate::environment::EnvironmentMap;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::material::ScatterRecord;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
+use crate::ray::Ray;
+use crate::rtweekend::{INF, RtRng, Shared, random_unif_1};
+use crate::sky::Sky;
+use crate::spectrum::Wavelengths;
+use crate::vec3::{Color, Point3, Vec3, color};
+
+// what the integrators get to see of a scene
+pub struct SceneView<'a> {
+    pub world: &'a dyn Hittable,
+    // emitters that can be sampled directly (spheres, quads, lists of them),
+    // also part of `world`; may be empty
+    pub lights: &'a HittableList,
+    pub background: &'a Background
+}
+
+impl SceneView<'_> {
+    // part of the light samples that go to the environment rather than to `lights`
+    fn environment_share(&self) -> f64 {
+        match (self.background.is_sampled(), self.lights.objects.is_empty()) {
+            (false, _) => 0.0,
+            (true, true) => 1.0,
+            (true, false) => 0.5
+        }
+    }
+
+    // density of the light samples from `origin` in `direction`
+    fn light_pdf(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let share = self.environment_share();
+        let lights = if share < 1.0 { (1.0 - share) * self.lights.pdf_value(origin, direction) } else { 0.0 };
+        lights + if share > 0.0 { share * self.background.pdf(direction) } else { 0.0 }
+    }
+}
+
+// signature shared by the path tracing integrators below, so a scene can pick one
+pub type RayColorFn = fn(&Ray, &mut RtRng, &SceneView, i32) -> Color;
+
+// what rays that leave the scene see
+#[derive(Debug, Clone)]
+pub enum Background {
+    // white to light blue sky, ray_color_background
+    Gradient,
+    // black for interiors and scenes lit only by their emissive materials
+    Solid(Color),
+    // an HDR image all around, also sampled like a light by ray_color_nee
+    Environment(Shared<EnvironmentMap>),
+    // daylight and a sun, which ray_color_nee samples like a light
+    Sky(Shared<Sky>)
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        spectral(ray, match self {
+            Background::Gradient => ray_color_background(ray),
+            Background::Solid(c) => c.clone(),
+            Background::Environment(env) => env.color(&ray.dir),
+            Background::Sky(sky) => sky.color(&ray.dir)
+        })
+    }
+
+    // whether light samples are drawn from the background too
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Background::Environment(_) | Background::Sky(_))
+    }
+
+    // a direction for a light sample, where the background is bright
+    pub fn sample(&self, rng: &mut RtRng) -> Vec3 {
+        match self {
+            Background::Environment(env) => env.sample(rng),
+            Background::Sky(sky) => sky.sample(rng),
+            _ => Vec3::random_unit_vector(rng)
+        }
+    }
+
+    // density of `sample`, per unit solid angle
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        match self {
+            Background::Environment(env) => env.pdf(direction),
+            Background::Sky(sky) => sky.pdf(direction),
+            _ => 1.0 / (4.0 * PI)
+        }
+    }
+}
+
+
+// In spectral mode, the RGB colors of materials, lights and backgrounds
+// become values at the wavelengths of the path where the integrators read
+// them, through these.
+
+fn spectral(ray: &Ray, rgb: Color) -> Color {
+    match &ray.wavelengths {
+        Some(wavelengths) => wavelengths.upsample(&rgb),
+        None => rgb
+    }
+}
+
+fn emitted(ray: &Ray, rec: &HitRecord) -> Color {
+    spectral(ray, rec.material.emitted(rec.u, rec.v, &rec.p))
+}
+
+fn scatter(ray: &Ray, rec: &HitRecord, rng: &mut RtRng) -> Option<ScatterRecord> {
+    let mut s_rec = rec.material.scatter(ray, rec, rng)?;
+    if let Some(wavelengths) = &ray.wavelengths {
+        let (next, factor) = leaving(wavelengths, rec);
+        s_rec.attenuation = &factor * &wavelengths.upsample(&s_rec.attenuation);
+        s_rec.scattered.wavelengths = Some(next);
+    }
+    Some(s_rec)
+}
+
+fn eval_bsdf(ray: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let bsdf = rec.material.eval_bsdf(ray, rec, scattered);
+    match &ray.wavelengths {
+        Some(wavelengths) => &leaving(wavelengths, rec).1 * &wavelengths.upsample(&bsdf),
+        None => bsdf
+    }
+}
+
+// the wavelengths that go on from `rec` and the factor of their radiance:
+// dispersive materials send each wavelength its own way, so only the hero
+// can follow
+fn leaving(wavelengths: &Wavelengths, rec: &HitRecord) -> (Wavelengths, Color) {
+    if rec.material.is_dispersive() {
+        wavelengths.terminate_secondary()
+    } else {
+        (*wavelengths, color(1., 1., 1.))
+    }
+}
+
+
+// primary rays see a white hemisphere towards +z, bounces see `background`
+pub fn ray_color_71(ray: &Ray, rng: &mut RtRng, scene: &SceneView, depth: i32) -> Color {
+    rng.start_bounce();
+    // listing 38: true lambertian reflection
+    let mut rec = HitRecord::default();
+    if depth <= 0 { return color(0., 0., 0.)}
+
+    if scene.world.hit(ray, 0.001, INF, &mut rec) {
+        let emitted = emitted(ray, &rec);
+        if let Some(s_rec) = scatter(ray, &rec, rng) {
+            emitted + &s_rec.attenuation * &ray_color_49(&s_rec.scattered, rng, scene, depth - 1)
+        } else {
+            emitted
+        }
+
+    } else {
+
+        if ray.dir.z > 0.0 {
+            color(1.0, 1.0, 1.0)
+        } else {
+            color(0., 0., 0.)
+        }
+
+    }
+}
+
+
+pub fn ray_color_49(ray: &Ray, rng: &mut RtRng, scene: &SceneView, depth: i32) -> Color {
+    rng.start_bounce();
+    // listing 38: true lambertian reflection
+    let mut rec = HitRecord::default();
+    if depth <= 0 { return color(0., 0., 0.)}
+
+    if scene.world.hit(ray, 0.001, INF, &mut rec) {
+        // "The Next Week", section 7: light emitted at the hit plus light scattered there
+        let emitted = emitted(ray, &rec);
+        if let Some(s_rec) = scatter(ray, &rec, rng) {
+            emitted + &s_rec.attenuation * &ray_color_49(&s_rec.scattered, rng, scene, depth - 1)
+        } else {
+            emitted
+        }
+
+    } else {
+        scene.background.color(ray)
+    }
+}
+
+
+// "The Rest of Your Life", section 12: bounces go towards the lights half of
+// the time and where the material would send them otherwise, weighted by the
+// mixture's density (one sample multiple importance sampling)
+pub fn ray_color_mixture(ray: &Ray, rng: &mut RtRng, scene: &SceneView, depth: i32) -> Color {
+    rng.start_bounce();
+    let mut rec = HitRecord::default();
+    if depth <= 0 { return color(0., 0., 0.)}
+
+    if !scene.world.hit(ray, 0.001, INF, &mut rec) {
+        return scene.background.color(ray)
+    }
+
+    let emitted = emitted(ray, &rec);
+    let s_rec = match scatter(ray, &rec, rng) {
+        Some(s_rec) => s_rec,
+        None => return emitted
+    };
+    let material_pdf = match &s_rec.pdf {
+        Some(pdf) => pdf,
+        // mirrors and glass: only one way to go
+        None => return emitted + &s_rec.attenuation * &ray_color_mixture(&s_rec.scattered, rng, scene, depth - 1)
+    };
+    if scene.lights.objects.is_empty() {
+        return emitted + &s_rec.attenuation * &ray_color_mixture(&s_rec.scattered, rng, scene, depth - 1)
+    }
+
+    let light_pdf = HittablePdf::new(scene.lights, &rec.p);
+    let mixed = MixturePdf::new(&light_pdf, material_pdf.as_ref());
+    let scattered = s_rec.scattered.next(&rec.p, &mixed.generate(rng));
+    let pdf_value = mixed.value(&scattered.dir);
+    let bsdf = eval_bsdf(ray, &rec, &scattered);
+    if pdf_value <= 0. || bsdf.near_zero() {
+        return emitted
+    }
+
+    let incoming = ray_color_mixture(&scattered, rng, scene, depth - 1);
+    emitted + (1.0 / pdf_value) * &(&bsdf * &incoming)
+}
+
+
+// next event estimation: every diffuse bounce also sends a shadow ray
+// towards a point picked on the lights. Light found that way and light
+// reached by the bounce itself are combined with the power heuristic, so
+// neither small lights (found by the shadow rays) nor large ones (found by
+// the bounces) get noisy.
+pub fn ray_color_nee(ray: &Ray, rng: &mut RtRng, scene: &SceneView, depth: i32) -> Color {
+    nee_path(ray, rng, scene, depth, None)
+}
+
+// `bsdf_pdf`: density the bounce that sent `ray` had for its direction; None
+// for camera rays and after mirror-like bounces, which no light sample can
+// stand in for
+fn nee_path(ray: &Ray, rng: &mut RtRng, scene: &SceneView, depth: i32, bsdf_pdf: Option<f64>) -> Color {
+    rng.start_bounce();
+    let mut rec = HitRecord::default();
+    if depth <= 0 { return color(0., 0., 0.)}
+
+    if !scene.world.hit(ray, 0.001, INF, &mut rec) {
+        return mis_background(ray, scene, bsdf_pdf)
+    }
+
+    let emitted = mis_emitted(ray, &rec, scene, bsdf_pdf);
+    let s_rec = match scatter(ray, &rec, rng) {
+        Some(s_rec) => s_rec,
+        None => return emitted
+    };
+    let material_pdf = match &s_rec.pdf {
+        Some(pdf) => pdf,
+        None => return emitted + &s_rec.attenuation * &nee_path(&s_rec.scattered, rng, scene, depth - 1, None)
+    };
+
+    // light sample; like the bounce below it only counts with depth to spare
+    let direct = if depth > 1 {
+        direct_light(ray, &rec, material_pdf.as_ref(), scene, rng)
+    } else {
+        color(0., 0., 0.)
+    };
+
+    let pdf_b = material_pdf.value(&s_rec.scattered.dir);
+    if pdf_b <= 0. || s_rec.attenuation.near_zero() {
+        return emitted + direct
+    }
+    let incoming = nee_path(&s_rec.scattered, rng, scene, depth - 1, Some(pdf_b));
+    emitted + direct + &s_rec.attenuation * &incoming
+}
+
+// light given off at `rec`, weighted against the chance that the light
+// sample of the previous bounce found it
+fn mis_emitted(ray: &Ray, rec: &HitRecord, scene: &SceneView, bsdf_pdf: Option<f64>) -> Color {
+    let emitted = emitted(ray, rec);
+    match bsdf_pdf {
+        Some(pdf_b) if !emitted.near_zero() => {
+            let pdf_l = scene.light_pdf(&ray.origin, &ray.dir);
+            power_heuristic(pdf_b, pdf_l) * &emitted
+        },
+        _ => emitted
+    }
+}
+
+// what `ray` sees when it leaves the scene, weighted like mis_emitted if
+// the light samples could have found it too
+fn mis_background(ray: &Ray, scene: &SceneView, bsdf_pdf: Option<f64>) -> Color {
+    let background = scene.background.color(ray);
+    match bsdf_pdf {
+        Some(pdf_b) if scene.environment_share() > 0.0 => {
+            power_heuristic(pdf_b, scene.light_pdf(&ray.origin, &ray.dir)) * &background
+        },
+        _ => background
+    }
+}
+
+// light reaching `rec` along a shadow ray to a random point on the lights
+// (or a direction of a sampled background), times what the material scatters
+// towards `ray`
+fn direct_light(ray: &Ray, rec: &HitRecord, material_pdf: &dyn Pdf, scene: &SceneView, rng: &mut RtRng) -> Color {
+    let share = scene.environment_share();
+    if scene.lights.objects.is_empty() && share == 0.0 {
+        return color(0., 0., 0.)
+    }
+
+    // no number drawn to choose unless there is a choice
+    let to_environment = share >= 1.0 || (share > 0.0 && random_unif_1(rng) < share);
+    let direction = if to_environment { scene.background.sample(rng) } else { scene.lights.random(&rec.p, rng) };
+    let to_light = ray.next(&rec.p, &direction);
+    let pdf_l = scene.light_pdf(&rec.p, &to_light.dir);
+    let bsdf = eval_bsdf(ray, rec, &to_light);
+    if pdf_l <= 0. || bsdf.near_zero() {
+        return color(0., 0., 0.)
+    }
+
+    // whatever the shadow ray runs into first; occluders emit nothing
+    let mut light_rec = HitRecord::default();
+    let light = if scene.world.hit(&to_light, 0.001, INF, &mut light_rec) {
+        emitted(&to_light, &light_rec)
+    } else if share > 0.0 {
+        scene.background.color(&to_light)
+    } else {
+        return color(0., 0., 0.)
+    };
+    let weight = power_heuristic(pdf_l, material_pdf.value(&to_light.dir));
+    (weight / pdf_l) * &(&bsdf * &light)
+}
+
+// weight of a sample drawn with density `pdf_f` when `pdf_g` could have drawn it too
+fn power_heuristic(pdf_f: f64, pdf_g: f64) -> f64 {
+    let (f2, g2) = (pdf_f * pdf_f, pdf_g * pdf_g);
+    f2 / (f2 + g2)
+}
+
+
+// how a pixel's color is computed
+#[derive(Clone, Copy)]
+pub enum Integrator {
+    // one of the ray_color functions, recursing down to a fixed depth
+    Recursive(RayColorFn),
+    // ray_color_iterative, with Russian roulette after `min_bounces`
+    Iterative{min_bounces: i32}
+}
+
+// why a path of ray_color_iterative stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathEnd {
+    // left the scene
+    Escaped,
+    // hit something that scatters nothing, like a light
+    Absorbed,
+    Roulette,
+    MaxDepth
+}
+
+// bounce counts of many paths of ray_color_iterative
+#[derive(Debug, Clone, Default)]
+pub struct PathStats {
+    pub paths: u64,
+    pub bounces: u64,
+    pub max_bounces: u64,
+    pub escaped: u64,
+    pub absorbed: u64,
+    pub roulette: u64,
+    pub max_depth: u64
+}
+
+impl PathStats {
+    pub fn record(&mut self, bounces: u64, end: PathEnd) {
+        self.paths += 1;
+        self.bounces += bounces;
+        self.max_bounces = self.max_bounces.max(bounces);
+        match end {
+            PathEnd::Escaped => self.escaped += 1,
+            PathEnd::Absorbed => self.absorbed += 1,
+            PathEnd::Roulette => self.roulette += 1,
+            PathEnd::MaxDepth => self.max_depth += 1
+        }
+    }
+
+    pub fn merge(&mut self, other: &PathStats) {
+        self.paths += other.paths;
+        self.bounces += other.bounces;
+        self.max_bounces = self.max_bounces.max(other.max_bounces);
+        self.escaped += other.escaped;
+        self.absorbed += other.absorbed;
+        self.roulette += other.roulette;
+        self.max_depth += other.max_depth;
+    }
+
+    pub fn mean_bounces(&self) -> f64 {
+        self.bounces as f64 / self.paths.max(1) as f64
+    }
+}
+
+impl fmt::Display for PathStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |n: u64| 100.0 * n as f64 / self.paths.max(1) as f64;
+        write!(f, "{} paths, {:.2} bounces on average, {} at most; ended by escaping {:.1}%, \
+                   absorption {:.1}%, Russian roulette {:.1}%, max depth {:.1}%",
+               self.paths, self.mean_bounces(), self.max_bounces, percent(self.escaped),
+               percent(self.absorbed), percent(self.roulette), percent(self.max_depth))
+    }
+}
+
+// ray_color_nee as a loop: the light still to be gathered is scaled by
+// `throughput`, the product of what the bounces so far let through. From
+// `min_bounces` on, a path only goes on with a probability given by its
+// throughput, and is boosted by the inverse when it does, so dim paths stop
+// early without darkening the image. `max_depth` stays as a hard limit.
+pub fn ray_color_iterative(ray: &Ray, rng: &mut RtRng, scene: &SceneView, max_depth: i32,
+                           min_bounces: i32, stats: &mut PathStats) -> Color {
+    let mut radiance = color(0., 0., 0.);
+    let mut throughput = color(1., 1., 1.);
+    let mut ray = ray.next(&ray.origin, &ray.dir);
+    let mut bsdf_pdf = None;
+    let mut bounces = 0;
+
+    let end = loop {
+        if bounces >= max_depth {
+            break PathEnd::MaxDepth
+        }
+        rng.start_bounce();
+        let mut rec = HitRecord::default();
+        if !scene.world.hit(&ray, 0.001, INF, &mut rec) {
+            radiance += &(&throughput * &mis_background(&ray, scene, bsdf_pdf));
+            break PathEnd::Escaped
+        }
+
+        radiance += &(&throughput * &mis_emitted(&ray, &rec, scene, bsdf_pdf));
+        let s_rec = match scatter(&ray, &rec, rng) {
+            Some(s_rec) => s_rec,
+            None => break PathEnd::Absorbed
+        };
+
+        match &s_rec.pdf {
+            None => {
+                throughput = &throughput * &s_rec.attenuation;
+                bsdf_pdf = None;
+            },
+            Some(material_pdf) => {
+                if bounces + 1 < max_depth {
+                    let direct = direct_light(&ray, &rec, material_pdf.as_ref(), scene, rng);
+                    radiance += &(&throughput * &direct);
+                }
+                let pdf_b = material_pdf.value(&s_rec.scattered.dir);
+                if pdf_b <= 0. || s_rec.attenuation.near_zero() {
+                    break PathEnd::Absorbed
+                }
+                throughput = &throughput * &s_rec.attenuation;
+                bsdf_pdf = Some(pdf_b);
+            }
+        }
+        ray = s_rec.scattered;
+        bounces += 1;
+
+        if bounces >= min_bounces && bounces < max_depth {
+            // capped below 1 so that even white rooms end
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if random_unif_1(rng) >= survival {
+                break PathEnd::Roulette
+            }
+            throughput /= survival;
+        }
+    };
+
+    stats.record(bounces as u64, end);
+    radiance
+}
+
+
+
+#[inline(always)]
+pub fn ray_color_background(ray: &Ray) -> Color {
+    let unit_dir = ray.dir.unit_vector();
+    let t = 0.5 * (unit_dir.y + 1.0);
+    (1.0 - t) * color(1.0, 1.0, 1.0) + t * color(0.5, 0.7, 1.)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::hittable_list;
+    use crate::material::{Conductor, Dielectric, DiffuseLight, Lambertian, Material, RoughDielectric};
+    use crate::principled::{Principled, PrincipledParams, constant};
+    use crate::quad::Quad;
+    use crate::rtweekend::{random_unif, rng_from_seed};
+    use crate::spectrum::Ior;
+    use crate::sphere::Sphere;
+    use crate::vec3::{point3, vec3_};
+
+    #[test]
+    fn test_emission_and_black_background() {
+        let lamp = Sphere::new(point3(0., 0., -2.), 0.5, DiffuseLight::new(color(4., 3., 2.)));
+        let black = Background::Solid(color(0., 0., 0.));
+        let no_lights = hittable_list(vec![]);
+        let scene = SceneView{world: lamp.as_ref(), lights: &no_lights, background: &black};
+        let mut rng = rng_from_seed(0);
+
+        let at_lamp = Ray::new(&point3(0., 0., 0.), &vec3_(0., 0., -1.));
+        assert_eq!(ray_color_49(&at_lamp, &mut rng, &scene, 50), color(4., 3., 2.));
+
+        let away = Ray::new(&point3(0., 0., 0.), &vec3_(0., 1., 0.));
+        assert_eq!(ray_color_49(&away, &mut rng, &scene, 50), color(0., 0., 0.));
+        let sky = SceneView{background: &Background::Gradient, ..scene};
+        assert_eq!(ray_color_49(&away, &mut rng, &sky, 50), ray_color_background(&away));
+    }
+
+    // mean and standard error of the green channel over rays aimed at the floor
+    fn estimate<F>(seed: u64, mut ray_color: F) -> (f64, f64)
+        where F: FnMut(&Ray, &mut RtRng) -> Color {
+        let mut rng = rng_from_seed(seed);
+        let n = 40_000;
+        let samples: Vec<f64> = (0..n).map(|_| {
+            let target = point3(random_unif(&mut rng, -1., 1.), 0., random_unif(&mut rng, -1., 1.));
+            let origin = point3(0., 1., 3.);
+            let ray = Ray::new(&origin, &(target - &origin));
+            ray_color(&ray, &mut rng).y
+        }).collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let var = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1) as f64;
+        (mean, (var / n as f64).sqrt())
+    }
+
+    // a floor and a diffuse ball lit by a small lamp and a dim panel: (world, lights)
+    fn lit_floor() -> (HittableList, HittableList) {
+        lit_floor_with(Lambertian::new(0.6, 0.6, 0.6))
+    }
+
+    fn lit_floor_with(ball: Shared<dyn Material>) -> (HittableList, HittableList) {
+        let gray = Lambertian::new(0.6, 0.6, 0.6);
+        let lamp = Sphere::new(point3(0.5, 2., 0.), 0.25, DiffuseLight::new(color(30., 30., 30.)));
+        let panel = Quad::new(point3(-2., 0., -2.), vec3_(0., 2., 0.), vec3_(0., 0., 4.),
+                              DiffuseLight::new(color(1., 1., 1.)));
+        let world = hittable_list(vec![
+            Quad::new(point3(-2., 0., 2.), vec3_(4., 0., 0.), vec3_(0., 0., -4.), gray.clone()),
+            Sphere::new(point3(-0.5, 0.5, 0.), 0.5, ball),
+            lamp.clone(),
+            panel.clone()
+        ]);
+        (world, hittable_list(vec![lamp, panel]))
+    }
+
+    // a dim sky that isn't among the lights
+    const SKY: Background = Background::Solid(Color{x: 0.2, y: 0.2, z: 0.2});
+
+    #[test]
+    fn test_light_sampling_is_unbiased() {
+        let (world, lights) = lit_floor();
+        let scene = SceneView{world: &world, lights: &lights, background: &SKY};
+
+        let (reference, reference_err) = estimate(1, |ray, rng| ray_color_49(ray, rng, &scene, 6));
+        for (name, ray_color) in [("nee", ray_color_nee as RayColorFn), ("mixture", ray_color_mixture)] {
+            let (mean, err) = estimate(2, |ray, rng| ray_color(ray, rng, &scene, 6));
+            let tolerance = 4.0 * (err * err + reference_err * reference_err).sqrt();
+            assert!((mean - reference).abs() < tolerance,
+                    "{}: {} +- {} vs {} +- {}", name, mean, err, reference, reference_err);
+            // and it should get there with less noise
+            assert!(err < 0.5 * reference_err, "{}: error {} vs {}", name, err, reference_err);
+        }
+    }
+
+    // light samples weighted by eval_bsdf agree with bounces weighted by
+    // the attenuation of scatter
+    #[test]
+    fn test_light_sampling_of_microfacets_is_unbiased() {
+        let coated_glass = Principled::new(PrincipledParams{
+            metallic: constant(0.3), sheen: constant(0.5), clearcoat: constant(1.0), transmission: constant(0.5),
+            anisotropy: constant(0.5), ..PrincipledParams::default()
+        });
+        for ball in [Conductor::metal("copper", 0.4).unwrap(), RoughDielectric::with_ior(Ior::Constant(1.5), 0.5), coated_glass] {
+            let (world, lights) = lit_floor_with(ball);
+            let scene = SceneView{world: &world, lights: &lights, background: &SKY};
+            let (reference, reference_err) = estimate(3, |ray, rng| ray_color_49(ray, rng, &scene, 6));
+            for (name, ray_color) in [("nee", ray_color_nee as RayColorFn), ("mixture", ray_color_mixture)] {
+                let (mean, err) = estimate(4, |ray, rng| ray_color(ray, rng, &scene, 6));
+                let tolerance = 4.0 * (err * err + reference_err * reference_err).sqrt();
+                assert!((mean - reference).abs() < tolerance,
+                        "{}: {} +- {} vs {} +- {}", name, mean, err, reference, reference_err);
+            }
+        }
+    }
+
+    // the film color of `ray_color` along `ray` at random wavelengths
+    fn spectral_sample(ray: &Ray, rng: &mut RtRng, ray_color: impl Fn(&Ray, &mut RtRng) -> Color) -> Color {
+        let wavelengths = Wavelengths::sample(random_unif_1(rng));
+        let ray = Ray{wavelengths: Some(wavelengths), ..ray.next(&ray.origin, &ray.dir)};
+        wavelengths.rgb(&ray_color(&ray, rng))
+    }
+
+    #[test]
+    fn test_spectral_paths() {
+        // gray things look the same at every wavelength
+        let (world, lights) = lit_floor_with(Dielectric::with_ior(Ior::Constant(1.5)));
+        let scene = SceneView{world: &world, lights: &lights, background: &SKY};
+        let (reference, reference_err) = estimate(5, |ray, rng| ray_color_nee(ray, rng, &scene, 6));
+        let (mean, err) =
+            estimate(6, |ray, rng| spectral_sample(ray, rng, |ray, rng| ray_color_nee(ray, rng, &scene, 6)));
+        let tolerance = 4.0 * (err * err + reference_err * reference_err).sqrt();
+        assert!((mean - reference).abs() < tolerance, "{} +- {} vs {} +- {}", mean, err, reference, reference_err);
+
+        // glass that splits light into colors ends the other wavelengths of
+        // its paths, and light samples at rough glass still agree with bounces
+        let flint = Ior::Cauchy{a: 1.7, b: 0.02};
+        for ball in [Dielectric::with_ior(flint.clone()), RoughDielectric::with_ior(flint, 0.4)] {
+            let (world, lights) = lit_floor_with(ball);
+            let scene = SceneView{world: &world, lights: &lights, background: &SKY};
+            let (reference, reference_err) =
+                estimate(7, |ray, rng| spectral_sample(ray, rng, |ray, rng| ray_color_49(ray, rng, &scene, 6)));
+            for (name, ray_color) in [("nee", ray_color_nee as RayColorFn), ("mixture", ray_color_mixture)] {
+                let (mean, err) =
+                    estimate(8, |ray, rng| spectral_sample(ray, rng, |ray, rng| ray_color(ray, rng, &scene, 6)));
+                let tolerance = 4.0 * (err * err + reference_err * reference_err).sqrt();
+                assert!((mean - reference).abs() < tolerance,
+                        "{}: {} +- {} vs {} +- {}", name, mean, err, reference, reference_err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_iterative_without_roulette_is_ray_color_nee() {
+        let (world, lights) = lit_floor();
+        let scene = SceneView{world: &world, lights: &lights, background: &SKY};
+        let mut stats = PathStats::default();
+        for k in 0..200 {
+            let ray = Ray::new(&point3(0., 1., 3.), &vec3_(0.01 * k as f64 - 1., -1., -3.));
+            let recursive = ray_color_nee(&ray, &mut rng_from_seed(k), &scene, 6);
+            let iterative = ray_color_iterative(&ray, &mut rng_from_seed(k), &scene, 6, 6, &mut stats);
+            assert!((&recursive - &iterative).length() < 1e-9 * (1.0 + recursive.length()),
+                    "{:?} vs {:?}", recursive, iterative);
+        }
+        assert_eq!(stats.paths, 200);
+        assert_eq!(stats.roulette, 0);
+        assert!(stats.max_bounces <= 6);
+    }
+
+    #[test]
+    fn test_russian_roulette_is_unbiased() {
+        let (world, lights) = lit_floor();
+        let scene = SceneView{world: &world, lights: &lights, background: &SKY};
+
+        let mut full = PathStats::default();
+        let (reference, reference_err) =
+            estimate(1, |ray, rng| ray_color_iterative(ray, rng, &scene, 50, 50, &mut full));
+        let mut roulette = PathStats::default();
+        let (mean, err) = estimate(2, |ray, rng| ray_color_iterative(ray, rng, &scene, 50, 1, &mut roulette));
+
+        let tolerance = 4.0 * (err * err + reference_err * reference_err).sqrt();
+        assert!((mean - reference).abs() < tolerance, "{} +- {} vs {} +- {}", mean, err, reference, reference_err);
+        assert!(roulette.roulette > 0);
+        assert!(roulette.mean_bounces() < 0.75 * full.mean_bounces(),
+                "{} vs {}", roulette.mean_bounces(), full.mean_bounces());
+        assert_eq!(roulette.paths, roulette.escaped + roulette.absorbed + roulette.roulette + roulette.max_depth);
+    }
+}
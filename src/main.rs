@@ -1,4 +1,3 @@
-
 mod vec3_img;
 mod vec3;
 mod ray;
@@ -7,142 +6,147 @@ mod hittable;
 mod rtweekend;
 mod camera;
 mod material;
-
-use std::ops::Range;
-
-use camera::CameraWithFocus;
-use hittable::HittableList;
-use image::ImageBuffer;
-use material::Dielectric;
-use rand::rngs::ThreadRng;
-use crate::rtweekend::{INF, random_unif, random_unif_1, degrees_to_radians, clamp, Shared};
-use crate::hittable::{HitRecord, hittable_list, Hittable, hittable_single};
+mod aabb;
+mod bvh;
+mod render;
+mod integrator;
+mod scenes;
+mod cli;
+mod scene_file;
+mod triangle;
+mod obj;
+mod texture;
+mod perlin;
+mod quad;
+mod transform;
+mod medium;
+mod pdf;
+mod output;
+mod display;
+mod aov;
+mod denoise;
+mod sampler;
+mod environment;
+mod sky;
+mod microfacet;
+mod principled;
+mod spectrum;
+
+use bvh::BvhNode;
+use image::{ImageBuffer, Rgb32FImage};
+use render::{render_scene, render_tiles, RenderParams};
+use scene_file::load_scene_file;
+use scenes::{find_scene, four_sphere_world_50, four_sphere_world_52,
+             four_sphere_world_55, four_sphere_world_60, four_sphere_world_65, two_sphere_world};
+use crate::rtweekend::{INF, random_unif, random_unif_1, degrees_to_radians, clamp, RtRng, Shared, rng_from_seed};
+use crate::hittable::{HitRecord, Hittable, HittableList, ObjectTag, SharedHittable, hittable_list,
+                      hittable_single};
 use crate::vec3::{vec3_, color, Color, point3, Vec3, Point3};
 use crate::ray::{Ray};
 use crate::sphere::{Sphere, hit_sphere_10, hit_sphere_11, hit_sphere_12};
-use crate::camera::Camera;
+use crate::camera::{Camera, CameraParams};
 use crate::vec3_img::color_no_gamma;
-use crate::material::{Lambertian, Metal, Material};
-
+use crate::aov::Aov;
+use crate::denoise::Denoiser;
+use crate::output::Output;
+use crate::display::DisplaySettings;
+use crate::integrator::{Background, Integrator, PathStats, SceneView, ray_color_49, ray_color_background,
+                        ray_color_nee};
+
+use std::path::Path;
+use std::sync::Mutex;
 use std::time::Instant;
 
 fn main() {
-    let listing_num = 71;
-
-    let rp = RenderParams::new(16.0/6.0, 400, 100);
-
-    match  listing_num {
-        n  if n < 0 => _use_some_funs(),
-        1 => listing_1(),
-        7 => listing_7(),
-        n if n>=9 && n <= 24 => listing_9_24(n),
-        n if n >= 30 && n < 69 => listing_30_68(n, rp),
-        n if n >= 30 && n < 69 => listing_30_68(n, rp),
-        n if n >= 30 && n < 69 => listing_69_(n, rp),
-        69  => {
-            let rp = RenderParams::new(16.0/6.0, 400, 100);
-            listing_69_(listing_num, rp)
-        },
-        70 => {
-            let rp = RenderParams::new(3.0/2.0, 1200, 500);
-            listing_69_(listing_num, rp)
-        },
-        71 => {
-            let rp = RenderParams::new(3.0/2.0, 900, 200);
-            listing_69_(listing_num, rp)
-        }
-        _ =>  panic!("listing_num: {} out of range", listing_num)
-    };
-}
-
+    let cli = cli::parse_args();
 
-struct RenderParams {
-    aspect_ratio: f64,
-    img_width: u32,
-    img_height: u32,
-    samples_per_pixel: i32,
-    depth: i32
-}
-
-impl RenderParams {
-    fn new(aspect_ratio: f64, img_width: u32, samples_per_pixel: i32)-> Self {
-        RenderParams {
-            aspect_ratio,
-            img_width,
-            img_height: ((img_width as f64) / aspect_ratio) as u32,
-            samples_per_pixel,
-            depth: 50
-        }
+    if cli.list_scenes {
+        print!("{}", cli::scenes_help());
+        return
     }
-}
 
+    if let Some(listing_num) = cli.listing {
+        let rp = cli.render_params(16.0/6.0, 400, 100);
+        match listing_num {
+            n  if n < 0 => _use_some_funs(),
+            1 => listing_1(&cli.display()),
+            7 => listing_7(&cli.display()),
+            n if (9..=24).contains(&n) => listing_9_24(n),
+            n if (30..69).contains(&n) => listing_30_68(n, rp),
+            _ => cli::exit_with_error(&format!(
+                "no listing {}; listings 69 and up are scenes now, see --list-scenes", listing_num))
+        };
+        return
+    }
 
-fn listing_69_(listing_num: i32, rp: RenderParams) {
-    let mut rng = rand::thread_rng();
+    if let Some(path) = &cli.scene_file {
+        let loaded = load_scene_file(path).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1)
+        });
+        let rp = cli.file_render_params(&loaded.render);
+        let camera = cli.camera_params(&loaded.camera);
+        let name = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("scene");
+        render_world(&loaded.world, &loaded.lights, cli.integrator(ray_color_nee), &cli.background(&loaded.background),
+                     &camera, rp, &cli.output(name));
+        return
+    }
 
-    let world = match listing_num {
-        69 => four_sphere_world_65(),
-        70 => many_sphere_world_70(&mut rng),
-        71 => marble_v1(&mut rng),
-        _ => panic!("Can't make world for {}", listing_num)
-    };
+    let scene = find_scene(&cli.scene).unwrap_or_else(|| cli::exit_with_error(&format!(
+        "unknown scene `{}`, see --list-scenes", cli.scene)));
+    let rp = cli.scene_render_params(&scene);
+    let camera = cli.camera_params(&scene.camera);
+    let world = (scene.build)(&mut rng_from_seed(rp.seed));
+    let lights = (scene.lights)();
+    render_world(&world, &lights, cli.integrator(scene.ray_color), &cli.background(&scene.background), &camera, rp,
+                 &cli.output(scene.name));
+}
 
 
-    let camera = match listing_num {
-        n if (n == 70)|| (n == 71) => {
-            CameraWithFocus::new(
-                &point3(13., 2., 3.),
-                &point3(0.,0.,0.),
-                vec3_(0.,1.,0.),
-                20.0,
-                rp.aspect_ratio, 0.1, 10.0
-            )
-        },
-        _ => {
-            let lookfrom = point3(3., 3., 2.);
-            let lookat = point3(0., 0., -1.);
-            CameraWithFocus::new(
-                &lookfrom,
-                &lookat,
-                vec3_(0., 1., 0.),
-                20.0,
-                rp.aspect_ratio,
-                2.0,
-                (&lookfrom - &lookat).length())
-        }
+fn render_world(world: &HittableList, lights: &HittableList, integrator: Integrator, background: &Background,
+                camera: &CameraParams, rp: RenderParams, output: &Output) {
+    let world = if output.aovs.contains(&Aov::ObjectId) {
+        hittable_list(world.objects.iter().enumerate()
+                           .map(|(k, object)| ObjectTag::new(object.clone(), k as u32 + 1) as _).collect())
+    } else {
+        hittable_list(world.objects.clone())
     };
+    // a scene file may have nothing but a background
+    let world: SharedHittable = if world.objects.is_empty() { Shared::new(world) } else { BvhNode::new(&world) };
+    let scene = SceneView{world: world.as_ref(), lights, background};
+    let path_stats = Mutex::new(PathStats::default());
+    let camera = camera.camera(rp.aspect_ratio);
+    // the denoiser is guided by the AOVs
+    let with_aovs = !output.aovs.is_empty() || rp.denoise;
 
     let now = Instant::now();
-    let img =
-        ImageBuffer::from_fn(rp.img_width, rp.img_height,
-         |i, j| {
-            let mut pixel_color = color(0., 0., 0.);
-            for _ in 0..rp.samples_per_pixel {
-                let u = (i as f64 + random_unif_1(&mut rng))
-                              / (rp.img_width - 1) as f64;
-                let v = ((rp.img_height - j) as f64 + random_unif_1(&mut rng))
-                              / (rp.img_height - 1) as f64;
-                let ray = camera.get_ray(u, v, &mut rng);
-
-                let ray_color = match listing_num {
-                    n if n <= 70 => ray_color_49(&ray, &mut rng, &world, rp.depth),
-                    n if n >= 71 => ray_color_71(&ray, &mut rng, &world, rp.depth),
-                    _ => panic!("this can't happen")
-                };
-
-                pixel_color += &ray_color;
-            }
-            pixel_color.to_rgb_sampled(rp.samples_per_pixel)
-        });
+    let (img, aovs) = render_scene(&scene, &camera, integrator, &rp, with_aovs, &path_stats);
     let elapsed = now.elapsed();
+    let img = if rp.denoise {
+        let now = Instant::now();
+        let denoised = Denoiser{n_threads: rp.n_threads, ..Default::default()}.denoise(&img, &aovs);
+        println!("Denoised in {:.2?}", now.elapsed());
+        denoised
+    } else {
+        img
+    };
 
     let mps = (rp.img_width * rp.img_height) as f64 / 1.0e6;
-    let outfn = format!("generated_imgs/listing_{}.png", listing_num);
     println!("Elapsed: {:.2?} ({:.0?} ms / megapixel) - writing image to {}",
-             elapsed,  elapsed.as_secs_f64() * 1000.0 /mps, outfn);
+             elapsed,  elapsed.as_secs_f64() * 1000.0 /mps, output.path);
+    if let Some(adaptive) = &rp.adaptive {
+        println!("Samples: {:.1} per pixel on average, between {} and {}",
+                 aovs.total_samples() as f64 / (rp.img_width * rp.img_height) as f64,
+                 adaptive.min_samples.min(rp.samples_per_pixel), rp.samples_per_pixel);
+    }
+    if let Integrator::Iterative{..} = integrator {
+        println!("Paths: {}", path_stats.into_inner().unwrap());
+    }
 
-    //img.save("generated_imgs/listing_9.png").unwrap();
-    img.save(outfn).unwrap();
+    output.save(&img, &aovs).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        std::process::exit(1)
+    });
 
 }
 
@@ -151,10 +155,10 @@ fn listing_30_68(listing_num: i32, rp: RenderParams) {
     // sampling many rays per Pixel
     let world = match listing_num {
         n if n <= 48 => two_sphere_world(),
-        n if n >= 49 && n < 52 => four_sphere_world_50(),
-        n if n >= 52 && n < 55 => four_sphere_world_52(),
-        n if n >= 55 && n < 60 => four_sphere_world_55(),
-        n if n >= 60 && n < 65 => four_sphere_world_60(),
+        n if (49..52).contains(&n) => four_sphere_world_50(),
+        n if (52..55).contains(&n) => four_sphere_world_52(),
+        n if (55..60).contains(&n) => four_sphere_world_55(),
+        n if (60..65).contains(&n) => four_sphere_world_60(),
         n if n >= 65 => four_sphere_world_65(),
         _ => panic!("Can't make world for {}", listing_num)
     };
@@ -171,27 +175,28 @@ fn listing_30_68(listing_num: i32, rp: RenderParams) {
                 20.0, rp.aspect_ratio),
         _ => Camera::default()
     };
+    let no_lights = hittable_list(vec![]);
+    let scene = SceneView{world: &world, lights: &no_lights, background: &Background::Gradient};
 
 
     let now = Instant::now();
-    let mut rng = rand::thread_rng();
     let img =
-        ImageBuffer::from_fn(rp.img_width, rp.img_height,
-         |i, j| {
+        render_tiles(rp.img_width, rp.img_height, rp.seed, rp.n_threads,
+         |i, j, rng| {
             let mut pixel_color = color(0., 0., 0.);
             for _ in 0..rp.samples_per_pixel {
-                let u = (i as f64 + random_unif_1(&mut rng))
+                let u = (i as f64 + random_unif_1(rng))
                               / (rp.img_width - 1) as f64;
-                let v = ((rp.img_height - j) as f64 + random_unif_1(&mut rng))
+                let v = ((rp.img_height - j) as f64 + random_unif_1(rng))
                               / (rp.img_height - 1) as f64;
                 let ray = camera.get_ray(u, v);
 
                 match listing_num {
                    30 => pixel_color += &ray_color_24(&ray, &world),
-                   33 => pixel_color += &ray_color_33(&ray, &mut rng, &world),
-                   36 => pixel_color += &ray_color_36(&ray, &mut rng, &world, rp.depth),
-                   38 => pixel_color += &ray_color_38(&ray, &mut rng, &world, rp.depth),
-                   n if n >= 49  => pixel_color += &ray_color_49(&ray, &mut rng, &world, rp.depth),
+                   33 => pixel_color += &ray_color_33(&ray, rng, &world),
+                   36 => pixel_color += &ray_color_36(&ray, rng, &world, rp.depth),
+                   38 => pixel_color += &ray_color_38(&ray, rng, &world, rp.depth),
+                   n if n >= 49  => pixel_color += &ray_color_49(&ray, rng, &scene, rp.depth),
                    _ => panic!("can't trace rays for listing_num: {}", listing_num)
                 }
 
@@ -211,196 +216,7 @@ fn listing_30_68(listing_num: i32, rp: RenderParams) {
 }
 
 
-fn marble_v1(rng: &mut ThreadRng) -> HittableList {
-    let mut world = hittable_list( &vec![] );
-
-    let glass = Dielectric::new(1.5);
-
-    let rc = 2.0;
-    world.add(Sphere::new(point3(0., 0.,0.), rc, glass));
-
-
-
-    for _i in (Range{start: 0, end: 50}) {
-        let center = (rc - 0.5) * Vec3::rand_in_sphere_1(rng);
-        let albedo = Color::random(rng, 0.5, 1.0);
-        let metal = Metal::new(&albedo, 0.1);
-
-        world.add(Sphere::new(center, 0.15, metal));
-    }
-    world
-}
-
-fn many_sphere_world_70(rng: &mut ThreadRng) -> HittableList {
-    let mut world = hittable_list( &vec![] );
-
-    let ground_material = Lambertian::new(0.5, 0.5, 0.5);
-
-    world.add(Sphere::new(point3(0.,-1000.,0.), 1000.0, ground_material));
-
-    for a in (Range{start: -11, end: 11}) {
-        for b in (Range{start: -11, end: 11}) {
-            let choose_mat = random_unif_1(rng);
-            let center =  point3(
-                (a as f64) + 0.9 * random_unif_1(rng),
-                0.2, (b as f64) + 0.9 * random_unif_1(rng));
-
-            if (&center - point3(4., 0.2, 0.)).length() > 0.9 {
-
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = &Color::random(rng, 0., 1.0)
-                                       * &Color::random(rng, 0., 1.0);
-                    let sphere_material = Lambertian::with_color(albedo);
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random(rng, 0.5, 1.0);
-                    let fuzz = random_unif(rng, 0., 0.5);
-                    let sphere_material = Metal::new(&albedo, fuzz);
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                } else {
-                    // glass
-                    let sphere_material = Dielectric::new(1.5);
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                }
-            }
-        }
-    }
-
-    let material1 = Dielectric::new(1.5);
-    world.add(Sphere::new(point3(0., 1., 0.), 1.0, material1));
-
-    let material2 = Lambertian::new(0.4, 0.2, 0.1);
-    world.add(Sphere::new(point3(-4.0, 1.0, 0.), 1.0, material2));
-
-    let material3 = Metal::new(&point3(0.7, 0.6, 0.5), 0.0);
-    world.add(Sphere::new(point3(4.0, 1.0, 0.0), 1.0, material3));
-
-    world
-}
-
-
-
-fn four_sphere_world_65() -> HittableList {
-
-    four_spheres_given_mats_60(
-        Lambertian::new(0.8, 0.8, 0.0),
-        Lambertian::new(0.1, 0.2, 0.5),
-        Dielectric::new(1.5),
-        Metal::new_rgb(0.8, 0.6, 0.2)
-    )
-}
-
-
-fn four_sphere_world_60() -> HittableList {
-
-    four_spheres_given_mats_60(
-        Lambertian::new(0.8, 0.8, 0.0),
-        Dielectric::new(1.5),
-        Dielectric::new(1.5),
-        Metal::new_rgb(0.8, 0.6, 0.2)
-    )
-}
-
-fn four_sphere_world_55() -> HittableList {
-
-    four_spheres_given_mats(
-        Lambertian::new(0.8, 0.8, 0.0),
-        Dielectric::new(1.5),
-        Dielectric::new(1.5),
-        Metal::new_rgb(0.8, 0.6, 0.2)
-    )
-}
-
-fn four_sphere_world_52() -> HittableList {
-    four_spheres_given_mats(Lambertian::new(0.8, 0.8, 0.0),
-            Lambertian::new(0.7, 0.3, 0.3),
-            Metal::new(&color(0.8, 0.8, 0.8), 0.3),
-            Metal::new(&color(0.8, 0.6, 0.2), 1.0))
-}
-
-
-fn four_sphere_world_50() -> HittableList {
-    let material_ground = Lambertian::new(0.8, 0.8, 0.0);
-    let material_center = Lambertian::new(0.7, 0.3, 0.3);
-    let material_left = Metal::new_rgb(0.8, 0.8, 0.8);
-    let material_right = Metal::new_rgb(0.8, 0.6, 0.2);
-
-    four_spheres_given_mats(material_ground, material_center,
-        material_left, material_right)
-}
-
-
-fn four_spheres_given_mats(mat_ground: Shared<dyn Material>, mat_center: Shared<dyn Material>,
-                           mat_left: Shared<dyn Material>, mat_right: Shared<dyn Material>) -> HittableList {
-    hittable_list(&vec![
-        Sphere::new(point3( 0., -100.5, -1.0), 100.0, mat_ground),
-        Sphere::new(point3( 0.,   0.0, -1.0), 0.5, mat_center),
-        Sphere::new(point3(-1.,   0.0, -1.0), 0.5, mat_left),
-        Sphere::new(point3( 1.,   0.0, -1.0), 0.5, mat_right)
-    ])
-}
-
-fn four_spheres_given_mats_60(mat_ground: Shared<dyn Material>,
-                           mat_center: Shared<dyn Material>,
-                           mat_left: Shared<dyn Material>,
-                           mat_right: Shared<dyn Material>) -> HittableList {
-    hittable_list(&vec![
-        Sphere::new(point3( 0., -100.5, -1.0), 100.0, mat_ground),
-        Sphere::new(point3( 0.,   0.0, -1.0), 0.5, mat_center),
-        Sphere::new(point3(-1.,   0.0, -1.0), 0.5, mat_left.clone()),
-        Sphere::new(point3(-1.,   0.0, -1.0), -0.45, mat_left),
-        Sphere::new(point3( 1.,   0.0, -1.0), 0.5, mat_right)
-    ])
-}
-
-
-
-fn ray_color_71(ray: &Ray, rng: &mut ThreadRng, world: &dyn Hittable, depth: i32) -> Color {
-    // listing 38: true lambertian reflection
-    let mut rec = HitRecord::default();
-    if depth <= 0 { return color(0., 0., 0.)}
-
-    if world.hit(ray, 0.001, INF, &mut rec) {
-        if let Some(s_rec) = rec.material.scatter(&ray, &rec, rng) {
-            &s_rec.attenuation * &ray_color_49(&s_rec.scattered, rng, world, depth - 1)
-        } else {
-            color(0., 0., 0.)
-        }
-
-    } else {
-
-        if ray.dir.z > 0.0 {
-            color(1.0, 1.0, 1.0)
-        } else {
-            color(0., 0., 0.)
-        }
-
-    }
-}
-
-
-fn ray_color_49(ray: &Ray, rng: &mut ThreadRng, world: &dyn Hittable, depth: i32) -> Color {
-    // listing 38: true lambertian reflection
-    let mut rec = HitRecord::default();
-    if depth <= 0 { return color(0., 0., 0.)}
-
-    if world.hit(ray, 0.001, INF, &mut rec) {
-        if let Some(s_rec) = rec.material.scatter(&ray, &rec, rng) {
-            &s_rec.attenuation * &ray_color_49(&s_rec.scattered, rng, world, depth - 1)
-        } else {
-            color(0., 0., 0.)
-        }
-
-    } else {
-        ray_color_background(ray)
-    }
-}
-
-
-
-fn ray_color_38(ray: &Ray, rng: &mut ThreadRng,
+fn ray_color_38(ray: &Ray, rng: &mut RtRng,
     world: &dyn Hittable, depth: i32) -> Color {
     // listing 38: true lambertian reflection
     let mut rec = HitRecord::default();
@@ -416,7 +232,7 @@ fn ray_color_38(ray: &Ray, rng: &mut ThreadRng,
 }
 
 
-fn ray_color_36(ray: &Ray, rng: &mut ThreadRng,
+fn ray_color_36(ray: &Ray, rng: &mut RtRng,
     world: &dyn Hittable, depth: i32) -> Color {
     // listing 36: fixing shadow acne
     let mut rec = HitRecord::default();
@@ -432,7 +248,7 @@ fn ray_color_36(ray: &Ray, rng: &mut ThreadRng,
 }
 
 
-fn ray_color_33(ray: &Ray, rng: &mut ThreadRng,
+fn ray_color_33(ray: &Ray, rng: &mut RtRng,
     world: &dyn Hittable) -> Color {
     // listing 33: with reflection from diffuse materials
     let mut rec = HitRecord{..Default::default()};
@@ -472,7 +288,8 @@ fn listing_9_24(listing_num: i32) {
             let u = i as f64 / (img_width - 1) as f64;
             let v = (img_height - j) as f64 / (img_height - 1) as f64;
             let ray = Ray{origin: origin.clone(),
-                               dir: &lower_left_corner + u * &horizontal + v * &vertical - &origin};
+                               dir: &lower_left_corner + u * &horizontal + v * &vertical - &origin,
+                               time: 0.0, wavelengths: None};
 
             let color = match listing_num {
                 9 => ray_color_background(&ray),
@@ -494,13 +311,6 @@ fn listing_9_24(listing_num: i32) {
 
 }
 
-fn two_sphere_world() -> HittableList {
-    hittable_list(&vec![
-        Sphere::new_cr( vec3_(0., 0., -1.), 0.5),
-        Sphere::new_cr( vec3_(0., -100.5, -1.), 100.0)
-    ])
-}
-
 fn ray_color_24(ray: &Ray, world: &dyn Hittable) -> Color {
     // listing 24
     let mut rec = HitRecord{..Default::default()};
@@ -512,21 +322,27 @@ fn ray_color_24(ray: &Ray, world: &dyn Hittable) -> Color {
     }
 }
 
-fn listing_1() {
+// `display.dither` breaks up the bands the smooth gradients show otherwise
+fn listing_1(display: &DisplaySettings) {
     let image_width = 1024;
     let image_height = 1024;
 
     let img =
         ImageBuffer::from_fn(image_width, image_height,
         |i, j| {
-            let r = (i as f32) / ((image_width - 1) as f32);
-            let g: f32 = (j as f32) / ((image_height -1) as f32);
-            let b = 0.25 as f32;
-
-            let ir = (255.999 * r) as u8;
-            let ig = (255.999 * g) as u8;
-            let ib = (255.999 * b) as u8;
-            image::Rgb( [ir, ig, ib])
+            let r = (i as f64) / ((image_width - 1) as f64);
+            let g = (j as f64) / ((image_height -1) as f64);
+            let b = 0.25;
+
+            if display.dither {
+                image::Rgb([display.quantize(r, i, j, 0), display.quantize(g, i, j, 1),
+                            display.quantize(b, i, j, 2)])
+            } else {
+                let ir = (255.999 * r) as u8;
+                let ig = (255.999 * g) as u8;
+                let ib = (255.999 * b) as u8;
+                image::Rgb( [ir, ig, ib])
+            }
         }
     );
 
@@ -539,34 +355,26 @@ fn listing_1() {
 }
 
 
-fn listing_7() {
+fn listing_7(display: &DisplaySettings) {
     let img_width = 1024;
     let img_height = 1024;
 
     let img =
-        ImageBuffer::from_fn(img_width, img_height,
+        Rgb32FImage::from_fn(img_width, img_height,
         |i, j|{
             vec3_((i as f64) / ((img_width - 1) as f64),
                   (j as f64) / ((img_height - 1) as f64),
-                  0.25).to_rgb()
+                  0.25).to_rgb32_sampled(1)
         }
 
     );
+    let img = display.to_rgb8_image(&img);
     img.save("generated_imgs/listing_7.png").unwrap();
 }
 
 
-#[inline(always)]
-fn ray_color_background(ray: &Ray) -> Color {
-    let unit_dir = ray.dir.unit_vector();
-    let t = 0.5 * (unit_dir.y + 1.0);
-    let rgb_vec = (1.0 - t) * color(1.0, 1.0, 1.0) + t * color(0.5, 0.7, 1.);
-    return rgb_vec
-}
-
-
 fn ray_color_with_sphere(ray: &Ray) -> Color {
-    if hit_sphere_10(&vec3_(0., 0., -1.), 0.5, &ray) {
+    if hit_sphere_10(&vec3_(0., 0., -1.), 0.5, ray) {
         color(1.0, 1.0, 0.5)
     } else {
         ray_color_background(ray)
@@ -575,12 +383,11 @@ fn ray_color_with_sphere(ray: &Ray) -> Color {
 
 fn ray_color_with_shaded_sphere_11(ray: &Ray) -> Color {
     // listing 11
-    let t = hit_sphere_11(&vec3_(0., 0., -1.), 0.5, &ray);
+    let t = hit_sphere_11(&vec3_(0., 0., -1.), 0.5, ray);
 
     if t > 0. {
         let normal = (ray.at(t) - vec3_(0., 0., -1.)).unit_vector();
-        let rgb_vec = 0.5 * color(normal.x, normal.y, normal.z);
-        rgb_vec
+        0.5 * color(normal.x, normal.y, normal.z)
     } else {
         ray_color_background(ray)
     }
@@ -588,12 +395,11 @@ fn ray_color_with_shaded_sphere_11(ray: &Ray) -> Color {
 
 fn ray_color_with_shaded_sphere_12(ray: &Ray) -> Color {
     // listing 12
-    let t = hit_sphere_12(&vec3_(0., 0., -1.), 0.5, &ray);
+    let t = hit_sphere_12(&vec3_(0., 0., -1.), 0.5, ray);
 
     if t > 0. {
         let normal = (ray.at(t) - vec3_(0., 0., -1.)).unit_vector();
-        let rgb_vec = 0.5 * color(normal.x, normal.y, normal.z);
-        rgb_vec
+        0.5 * color(normal.x, normal.y, normal.z)
     } else {
         ray_color_background(ray)
     }
@@ -601,7 +407,7 @@ fn ray_color_with_shaded_sphere_12(ray: &Ray) -> Color {
 
 fn _use_some_funs() {
     // function to use some other functions and avoid warnings `xyz` is never used
-    let mut rng = rand::thread_rng();
+    let mut rng = rng_from_seed(0);
     println!( "{} {} {} {:?} {} {:?}",
              degrees_to_radians(90.0),
              random_unif(&mut rng, 0., 1.0),
@@ -1,35 +1,79 @@
 // listing 41
+use std::f64::consts::PI;
+
+use crate::microfacet::{ConductorBsdf, DielectricBsdf, Ggx, eval_bsdf, fresnel_conductor, fresnel_dielectric,
+                        outward_frame, pow5, refract, scatter_bsdf};
+use crate::pdf::{CosinePdf, Onb, Pdf, SpherePdf};
 use crate::ray::Ray;
 use crate::hittable::HitRecord;
-use crate::rtweekend::{Shared, random_unif_1};
-use crate::vec3::{Vec3, Color, color};
-use rand::rngs::ThreadRng;
+use crate::rtweekend::{Shared, RtRng, random_unif_1};
+use crate::spectrum::Ior;
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::{Vec3, Color, Point3, color, vec3_};
 
 pub struct ScatterRecord {
+    // what the light along `scattered` is multiplied by: the BSDF times the
+    // cosine over the density of `scattered`
     pub attenuation: Color,
-    pub scattered: Ray
+    pub scattered: Ray,
+    // the density `scattered` was drawn from, None for mirror-like materials
+    // whose direction can't be chosen any other way
+    pub pdf: Option<Box<dyn Pdf>>
 }
 
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut ThreadRng) -> Option<ScatterRecord>;
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng) -> Option<ScatterRecord>;
+
+    // "The Rest of Your Life" section 10: with attenuation the BSDF times
+    // the cosine is attenuation * scattering_pdf, for materials with a pdf
+    fn scattering_pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    // the BSDF times the cosine towards `scattered`, what the integrators
+    // weigh light samples and bounces by; the color times scattering_pdf
+    // unless it depends on the directions
+    fn eval_bsdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        self.scattering_pdf(ray_in, hit_record, scattered) * self.albedo(hit_record)
+    }
+
+    // light given off at the hit point, none for anything but lights
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        color(0., 0., 0.)
+    }
+
+    // the surface color at the hit, for the albedo output
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        color(0., 0., 0.)
+    }
+
+    // whether the direction light leaves in depends on its wavelength
+    // (Ray::wavelength), so that spectral paths go on with the hero only
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color
+    albedo: Shared<dyn Texture>
 }
 
 impl Lambertian {
     pub fn new(r: f64, g: f64, b: f64) -> Shared<dyn Material> {
-        return Shared::new( Lambertian {albedo: color(r,g,b)} )
+        Self::with_color(color(r,g,b))
     }
 
     pub fn with_color(color: Vec3) -> Shared<dyn Material> {
-        return Shared::new( Lambertian {albedo: color} )
+        Self::with_texture(SolidColor::new(color))
+    }
+
+    pub fn with_texture(albedo: Shared<dyn Texture>) -> Shared<dyn Material> {
+        Shared::new( Lambertian {albedo} )
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord, rng: &mut ThreadRng)
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng)
         -> Option<ScatterRecord> {
         let scatter_dir0 = &hit_record.normal + Vec3::random_unit_vector(rng);
         // listing 46: guard agains very small scatter_direction
@@ -39,78 +83,97 @@ impl Material for Lambertian {
             scatter_dir0
         };
 
-        let scattered = Ray::new(&hit_record.p, &scatter_direction);
-        return Some(ScatterRecord{attenuation: self.albedo.clone(),
-                                  scattered: scattered})
+        // normal + unit vector is already distributed as cos(theta) / pi
+        let scattered = Ray::with_time(&hit_record.p, &scatter_direction, ray_in.time);
+        Some(ScatterRecord{attenuation: self.albedo.value(hit_record.u, hit_record.v, &hit_record.p),
+                           scattered,
+                           pdf: Some(Box::new(CosinePdf::new(&hit_record.normal)))})
 
     }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = hit_record.normal.dot(&scattered.dir.unit_vector());
+        (cosine / PI).max(0.)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.albedo.value(hit_record.u, hit_record.v, &hit_record.p)
+    }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Shared<dyn Texture>,
     fuzz: f64
 }
 
 impl Metal {
     pub fn new(color: &Color, fuzz: f64) -> Shared<dyn Material> {
+        Self::with_texture(SolidColor::new(color.clone()), fuzz)
+    }
+    pub fn new_rgb(r: f64, g: f64, b: f64) -> Shared<dyn Material> {
+        Self::new(&color(r,g,b), 0.)
+    }
+    pub fn with_texture(albedo: Shared<dyn Texture>, fuzz: f64) -> Shared<dyn Material> {
         Shared::new( Metal {
-            albedo: color.clone(),
+            albedo,
             fuzz: if fuzz < 1.0  { fuzz } else {1.0}
         })
     }
-    pub fn new_rgb(r: f64, g: f64, b: f64) -> Shared<dyn Material> {
-        return Shared::new( Metal {albedo: color(r,g,b), fuzz: 0.} )
-    }
 }
 
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut ThreadRng)
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng)
         -> Option<ScatterRecord> {
             let reflected = ray_in.dir.unit_vector().reflect(&hit_record.normal);
-            let dir = reflected + &(self.fuzz * Vec3::rand_in_sphere_1(rng)); // listing 51
-            let scattered = Ray::new(&hit_record.p, &dir);
+            let dir = reflected + &(self.fuzz * Vec3::random_in_unit_ball(rng)); // listing 51
+            let scattered = Ray::with_time(&hit_record.p, &dir, ray_in.time);
 
             if scattered.dir.dot(&hit_record.normal) > 0. {
-                Some(ScatterRecord{attenuation: self.albedo.clone(),
-                                   scattered: scattered})
+                Some(ScatterRecord{attenuation: self.albedo.value(hit_record.u, hit_record.v, &hit_record.p),
+                                   scattered,
+                                   pdf: None})
             } else {
                 None
             }
 
     }
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.albedo.value(hit_record.u, hit_record.v, &hit_record.p)
+    }
 }
 
 
 pub struct Dielectric {
-    eta: f64  // index_of_refraction
+    ior: Ior
 }
 
 impl Dielectric {
     pub fn new(eta: f64) -> Shared<dyn Material> {
-        Shared::new( Dielectric {eta} )
+        Self::with_ior(Ior::Constant(eta))
+    }
+
+    // glass that splits white light into colors in spectral mode
+    pub fn with_ior(ior: Ior) -> Shared<dyn Material> {
+        Shared::new( Dielectric {ior} )
     }
 
     pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let r0_ = (1.0 - ref_idx) / (1.0 + ref_idx);
         let r0 = r0_*r0_;
-        return r0 + (1.0 - r0) * pow5(1.0 - cosine)
+        r0 + (1.0 - r0) * pow5(1.0 - cosine)
     }
 }
 
-fn pow5(x: f64) -> f64 {
-    let s = x * x;
-    return s * s * x
-}
-
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut ThreadRng)
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut RtRng)
      -> Option<ScatterRecord> {
         let attenuation = color(1.0, 1.0, 1.0);
 
-        let eta_ratio = if rec.front_face { 1.0 / self.eta } else { self.eta };
+        let eta = self.ior.at(r_in.wavelength());
+        let eta_ratio = if rec.front_face { 1.0 / eta } else { eta };
 
         let unit_dir = r_in.dir.unit_vector();
         let cos_theta = (-unit_dir.dot(&rec.normal)).min(1.0);
@@ -128,7 +191,186 @@ impl Material for Dielectric {
 
         Some(ScatterRecord{
                 attenuation,
-                scattered: Ray::new(&rec.p, &direction)
+                scattered: Ray::with_time(&rec.p, &direction, r_in.time),
+                pdf: None
              })
     }
-}
\ No newline at end of file
+    // clear glass lets everything through
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        color(1., 1., 1.)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive()
+    }
+}
+
+// metal as GGX microfacets that reflect by the Fresnel equations of a
+// complex index of refraction eta + i k, given per color channel
+pub struct Conductor {
+    bsdf: ConductorBsdf
+}
+
+// eta and k of the red, green and blue primaries (650, 550 and 450 nm)
+const METALS: [(&str, [f64; 3], [f64; 3]); 3] = [
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("aluminum", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837])
+];
+
+impl Conductor {
+    // `roughness` from 0 (a mirror) to 1
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Shared<dyn Material> {
+        Shared::new(Conductor{bsdf: ConductorBsdf{eta, k, distribution: Ggx::isotropic(roughness)}})
+    }
+
+    // one of METALS by name
+    pub fn metal(name: &str, roughness: f64) -> Option<Shared<dyn Material>> {
+        METALS.iter().find(|(metal, _, _)| *metal == name).map(|(_, eta, k)| {
+            Self::new(color(eta[0], eta[1], eta[2]), color(k[0], k[1], k[2]), roughness)
+        })
+    }
+
+    pub fn metal_names() -> Vec<&'static str> {
+        METALS.iter().map(|(name, _, _)| *name).collect()
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng) -> Option<ScatterRecord> {
+        if self.bsdf.distribution.is_smooth() {
+            let unit_dir = ray_in.dir.unit_vector();
+            let attenuation = fresnel_conductor(-unit_dir.dot(&hit_record.normal), &self.bsdf.eta, &self.bsdf.k);
+            let scattered = Ray::with_time(&hit_record.p, &unit_dir.reflect(&hit_record.normal), ray_in.time);
+            return Some(ScatterRecord{attenuation, scattered, pdf: None})
+        }
+        scatter_bsdf(self.bsdf.clone(), Onb::new(&hit_record.normal), ray_in, hit_record, rng)
+    }
+
+    fn eval_bsdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        eval_bsdf(&self.bsdf, &Onb::new(&hit_record.normal), ray_in, scattered)
+    }
+
+    // the color seen straight on
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        fresnel_conductor(1.0, &self.bsdf.eta, &self.bsdf.k)
+    }
+}
+
+
+// frosted glass: a dielectric whose surface is GGX microfacets, with the
+// exact Fresnel reflectance
+pub struct RoughDielectric {
+    ior: Ior,
+    distribution: Ggx
+}
+
+impl RoughDielectric {
+    // `roughness` from 0 (clear glass) to 1
+    pub fn with_ior(ior: Ior, roughness: f64) -> Shared<dyn Material> {
+        Shared::new(RoughDielectric{ior, distribution: Ggx::isotropic(roughness)})
+    }
+
+    // at the wavelength of `ray_in`
+    fn bsdf(&self, ray_in: &Ray) -> DielectricBsdf {
+        DielectricBsdf{eta: self.ior.at(ray_in.wavelength()), distribution: self.distribution}
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng) -> Option<ScatterRecord> {
+        let (frame, bsdf) = (outward_frame(hit_record), self.bsdf(ray_in));
+        if !self.distribution.is_smooth() {
+            return scatter_bsdf(bsdf, frame, ray_in, hit_record, rng)
+        }
+
+        let wo = frame.to_local(&-ray_in.dir.unit_vector());
+        let normal = vec3_(0., 0., 1.);
+        let (wi, attenuation) = match refract(&wo, &normal, bsdf.eta) {
+            Some((wi, etap)) if random_unif_1(rng) >= fresnel_dielectric(wo.z, bsdf.eta) => {
+                (wi, color(1., 1., 1.) / (etap * etap))
+            },
+            _ => (vec3_(-wo.x, -wo.y, wo.z), color(1., 1., 1.))
+        };
+        Some(ScatterRecord{attenuation, scattered: Ray::with_time(&hit_record.p, &frame.local(&wi), ray_in.time),
+                           pdf: None})
+    }
+
+    fn eval_bsdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        eval_bsdf(&self.bsdf(ray_in), &outward_frame(hit_record), ray_in, scattered)
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        color(1., 1., 1.)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive()
+    }
+}
+
+// area light: emits `emit` on both sides and scatters nothing
+pub struct DiffuseLight {
+    emit: Shared<dyn Texture>
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Shared<dyn Material> {
+        Self::with_texture(SolidColor::new(emit))
+    }
+
+    pub fn with_texture(emit: Shared<dyn Texture>) -> Shared<dyn Material> {
+        Shared::new( DiffuseLight {emit} )
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord, _rng: &mut RtRng)
+        -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+    // the light's color, as bright as a white surface at most
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        let emit = self.emit.value(hit_record.u, hit_record.v, &hit_record.p);
+        color(emit.x.min(1.), emit.y.min(1.), emit.z.min(1.))
+    }
+}
+
+
+// phase function of fog and smoke, "The Next Week" section 9: scatters
+// the same amount in every direction
+pub struct Isotropic {
+    albedo: Shared<dyn Texture>
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Shared<dyn Material> {
+        Self::with_texture(SolidColor::new(albedo))
+    }
+
+    pub fn with_texture(albedo: Shared<dyn Texture>) -> Shared<dyn Material> {
+        Shared::new( Isotropic {albedo} )
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng)
+        -> Option<ScatterRecord> {
+        Some(ScatterRecord{
+            attenuation: self.albedo.value(hit_record.u, hit_record.v, &hit_record.p),
+            scattered: Ray::with_time(&hit_record.p, &Vec3::random_unit_vector(rng), ray_in.time),
+            pdf: Some(Box::new(SpherePdf))
+        })
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.albedo.value(hit_record.u, hit_record.v, &hit_record.p)
+    }
+}
@@ -0,0 +1,130 @@
+// participating media, "The Next Week" section 9
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::{INF, Shared, hash_unif};
+use crate::vec3::vec3_;
+
+// fog of constant density filling a closed `boundary`; rays scatter at a
+// random distance inside it, by `phase_function` (normally Isotropic)
+pub struct ConstantMedium {
+    boundary: SharedHittable,
+    neg_inv_density: f64,
+    phase_function: Shared<dyn Material>
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: SharedHittable, density: f64,
+               phase_function: Shared<dyn Material>) -> Shared<ConstantMedium> {
+        Shared::new(ConstantMedium{boundary, neg_inv_density: -1.0 / density, phase_function})
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Free flight distance, exponentially distributed. hit() has no
+        // generator, so the uniform comes from the ray itself: the same ray
+        // always stops at the same place, whoever asks.
+        let u = hash_unif(&[ray.origin.x, ray.origin.y, ray.origin.z,
+                            ray.dir.x, ray.dir.y, ray.dir.z, ray.time]);
+        let ray_length = ray.dir.length();
+        let mut distance_left = self.neg_inv_density * u.ln();
+
+        // walk the (entry, exit) pairs along the whole line, not only the first
+        // one, so that rays starting inside (after a scattering) and boundaries
+        // entered more than once are handled
+        let mut rec1 = HitRecord::default();
+        let mut rec2 = HitRecord::default();
+        let mut t_from = -INF;
+        loop {
+            if !self.boundary.hit(ray, t_from, INF, &mut rec1) {
+                return false
+            }
+            if !self.boundary.hit(ray, rec1.t + 1e-4, INF, &mut rec2) {
+                return false
+            }
+
+            let enter = rec1.t.max(t_min);
+            let exit = rec2.t.min(t_max);
+            if enter < exit {
+                let inside = (exit - enter) * ray_length;
+                if distance_left < inside {
+                    rec.t = enter + distance_left / ray_length;
+                    rec.p = ray.at(rec.t);
+                    // arbitrary, a medium has no surface
+                    rec.normal = vec3_(1., 0., 0.);
+                    rec.front_face = true;
+                    (rec.u, rec.v) = (0., 0.);
+                    rec.tangent = vec3_(0., 0., 0.);
+                    rec.material = self.phase_function.clone();
+                    return true
+                }
+                distance_left -= inside;
+            }
+            if rec2.t >= t_max {
+                return false
+            }
+            t_from = rec2.t + 1e-4;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::hittable_list;
+    use crate::integrator::{Background, SceneView, ray_color_49};
+    use crate::material::Isotropic;
+    use crate::rtweekend::{random_unif, rng_from_seed};
+    use crate::sphere::Sphere;
+    use crate::vec3::{color, point3};
+
+    // fraction of light getting through along the x axis: fully absorbing fog
+    // in front of a white background
+    fn transmittance(medium: &dyn Hittable) -> f64 {
+        let mut rng = rng_from_seed(1);
+        let white = Background::Solid(color(1., 1., 1.));
+        let no_lights = hittable_list(vec![]);
+        let scene = SceneView{world: medium, lights: &no_lights, background: &white};
+        let n = 20000;
+        let total: f64 = (0..n).map(|_| {
+            // rays spread around the axis by less than a thousandth
+            let origin = point3(-10., random_unif(&mut rng, -1e-3, 1e-3), random_unif(&mut rng, -1e-3, 1e-3));
+            let ray = Ray::new(&origin, &vec3_(1., 0., 0.));
+            ray_color_49(&ray, &mut rng, &scene, 50).x
+        }).sum();
+        total / n as f64
+    }
+
+    #[test]
+    fn test_transmittance_is_beer_lambert() {
+        let black = Isotropic::new(color(0., 0., 0.));
+        let ball = Sphere::new(point3(0., 0., 0.), 1.0, black.clone());
+        let fog = ConstantMedium::new(ball, 0.5, black.clone());
+        let expected = (-0.5f64 * 2.0).exp();
+        let got = transmittance(fog.as_ref());
+        assert!((got - expected).abs() < 0.01, "{} vs {}", got, expected);
+
+        // two balls in one boundary: entered and left twice, 4 units of fog in total
+        let balls = hittable_list(vec![
+            Sphere::new(point3(0., 0., 0.), 1.0, black.clone()),
+            Sphere::new(point3(3., 0., 0.), 1.0, black.clone())
+        ]);
+        let fog = ConstantMedium::new(Shared::new(balls), 0.5, black);
+        let expected = (-0.5f64 * 4.0).exp();
+        let got = transmittance(fog.as_ref());
+        assert!((got - expected).abs() < 0.01, "{} vs {}", got, expected);
+
+        // white fog loses nothing: paths scattered inside must find their way out
+        let white = Isotropic::new(color(1., 1., 1.));
+        let fog = ConstantMedium::new(Sphere::new(point3(0., 0., 0.), 1.0, white.clone()), 0.5, white);
+        let got = transmittance(fog.as_ref());
+        assert!((got - 1.0).abs() < 1e-3, "{}", got);
+    }
+}
@@ -0,0 +1,411 @@
+// rough surfaces as many tiny mirrors: the GGX (Trowbridge-Reitz)
+// distribution of Walter et al., "Microfacet Models for Refraction through
+// Rough Surfaces" (2007), with Smith masking-shadowing, and sampling of the
+// normals visible from the incoming direction (Heitz, "Sampling the GGX
+// Distribution of Visible Normals", 2018). Directions are in a local frame
+// whose +z is the surface normal, `wo` towards where the light goes and
+// `wi` towards where it comes from.
+use std::f64::consts::PI;
+
+use crate::hittable::HitRecord;
+use crate::material::ScatterRecord;
+use crate::pdf::{Onb, Pdf};
+use crate::ray::Ray;
+use crate::rtweekend::{RtRng, random_unif_1};
+use crate::vec3::{Color, Vec3, color, vec3_};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64
+}
+
+impl Ggx {
+    // the x and y axes of the local frame can be rougher than each other
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Ggx{alpha_x: alpha_x.max(1e-4), alpha_y: alpha_y.max(1e-4)}
+    }
+
+    // roughness from 0 (a mirror) to 1, squared to look about linear
+    pub fn isotropic(roughness: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        Self::new(alpha, alpha)
+    }
+
+    // so smooth that only the mirror direction counts
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    // density of microfacet normals, per unit projected area
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z <= 0.0 {
+            return 0.0
+        }
+        let e = (wm.x / self.alpha_x).powi(2) + (wm.y / self.alpha_y).powi(2) + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY
+        }
+        let alpha2_tan2 = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        ((1.0 + alpha2_tan2).sqrt() - 1.0) / 2.0
+    }
+
+    // part of the microfacets seen from `w`
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // part seen from both `wo` and `wi`
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of the normals visible from `w`, what sample_visible draws
+    pub fn visible_d(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        self.g1(w) / w.z.abs() * self.d(wm) * (w.dot(wm) * w.z.signum()).max(0.0)
+    }
+
+    // a microfacet normal seen from `w`, from either side
+    pub fn sample_visible(&self, w: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // stretch to the hemisphere of a roughness 1 surface
+        let wh = vec3_(self.alpha_x * w.x, self.alpha_y * w.y, w.z).unit_vector();
+        let wh = if wh.z < 0.0 { -wh } else { wh };
+        let t1 = if wh.z < 0.99999 { vec3_(0., 0., 1.).cross(&wh).unit_vector() } else { vec3_(1., 0., 0.) };
+        let t2 = wh.cross(&t1);
+
+        // a point on the disk, squeezed to the part of it that is visible
+        let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+        let (px, py) = (r * phi.cos(), r * phi.sin());
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        let py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+        let nh = px * &t1 + py * &t2 + pz * &wh;
+        vec3_(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).unit_vector()
+    }
+}
+
+// reflectance of the boundary to a medium `eta` times denser than the
+// outside, at `cos_i` from its outward normal (negative from inside)
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 { (-cos_i, 1.0 / eta) } else { (cos_i, eta) };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// reflectance of a metal with the complex index of refraction eta + i k,
+// per color channel
+pub fn fresnel_conductor(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let channel = |eta: f64, k: f64| {
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let r_s = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let r_p = r_s * (t3 - t4) / (t3 + t4);
+        (r_s + r_p) / 2.0
+    };
+    color(channel(eta.x, k.x), channel(eta.y, k.y), channel(eta.z, k.z))
+}
+
+// x^5, for Schlick's Fresnel approximation
+pub fn pow5(x: f64) -> f64 {
+    let s = x * x;
+    s * s * x
+}
+
+// `w` mirrored about `n`
+pub fn reflect(w: &Vec3, n: &Vec3) -> Vec3 {
+    -w + 2.0 * w.dot(n) * n
+}
+
+// `w` refracted through a surface with normal `n` into a medium `eta` times
+// denser (from either side), and the ratio of the indices it went through;
+// None on total internal reflection
+pub fn refract(w: &Vec3, n: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let cos_i = n.dot(w);
+    let (cos_i, eta, n) = if cos_i < 0.0 { (-cos_i, 1.0 / eta, -n) } else { (cos_i, eta, n.clone()) };
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((-w / eta + (cos_i / eta - cos_t) * &n, eta))
+}
+
+
+// a BSDF in the local frame
+pub trait LocalBsdf: Send + Sync {
+    // a direction for `wi`, None if the sample scatters nothing
+    fn sample(&self, wo: &Vec3, rng: &mut RtRng) -> Option<Vec3>;
+    // density of `sample`, per unit solid angle
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64;
+    // the BSDF times |cos| of `wi`
+    fn f_cos(&self, wo: &Vec3, wi: &Vec3) -> Color;
+}
+
+// the directions a LocalBsdf samples, for the integrators
+pub struct BsdfPdf<B> {
+    frame: Onb,
+    wo: Vec3,
+    bsdf: B
+}
+
+impl<B: LocalBsdf> Pdf for BsdfPdf<B> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.bsdf.pdf(&self.wo, &self.frame.to_local(&direction.unit_vector()))
+    }
+
+    fn generate(&self, rng: &mut RtRng) -> Vec3 {
+        // a failed sample becomes a grazing direction, which scatters nothing
+        self.frame.local(&self.bsdf.sample(&self.wo, rng).unwrap_or(vec3_(1., 0., 0.)))
+    }
+}
+
+// the local frame at a hit: +z is outwards, whichever side the ray comes
+// from, and +x along the surface's tangent
+pub fn outward_frame(rec: &HitRecord) -> Onb {
+    Onb::with_tangent(&if rec.front_face { rec.normal.clone() } else { -&rec.normal }, &rec.tangent)
+}
+
+// Material::scatter of a material made of `bsdf` around the normal of `frame`
+pub fn scatter_bsdf<B: LocalBsdf + 'static>(bsdf: B, frame: Onb, ray_in: &Ray, rec: &HitRecord, rng: &mut RtRng)
+    -> Option<ScatterRecord> {
+    let wo = frame.to_local(&-ray_in.dir.unit_vector());
+    let wi = bsdf.sample(&wo, rng)?;
+    let pdf = bsdf.pdf(&wo, &wi);
+    if pdf <= 0.0 {
+        return None
+    }
+    let attenuation = &bsdf.f_cos(&wo, &wi) / pdf;
+    let scattered = Ray::with_time(&rec.p, &frame.local(&wi), ray_in.time);
+    Some(ScatterRecord{attenuation, scattered, pdf: Some(Box::new(BsdfPdf{frame, wo, bsdf}))})
+}
+
+// Material::eval_bsdf of the same
+pub fn eval_bsdf<B: LocalBsdf>(bsdf: &B, frame: &Onb, ray_in: &Ray, scattered: &Ray) -> Color {
+    let wo = frame.to_local(&-ray_in.dir.unit_vector());
+    bsdf.f_cos(&wo, &frame.to_local(&scattered.dir.unit_vector()))
+}
+
+
+// metal: microfacet mirrors that reflect by the Fresnel equations of a
+// complex index of refraction, on the side of +z only
+#[derive(Debug, Clone)]
+pub struct ConductorBsdf {
+    pub eta: Color,
+    pub k: Color,
+    pub distribution: Ggx
+}
+
+impl LocalBsdf for ConductorBsdf {
+    fn sample(&self, wo: &Vec3, rng: &mut RtRng) -> Option<Vec3> {
+        if wo.z <= 0.0 {
+            return None
+        }
+        let (u1, u2) = rng.unif_2d();
+        let wi = reflect(wo, &self.distribution.sample_visible(wo, u1, u2));
+        // reflected into the surface: light that would bounce between
+        // microfacets is lost
+        (wi.z > 0.0).then_some(wi)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0
+        }
+        let wm = (wo + wi).unit_vector();
+        self.distribution.visible_d(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    fn f_cos(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return color(0., 0., 0.)
+        }
+        let wm = (wo + wi).unit_vector();
+        let f = self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z);
+        f * fresnel_conductor(wo.dot(&wm), &self.eta, &self.k)
+    }
+}
+
+// the boundary of glass or water seen as rough: +z is outwards, towards
+// the medium with index 1, `eta` is the index inside
+#[derive(Debug, Clone)]
+pub struct DielectricBsdf {
+    pub eta: f64,
+    pub distribution: Ggx
+}
+
+impl DielectricBsdf {
+    // the microfacet normal between `wo` and `wi`, facing +z, and the ratio
+    // of indices from the side of `wo` to that of `wi`; None if no
+    // microfacet seen from both sides turns one into the other
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, f64)> {
+        let reflect = wo.z * wi.z > 0.0;
+        let etap = if reflect { 1.0 } else if wo.z > 0.0 { self.eta } else { 1.0 / self.eta };
+        let wm = etap * wi + wo;
+        if wo.z == 0.0 || wi.z == 0.0 || wm.length_squared() == 0.0 {
+            return None
+        }
+        let wm = wm.unit_vector();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+        // back facing microfacets
+        if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+            return None
+        }
+        Some((wm, etap))
+    }
+}
+
+impl LocalBsdf for DielectricBsdf {
+    fn sample(&self, wo: &Vec3, rng: &mut RtRng) -> Option<Vec3> {
+        if wo.z == 0.0 {
+            return None
+        }
+        let (u1, u2) = rng.unif_2d();
+        let wm = self.distribution.sample_visible(wo, u1, u2);
+        // reflected with probability F: always on total internal reflection
+        if random_unif_1(rng) < fresnel_dielectric(wo.dot(&wm), self.eta) {
+            let wi = reflect(wo, &wm);
+            (wo.z * wi.z > 0.0).then_some(wi)
+        } else {
+            let (wi, _) = refract(wo, &wm, self.eta)?;
+            (wo.z * wi.z < 0.0).then_some(wi)
+        }
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let Some((wm, etap)) = self.half_vector(wo, wi) else { return 0.0 };
+        let reflectance = fresnel_dielectric(wo.dot(&wm), self.eta);
+        let visible = self.distribution.visible_d(wo, &wm);
+        if etap == 1.0 && wo.z * wi.z > 0.0 {
+            visible / (4.0 * wo.dot(&wm).abs()) * reflectance
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+            visible * wi.dot(&wm).abs() / denom * (1.0 - reflectance)
+        }
+    }
+
+    fn f_cos(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let Some((wm, etap)) = self.half_vector(wo, wi) else { return color(0., 0., 0.) };
+        let reflectance = fresnel_dielectric(wo.dot(&wm), self.eta);
+        let dg = self.distribution.d(&wm) * self.distribution.g(wo, wi);
+        let f_cos = if wo.z * wi.z > 0.0 {
+            dg * reflectance / (4.0 * wo.z.abs())
+        } else {
+            let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2) * wo.z.abs();
+            // radiance is squeezed into the narrower cone of the denser side
+            dg * (1.0 - reflectance) * (wi.dot(&wm) * wo.dot(&wm)).abs() / denom / (etap * etap)
+        };
+        color(f_cos, f_cos, f_cos)
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::rtweekend::rng_from_seed;
+
+    // in the local frame, `phi_deg` from +x towards +y
+    pub(crate) fn direction(theta_deg: f64, phi_deg: f64) -> Vec3 {
+        let (theta, phi) = (theta_deg.to_radians(), phi_deg.to_radians());
+        vec3_(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos())
+    }
+
+    // the visible normals seen from any direction cover the projected area:
+    // integral of G1(w) max(0, w.wm) D(wm) dwm / cos(theta) = 1
+    #[test]
+    fn test_visible_normals_are_normalized() {
+        let (n_theta, n_phi) = (4000, 128);
+        for ggx in [Ggx::isotropic(0.3), Ggx::isotropic(0.8), Ggx::new(0.1, 0.5)] {
+            for w in [direction(0.0, 0.0), direction(50.0, 30.0), direction(80.0, 200.0)] {
+                // midpoint rule over the upper hemisphere
+                let (d_theta, d_phi) = (PI / 2.0 / n_theta as f64, 2.0 * PI / n_phi as f64);
+                let integral: f64 = (0..n_theta).flat_map(|i| (0..n_phi).map(move |j| (i, j))).map(|(i, j)| {
+                    let theta = (i as f64 + 0.5) * d_theta;
+                    let wm = direction(theta.to_degrees(), ((j as f64 + 0.5) * d_phi).to_degrees());
+                    ggx.visible_d(&w, &wm) * theta.sin() * d_theta * d_phi
+                }).sum();
+                assert!((integral - 1.0).abs() < 1e-3, "{:?} from {:?}: {}", ggx, w, integral);
+            }
+        }
+    }
+
+    // the samples follow the pdf, and E[f_cos / pdf] over them is the
+    // albedo, which light lost between microfacets keeps below 1
+    fn check_bsdf(bsdf: &dyn LocalBsdf, wo: &Vec3, rng: &mut RtRng) -> f64 {
+        let n = 100_000;
+        let mut albedo = 0.0;
+        let mut samples = 0;
+        for _ in 0..n {
+            if let Some(wi) = bsdf.sample(wo, rng) {
+                let pdf = bsdf.pdf(wo, &wi);
+                assert!(pdf > 0.0, "{:?} -> {:?}", wo, wi);
+                albedo += bsdf.f_cos(wo, &wi).y / pdf;
+                samples += 1;
+            }
+        }
+        // the pdf integrates to the part of the samples that succeed
+        let integral = (0..n).map(|_| bsdf.pdf(wo, &Vec3::random_unit_vector(rng)) * 4.0 * PI)
+            .sum::<f64>() / n as f64;
+        let succeeded = samples as f64 / n as f64;
+        assert!((integral - succeeded).abs() < 0.05, "{:?}: {} vs {}", wo, integral, succeeded);
+        albedo / n as f64
+    }
+
+    #[test]
+    fn test_conductor() {
+        let mut rng = rng_from_seed(4);
+        let white = ConductorBsdf{eta: color(0., 0., 0.), k: color(1e9, 1e9, 1e9), distribution: Ggx::isotropic(0.5)};
+        assert!((fresnel_conductor(0.7, &white.eta, &white.k).x - 1.0).abs() < 1e-6);
+        for wo in [direction(10.0, 0.0), direction(60.0, 90.0)] {
+            let albedo = check_bsdf(&white, &wo, &mut rng);
+            // a perfect reflector only loses what bounces more than once
+            assert!(albedo > 0.85 && albedo <= 1.01, "{:?}: {}", wo, albedo);
+        }
+
+        // gold is yellow, and every metal turns white at grazing angles
+        let gold = fresnel_conductor(1.0, &color(0.143, 0.374, 1.442), &color(3.983, 2.385, 1.603));
+        assert!(gold.x > 0.9 && gold.z < 0.5, "{:?}", gold);
+        let grazing = fresnel_conductor(0.01, &color(0.143, 0.374, 1.442), &color(3.983, 2.385, 1.603));
+        assert!(grazing.z > 0.9, "{:?}", grazing);
+    }
+
+    #[test]
+    fn test_dielectric() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel_dielectric(-0.3, 1.5), 1.0);
+        let mut rng = rng_from_seed(5);
+        let glass = DielectricBsdf{eta: 1.5, distribution: Ggx::isotropic(0.4)};
+        for wo in [direction(20.0, 0.0), direction(70.0, 45.0), direction(160.0, 0.0)] {
+            let albedo = check_bsdf(&glass, &wo, &mut rng);
+            // radiance into the glass is squeezed by 1 / 1.5^2, out of it spread
+            let expected = if wo.z > 0.0 { 1.0 / 2.25 } else { 2.25 };
+            assert!(albedo < 1.0f64.max(expected) * 1.01 && albedo > 0.3 * expected, "{:?}: {}", wo, albedo);
+        }
+
+        // reciprocity of radiance BSDFs: f(wo, wi) / eta_o^2 = f(wi, wo) / eta_i^2
+        let index = |w: &Vec3| if w.z > 0.0 { 1.0f64 } else { 1.5 };
+        for (wo, wi) in [(direction(30.0, 0.0), direction(150.0, 170.0)), (direction(40.0, 10.0), direction(20.0, 200.0))] {
+            let (f, b) = (glass.f_cos(&wo, &wi).x / wi.z.abs(), glass.f_cos(&wi, &wo).x / wo.z.abs());
+            assert!(f > 0.0 && (f / index(&wo).powi(2) - b / index(&wi).powi(2)).abs() < 1e-9, "{} vs {}", f, b);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// Wavefront OBJ meshes: `v`, `vt`, `vn` and `f` records. Polygons are split
+// into triangle fans, `usemtl` picks the material for the faces that follow.
+// Anything else (groups, smoothing, mtllib, ...) is ignored.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+
+use crate::hittable::{HittableList, hittable_list};
+use crate::material::Material;
+use crate::rtweekend::Shared;
+use crate::triangle::{MeshFace, Triangle, TriangleMesh};
+use crate::vec3::vec3_;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(String, std::io::Error),
+    // file, line number, what was wrong
+    Parse(String, usize, String)
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {}", path, err),
+            ObjError::Parse(path, line, msg) => write!(f, "{}, line {}: {}", path, line, msg)
+        }
+    }
+}
+
+// `materials` maps `usemtl` names to materials; faces before any `usemtl`, or
+// whose material isn't in the map, get `default_material`
+pub fn load_obj(path: &str, materials: &HashMap<String, Shared<dyn Material>>,
+                default_material: &Shared<dyn Material>) -> Result<HittableList, ObjError> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| ObjError::Io(path.to_string(), err))?;
+    parse_obj(&text, path, materials, default_material)
+}
+
+// one corner of an `f` record, as 0-based indices
+struct Corner {
+    v: usize,
+    uv: Option<usize>,
+    n: Option<usize>
+}
+
+pub fn parse_obj(text: &str, origin: &str, materials: &HashMap<String, Shared<dyn Material>>,
+                 default_material: &Shared<dyn Material>) -> Result<HittableList, ObjError> {
+    let mut mesh = TriangleMesh::default();
+    let mut faces: Vec<(MeshFace, Shared<dyn Material>)> = vec![];
+    let mut material = default_material.clone();
+
+    for (k, line) in text.lines().enumerate() {
+        let err = |msg: String| ObjError::Parse(origin.to_string(), k + 1, msg);
+        let line = line.split('#').next().unwrap().trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" | "vn" => {
+                let xyz = parse_floats(&args, 3).map_err(err)?;
+                let vec = vec3_(xyz[0], xyz[1], xyz[2]);
+                if keyword == "v" { mesh.positions.push(vec) } else { mesh.normals.push(vec) }
+            },
+            "vt" => {
+                let uv = parse_floats(&args, 2).map_err(err)?;
+                mesh.uvs.push((uv[0], uv[1]));
+            },
+            "f" => {
+                if args.len() < 3 {
+                    return Err(err(format!("a face needs at least 3 vertices, got {}", args.len())))
+                }
+                let corners = args.iter()
+                    .map(|arg| parse_corner(arg, &mesh))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(err)?;
+                for k in 1..corners.len() - 1 {
+                    let tri = [&corners[0], &corners[k], &corners[k + 1]];
+                    let face = MeshFace{
+                        v: [tri[0].v, tri[1].v, tri[2].v],
+                        n: match (tri[0].n, tri[1].n, tri[2].n) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None
+                        },
+                        uv: match (tri[0].uv, tri[1].uv, tri[2].uv) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None
+                        }
+                    };
+                    faces.push((face, material.clone()));
+                }
+            },
+            "usemtl" => {
+                let name = args.first().ok_or_else(|| err(String::from("usemtl without a name")))?;
+                material = materials.get(*name).unwrap_or(default_material).clone();
+            },
+            _ => {}
+        }
+    }
+
+    let mesh = Shared::new(mesh);
+    let triangles = faces.into_iter()
+        .map(|(face, material)| Triangle::in_mesh(mesh.clone(), face, material) as _)
+        .collect();
+    Ok(hittable_list(triangles))
+}
+
+fn parse_floats(args: &[&str], count: usize) -> Result<Vec<f64>, String> {
+    if args.len() < count {
+        return Err(format!("expected {} numbers, got {}", count, args.len()))
+    }
+    args[..count].iter()
+        .map(|arg| arg.parse::<f64>().map_err(|_| format!("`{}` is not a number", arg)))
+        .collect()
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_corner(arg: &str, mesh: &TriangleMesh) -> Result<Corner, String> {
+    let mut parts = arg.split('/');
+    let v = parse_index(parts.next().unwrap(), mesh.positions.len(), "vertex")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(idx) => Some(parse_index(idx, mesh.uvs.len(), "texture coordinate")?)
+    };
+    let n = match parts.next() {
+        Some("") | None => None,
+        Some(idx) => Some(parse_index(idx, mesh.normals.len(), "normal")?)
+    };
+    Ok(Corner{v, uv, n})
+}
+
+// OBJ indices start at 1, negative ones count back from the last record so far
+fn parse_index(idx: &str, len: usize, what: &str) -> Result<usize, String> {
+    let i: i64 = idx.parse().map_err(|_| format!("`{}` is not a {} index", idx, what))?;
+    let resolved = if i > 0 { i - 1 } else { len as i64 + i };
+    if i == 0 || resolved < 0 || resolved >= len as i64 {
+        Err(format!("{} index {} out of range, {} defined so far", what, i, len))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::point3;
+
+    const QUAD: &str = "
+        # unit square in the z = -1 plane, as one polygon
+        v 0 0 -1
+        v 1 0 -1
+        v 1 1 -1
+        v 0 1 -1
+        vt 0 0
+        vt 1 0
+        vt 1 1
+        vt 0 1
+        vn 0 0 1
+        usemtl red
+        f 1/1/1 2/2/1 3/3/1 -1/-1/-1
+    ";
+
+    #[test]
+    fn test_polygon_is_fan_triangulated() {
+        let red = Lambertian::new(1., 0., 0.);
+        let materials = HashMap::from([(String::from("red"), red.clone())]);
+        let mesh = parse_obj(QUAD, "quad.obj", &materials, &Lambertian::new(0.5, 0.5, 0.5)).unwrap();
+        assert_eq!(mesh.objects.len(), 2);
+
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(&point3(0.25, 0.75, 0.), &vec3_(0., 0., -1.));
+        assert!(mesh.hit(&ray, 0.001, 10.0, &mut rec));
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+        // u grows along x in the texture coordinates as in space
+        assert!((&rec.tangent - &vec3_(1., 0., 0.)).length() < 1e-12, "{:?}", rec.tangent);
+        assert!(Shared::ptr_eq(&rec.material, &red));
+    }
+
+    #[test]
+    fn test_bad_index_reports_line() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n";
+        let default = Lambertian::new(0.5, 0.5, 0.5);
+        match parse_obj(text, "bad.obj", &HashMap::new(), &default) {
+            Err(err) => assert_eq!(err.to_string(),
+                                   "bad.obj, line 4: vertex index 4 out of range, 3 defined so far"),
+            Ok(_) => panic!("expected an error")
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// writing the float framebuffer: linear HDR formats keep everything the
+// renderer computed, 8-bit formats go through the display settings
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::Rgb32FImage;
+use image::codecs::hdr::HdrEncoder;
+
+use crate::aov::{Aov, AovBuffers, aov_path, write_layered_exr};
+use crate::display::DisplaySettings;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    // OpenEXR, 32-bit float RGB
+    Exr,
+    // Radiance RGBE
+    Hdr,
+    // Portable Float Map
+    Pfm
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &str) -> Option<OutputFormat> {
+        let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Exr => "exr",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm"
+        }
+    }
+}
+
+// where a render goes and how it's encoded
+pub struct Output {
+    pub path: String,
+    pub format: OutputFormat,
+    // only for the 8-bit formats
+    pub display: DisplaySettings,
+    // layers of the EXR file, files of their own for other formats
+    pub aovs: Vec<Aov>,
+    // files of their own for EXR too
+    pub aov_separate: bool
+}
+
+impl Output {
+    // `aovs` holds the buffers for `self.aovs`, it isn't read if there are none
+    pub fn save(&self, img: &Rgb32FImage, aovs: &AovBuffers) -> Result<(), OutputError> {
+        if self.aovs.is_empty() {
+            return save_image(img, &self.path, self.format, &self.display)
+        }
+        if self.format == OutputFormat::Exr && !self.aov_separate {
+            return write_layered_exr(&self.path, img, aovs, &self.aovs)
+                .map_err(|err| OutputError::Exr(self.path.clone(), err))
+        }
+        save_image(img, &self.path, self.format, &self.display)?;
+        for aov in &self.aovs {
+            let path = aov_path(&self.path, *aov);
+            match self.format {
+                OutputFormat::Png | OutputFormat::Jpeg => aovs.preview(*aov).save(&path)
+                    .map_err(|err| OutputError::Image(path.clone(), err))?,
+                _ => save_image(&aovs.image(*aov), &path, self.format, &self.display)?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    Io(String, io::Error),
+    Image(String, image::ImageError),
+    Exr(String, exr::error::Error)
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputError::Io(path, err) => write!(f, "can't write {}: {}", path, err),
+            OutputError::Image(path, err) => write!(f, "can't write {}: {}", path, err),
+            OutputError::Exr(path, err) => write!(f, "can't write {}: {}", path, err)
+        }
+    }
+}
+
+pub fn save_image(img: &Rgb32FImage, path: &str, format: OutputFormat, display: &DisplaySettings)
+    -> Result<(), OutputError> {
+    let image_err = |err| OutputError::Image(path.to_string(), err);
+    match format {
+        OutputFormat::Png => display.to_rgb8_image(img).save_with_format(path, image::ImageFormat::Png).map_err(image_err),
+        OutputFormat::Jpeg => display.to_rgb8_image(img).save_with_format(path, image::ImageFormat::Jpeg).map_err(image_err),
+        OutputFormat::Exr => img.save_with_format(path, image::ImageFormat::OpenExr).map_err(image_err),
+        OutputFormat::Hdr => {
+            let file = File::create(path).map_err(|err| OutputError::Io(path.to_string(), err))?;
+            HdrEncoder::new(BufWriter::new(file))
+                .encode(img.pixels().copied().collect::<Vec<_>>().as_slice(),
+                        img.width() as usize, img.height() as usize)
+                .map_err(image_err)
+        },
+        OutputFormat::Pfm => {
+            let file = File::create(path).map_err(|err| OutputError::Io(path.to_string(), err))?;
+            write_pfm(img, &mut BufWriter::new(file)).map_err(|err| OutputError::Io(path.to_string(), err))
+        }
+    }
+}
+
+// "PF" for color, then width and height, then the scale whose sign gives
+// the byte order (negative: little endian), then rows from the bottom up
+pub fn write_pfm<W: Write>(img: &Rgb32FImage, w: &mut W) -> io::Result<()> {
+    write!(w, "PF\n{} {}\n-1.0\n", img.width(), img.height())?;
+    for j in (0..img.height()).rev() {
+        for i in 0..img.width() {
+            for c in img.get_pixel(i, j).0 {
+                w.write_all(&c.to_le_bytes())?;
+            }
+        }
+    }
+    w.flush()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+    use image::codecs::hdr::HdrDecoder;
+
+    fn gradient() -> Rgb32FImage {
+        // values well above 1, which 8 bits can't hold
+        Rgb32FImage::from_fn(5, 3, |i, j| Rgb([i as f32 * 10.0, j as f32 + 0.25, 0.5]))
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(OutputFormat::from_path("out/a.EXR"), Some(OutputFormat::Exr));
+        assert_eq!(OutputFormat::from_path("a.jpeg"), Some(OutputFormat::Jpeg));
+        assert_eq!(OutputFormat::from_path("a.tiff"), None);
+        assert_eq!(OutputFormat::from_path("a"), None);
+    }
+
+    #[test]
+    fn test_pfm_layout() {
+        let img = gradient();
+        let mut bytes = vec![];
+        write_pfm(&img, &mut bytes).unwrap();
+        let header = b"PF\n5 3\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 5 * 3 * 3 * 4);
+        // the first row written is the bottom one
+        let first = f32::from_le_bytes(bytes[header.len() + 4..header.len() + 8].try_into().unwrap());
+        assert_eq!(first, 2.25);
+    }
+
+    #[test]
+    fn test_hdr_files_keep_highlights() {
+        let img = gradient();
+        let dir = std::env::temp_dir();
+        for format in [OutputFormat::Exr, OutputFormat::Hdr] {
+            let path = dir.join(format!("rust_tracing_output_test.{}", format.extension()));
+            let path = path.to_str().unwrap();
+            save_image(&img, path, format, &DisplaySettings::default()).unwrap();
+            let back: Vec<Rgb<f32>> = match format {
+                // the generic decoder would tone map to 8 bits
+                OutputFormat::Hdr => {
+                    let reader = io::BufReader::new(File::open(path).unwrap());
+                    HdrDecoder::new(reader).unwrap().read_image_hdr().unwrap()
+                },
+                _ => image::open(path).unwrap().into_rgb32f().pixels().copied().collect()
+            };
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(back.len(), img.pixels().len());
+            for (a, b) in back.iter().zip(img.pixels()) {
+                for c in 0..3 {
+                    // RGBE keeps about 8 bits of mantissa
+                    assert!((a[c] - b[c]).abs() <= 0.01 * b[c].max(1.0), "{:?} vs {:?}", a, b);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// probability densities over directions, "The Rest of Your Life" sections 8-9
+use std::f64::consts::PI;
+
+use crate::hittable::Hittable;
+use crate::rtweekend::{RtRng, random_unif_1};
+use crate::vec3::{Point3, Vec3, vec3_};
+
+pub trait Pdf {
+    // density, per steradian, of drawing `direction`
+    fn value(&self, direction: &Vec3) -> f64;
+    fn generate(&self, rng: &mut RtRng) -> Vec3;
+}
+
+
+// orthonormal basis whose w axis is `n`
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x.abs() > 0.9 { vec3_(0., 1., 0.) } else { vec3_(1., 0., 0.) };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Onb{u, v, w}
+    }
+
+    // w along `n` and u along the part of `t` perpendicular to it, so that
+    // the basis follows the surface; as new() when there is no such part
+    pub fn with_tangent(n: &Vec3, t: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let u = t - t.dot(&w) * &w;
+        if u.length_squared() <= 1e-12 * t.length_squared() {
+            return Self::new(n)
+        }
+        let u = u.unit_vector();
+        let v = w.cross(&u);
+        Onb{u, v, w}
+    }
+
+    // `a` given in the basis, in world coordinates
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * &self.u + a.y * &self.v + a.z * &self.w
+    }
+
+    // the other way round: world coordinates `a` in the basis
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        vec3_(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
+
+
+// cos(theta) / pi around a normal, what a Lambertian surface scatters with
+pub struct CosinePdf {
+    uvw: Onb
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        CosinePdf{uvw: Onb::new(normal)}
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = direction.unit_vector().dot(&self.uvw.w);
+        (cosine / PI).max(0.)
+    }
+
+    fn generate(&self, rng: &mut RtRng) -> Vec3 {
+        self.uvw.local(&random_cosine_direction(rng))
+    }
+}
+
+
+// every direction alike, for isotropic media
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, rng: &mut RtRng) -> Vec3 {
+        Vec3::random_unit_vector(rng)
+    }
+}
+
+
+// directions from `origin` towards `objects`, drawn with Hittable::random
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: &Point3) -> Self {
+        HittablePdf{objects, origin: origin.clone()}
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, rng: &mut RtRng) -> Vec3 {
+        self.objects.random(&self.origin, rng)
+    }
+}
+
+
+// picks one of two densities with even odds
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2]
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        MixturePdf{p: [p0, p1]}
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self, rng: &mut RtRng) -> Vec3 {
+        if random_unif_1(rng) < 0.5 {
+            self.p[0].generate(rng)
+        } else {
+            self.p[1].generate(rng)
+        }
+    }
+}
+
+
+// cosine weighted direction around +z
+pub fn random_cosine_direction(rng: &mut RtRng) -> Vec3 {
+    let (r1, r2) = rng.unif_2d();
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+    vec3_(x, y, z)
+}
+
+// uniform direction around +z in the cone that a sphere of `radius` at
+// `distance_squared` covers
+pub fn random_to_sphere(rng: &mut RtRng, radius: f64, distance_squared: f64) -> Vec3 {
+    let (r1, r2) = rng.unif_2d();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+    vec3_(x, y, z)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::hittable_list;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::rtweekend::rng_from_seed;
+    use crate::sphere::Sphere;
+    use crate::vec3::point3;
+
+    // E[value(d) / value(d)] over d drawn from `pdf` is trivially 1, so
+    // check instead that value integrates to 1 over uniform directions and
+    // that generated directions have value > 0
+    fn check_pdf(pdf: &dyn Pdf, rng: &mut RtRng) {
+        let n = 200_000;
+        let integral = (0..n).map(|_| pdf.value(&Vec3::random_unit_vector(rng)) * 4.0 * PI)
+                             .sum::<f64>() / n as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+
+        for _ in 0..1000 {
+            assert!(pdf.value(&pdf.generate(rng)) > 0.);
+        }
+    }
+
+    #[test]
+    fn test_pdfs_are_normalized() {
+        let mut rng = rng_from_seed(3);
+        let white = Lambertian::new(1., 1., 1.);
+        let sphere = Sphere::new(point3(0., 0., -3.), 1.0, white.clone());
+        let quad = Quad::new(point3(-1., 2., -2.), vec3_(2., 0., 0.), vec3_(0., 0., 1.5), white);
+        let lights = hittable_list(vec![sphere.clone(), quad.clone()]);
+        let origin = point3(0.2, 0., 0.);
+
+        check_pdf(&CosinePdf::new(&vec3_(1., 1., 0.)), &mut rng);
+        check_pdf(&SpherePdf, &mut rng);
+        check_pdf(&HittablePdf::new(sphere.as_ref(), &origin), &mut rng);
+        check_pdf(&HittablePdf::new(quad.as_ref(), &origin), &mut rng);
+        check_pdf(&HittablePdf::new(&lights, &origin), &mut rng);
+
+        let cosine = CosinePdf::new(&vec3_(0., 1., 0.));
+        let towards_lights = HittablePdf::new(&lights, &origin);
+        check_pdf(&MixturePdf::new(&cosine, &towards_lights), &mut rng);
+    }
+
+    #[test]
+    fn test_sphere_pdf_value() {
+        // a sphere of radius 1 seen from distance 2 fills a cone of half angle 30 degrees
+        let sphere = Sphere::new(point3(0., 0., -2.), 1.0, Lambertian::new(1., 1., 1.));
+        let solid_angle = 2.0 * PI * (1.0 - 0.75f64.sqrt());
+        let origin = point3(0., 0., 0.);
+        assert!((sphere.pdf_value(&origin, &vec3_(0., 0., -1.)) - 1.0 / solid_angle).abs() < 1e-12);
+        assert_eq!(sphere.pdf_value(&origin, &vec3_(0., 1., -1.)), 0.);
+    }
+}
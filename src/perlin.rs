@@ -0,0 +1,81 @@
+// Perlin noise with random unit gradients, "The Next Week" section 5
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::vec3::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+
+impl Perlin {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| Vec3::rand_unif(rng, -1.0, 1.0).unit_vector())
+            .collect();
+        Perlin{
+            ranvec,
+            perm_x: perlin_generate_perm(rng),
+            perm_y: perlin_generate_perm(rng),
+            perm_z: perlin_generate_perm(rng)
+        }
+    }
+
+    // smooth noise in [-1, 1]
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mut accum = 0.0;
+        // hermite cubic smoothing of the interpolation weights
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let idx = self.perm_x[((i + di) & 255) as usize]
+                            ^ self.perm_y[((j + dj) & 255) as usize]
+                            ^ self.perm_z[((k + dk) & 255) as usize];
+                    let weight = Vec3{x: u - di as f64, y: v - dj as f64, z: w - dk as f64};
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                           * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                           * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                           * self.ranvec[idx].dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // sum of `depth` octaves of noise, each at twice the frequency and half the weight
+    pub fn turb(&self, p: &Point3, depth: i32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = p.clone();
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+fn perlin_generate_perm<R: Rng + ?Sized>(rng: &mut R) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    p.shuffle(rng);
+    p
+}
@@ -0,0 +1,444 @@
+// the "principled" uber material of DCC tools, after Burley's Disney BRDF
+// (2012, 2015) and OpenPBR: a handful of parameters from 0 to 1, each a
+// texture, blend a diffuse base with sheen, a GGX specular layer that
+// turns into metal, rough glass and a clear coat on top. Each layer only
+// passes on the light it doesn't reflect itself, so a white surface
+// reflects at most what it receives. Bounces pick one of these lobes, and
+// the pdf is that of the whole mixture, so light samples and bounces are
+// weighed against each other like for any other material.
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::microfacet::{DielectricBsdf, Ggx, LocalBsdf, eval_bsdf, outward_frame, pow5, reflect, scatter_bsdf};
+use crate::pdf::random_cosine_direction;
+use crate::ray::Ray;
+use crate::rtweekend::{RtRng, Shared, random_unif_1};
+use crate::spectrum::luminance;
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::{Color, Vec3, color, vec3_};
+
+pub struct PrincipledParams {
+    pub base_color: Shared<dyn Texture>,
+    // 0 for a dielectric, 1 for a metal tinted by base_color
+    pub metallic: Shared<dyn Texture>,
+    // 0 (polished) to 1
+    pub roughness: Shared<dyn Texture>,
+    // strength of the dielectric reflection: 0.5 is that of glass with
+    // index 1.5, which transmission also refracts with
+    pub specular: Shared<dyn Texture>,
+    // the soft bright rim of cloth
+    pub sheen: Shared<dyn Texture>,
+    // a colorless glossy layer on top, like varnish
+    pub clearcoat: Shared<dyn Texture>,
+    // 0 for opaque, 1 for glass tinted by base_color
+    pub transmission: Shared<dyn Texture>,
+    // 0 round highlights, up to 1 stretched along the direction in which
+    // the surface's u coordinate grows
+    pub anisotropy: Shared<dyn Texture>
+}
+
+// a gray value for the scalar parameters
+pub fn constant(value: f64) -> Shared<dyn Texture> {
+    SolidColor::new(color(value, value, value))
+}
+
+impl Default for PrincipledParams {
+    fn default() -> Self {
+        PrincipledParams{
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            transmission: constant(0.0),
+            anisotropy: constant(0.0)
+        }
+    }
+}
+
+pub struct Principled {
+    params: PrincipledParams
+}
+
+impl Principled {
+    pub fn new(params: PrincipledParams) -> Shared<dyn Material> {
+        Shared::new(Principled{params})
+    }
+
+    // the lobes with the textures looked up at the hit
+    fn bsdf(&self, rec: &HitRecord) -> PrincipledBsdf {
+        let p = &self.params;
+        let scalar = |texture: &Shared<dyn Texture>| {
+            let c = texture.value(rec.u, rec.v, &rec.p);
+            ((c.x + c.y + c.z) / 3.0).clamp(0.0, 1.0)
+        };
+        PrincipledBsdf::new(&p.base_color.value(rec.u, rec.v, &rec.p), scalar(&p.metallic), scalar(&p.roughness),
+                            scalar(&p.specular), scalar(&p.sheen), scalar(&p.clearcoat), scalar(&p.transmission),
+                            scalar(&p.anisotropy))
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, rng: &mut RtRng) -> Option<ScatterRecord> {
+        scatter_bsdf(self.bsdf(hit_record), outward_frame(hit_record), ray_in, hit_record, rng)
+    }
+
+    fn eval_bsdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        eval_bsdf(&self.bsdf(hit_record), &outward_frame(hit_record), ray_in, scattered)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.params.base_color.value(hit_record.u, hit_record.v, &hit_record.p)
+    }
+}
+
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const GLASS: usize = 2;
+const COAT: usize = 3;
+
+const COAT_ROUGHNESS: f64 = 0.15;
+const COAT_F0: f64 = 0.04;
+
+#[derive(Debug, Clone)]
+struct PrincipledBsdf {
+    base: Color,
+    roughness: f64,
+    sheen: f64,
+    // reflectance of the specular layer seen straight on, and of its
+    // dielectric part, which the diffuse lobe lies under
+    specular_f0: Color,
+    dielectric_f0: f64,
+    specular: Ggx,
+    coat: Ggx,
+    glass: DielectricBsdf,
+    // how much each lobe counts, and the chance of sampling it
+    weights: [f64; 4],
+    chances: [f64; 4]
+}
+
+impl PrincipledBsdf {
+    #[allow(clippy::too_many_arguments)]
+    fn new(base: &Color, metallic: f64, roughness: f64, specular: f64, sheen: f64, clearcoat: f64,
+           transmission: f64, anisotropy: f64) -> Self {
+        let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+        let alpha = roughness * roughness;
+        let f0 = 0.08 * specular;
+        // the index of refraction with that reflectance, kept off 1 so glass still refracts
+        let eta = ((1.0 + f0.sqrt()) / (1.0 - f0.sqrt())).max(1.01);
+        let specular_f0 = (1.0 - metallic) * color(f0, f0, f0) + metallic * base;
+
+        // glass reflects by itself, the specular layer covers everything else
+        let glass = (1.0 - metallic) * transmission;
+        let weights = [(1.0 - metallic) * (1.0 - transmission), 1.0 - glass, glass, 0.25 * clearcoat];
+        // the diffuse lobe sends most of the light of dielectrics
+        let chances = [weights[DIFFUSE], weights[SPECULAR] * (0.25 + 0.75 * luminance(&specular_f0)),
+                       weights[GLASS], weights[COAT]];
+        let total: f64 = chances.iter().sum();
+
+        PrincipledBsdf{
+            base: base.clone(), roughness, sheen, specular_f0, dielectric_f0: f0,
+            specular: Ggx::new(alpha / aspect, alpha * aspect),
+            coat: Ggx::isotropic(COAT_ROUGHNESS),
+            glass: DielectricBsdf{eta, distribution: Ggx::new(alpha / aspect, alpha * aspect)},
+            weights,
+            chances: chances.map(|c| c / total)
+        }
+    }
+}
+
+impl LocalBsdf for PrincipledBsdf {
+    fn sample(&self, wo: &Vec3, rng: &mut RtRng) -> Option<Vec3> {
+        if wo.z == 0.0 {
+            return None
+        }
+        let mut pick = random_unif_1(rng);
+        let lobe = (0..4).find(|&k| { pick -= self.chances[k]; pick < 0.0 }).unwrap_or(SPECULAR);
+        if lobe == GLASS {
+            return self.glass.sample(wo, rng)
+        }
+
+        // the other lobes reflect on the side of `wo`
+        let o = upper(wo, wo);
+        let wi = match lobe {
+            DIFFUSE => random_cosine_direction(rng),
+            _ => {
+                let distribution = if lobe == COAT { &self.coat } else { &self.specular };
+                let (u1, u2) = rng.unif_2d();
+                reflect(&o, &distribution.sample_visible(&o, u1, u2))
+            }
+        };
+        (wi.z > 0.0).then(|| upper(wo, &wi))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let mut pdf = self.chances[GLASS] * self.glass.pdf(wo, wi);
+        if wo.z * wi.z > 0.0 {
+            let (o, i) = (upper(wo, wo), upper(wo, wi));
+            let wm = (&o + &i).unit_vector();
+            pdf += self.chances[DIFFUSE] * i.z / PI
+                + self.chances[SPECULAR] * self.specular.visible_d(&o, &wm) / (4.0 * o.dot(&wm))
+                + self.chances[COAT] * self.coat.visible_d(&o, &wm) / (4.0 * o.dot(&wm));
+        }
+        pdf
+    }
+
+    fn f_cos(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        // what the coat lets through to the layers under it
+        let [coat_scale, coat_bias, _, _] = albedos(wo.z.abs(), COAT_ROUGHNESS);
+        let under_coat = 1.0 - self.weights[COAT] * (COAT_F0 * coat_scale + coat_bias);
+        let mut f_cos = color(0., 0., 0.);
+        if self.weights[GLASS] > 0.0 {
+            let glass = self.weights[GLASS] * self.glass.f_cos(wo, wi);
+            // what goes through is tinted
+            f_cos += &(if wo.z * wi.z < 0.0 { &glass * &self.base } else { glass });
+        }
+        if wo.z * wi.z <= 0.0 {
+            return under_coat * &f_cos
+        }
+
+        let (o, i) = (upper(wo, wo), upper(wo, wi));
+        let wm = (&o + &i).unit_vector();
+        let cos_d = i.dot(&wm);
+        if self.weights[DIFFUSE] > 0.0 {
+            // Burley's diffuse, darker at grazing angles when smooth, and
+            // the sheen, tinted halfway towards the base color
+            let retro = burley_retro(self.roughness, cos_d, i.z, o.z);
+            let tint = if luminance(&self.base) > 0.0 { &self.base / luminance(&self.base) } else { color(1., 1., 1.) };
+            let sheen_tint = 0.5 * color(1., 1., 1.) + 0.5 * tint;
+            let sheen = (self.sheen * pow5(1.0 - cos_d)) * &sheen_tint;
+            // they get what the specular layer doesn't reflect, and send out
+            // no more than they get, which rough retro-reflection and sheen
+            // otherwise would at grazing angles
+            let [scale, bias, retro_albedo, sheen_albedo] = albedos(o.z, self.roughness);
+            let transmitted = 1.0 - (self.dielectric_f0 * scale + bias);
+            let albedo = retro_albedo * &self.base + (self.sheen * sheen_albedo) * &sheen_tint;
+            let norm = color(1.0 / albedo.x.max(1.0), 1.0 / albedo.y.max(1.0), 1.0 / albedo.z.max(1.0));
+            f_cos += &((self.weights[DIFFUSE] * transmitted * i.z) * &(&((retro / PI) * &self.base + sheen) * &norm));
+        }
+        let specular = self.specular.d(&wm) * self.specular.g(&o, &i) / (4.0 * o.z);
+        f_cos += &((self.weights[SPECULAR] * specular) * schlick(&self.specular_f0, o.dot(&wm)));
+        f_cos = under_coat * &f_cos;
+        if self.weights[COAT] > 0.0 {
+            let coat = self.coat.d(&wm) * self.coat.g(&o, &i) / (4.0 * o.z);
+            f_cos += &((self.weights[COAT] * coat) * schlick(&color(COAT_F0, COAT_F0, COAT_F0), o.dot(&wm)));
+        }
+        f_cos
+    }
+}
+
+// `w` mirrored to the upper side if `wo` is below it
+fn upper(wo: &Vec3, w: &Vec3) -> Vec3 {
+    if wo.z < 0.0 { vec3_(w.x, w.y, -w.z) } else { w.clone() }
+}
+
+fn schlick(f0: &Color, cos: f64) -> Color {
+    f0 + pow5(1.0 - cos.clamp(0.0, 1.0)) * (color(1., 1., 1.) - f0)
+}
+
+// Burley's diffuse over that of a Lambertian surface
+fn burley_retro(roughness: f64, cos_d: f64, cos_i: f64, cos_o: f64) -> f64 {
+    let fd90 = 0.5 + 2.0 * roughness * cos_d * cos_d;
+    (1.0 + (fd90 - 1.0) * pow5(1.0 - cos_i)) * (1.0 + (fd90 - 1.0) * pow5(1.0 - cos_o))
+}
+
+// the albedo table's points in cos(theta_o) and in roughness, both from 0 to 1
+const ALBEDO_SIZE: usize = 32;
+
+// what the lobes reflect of light from `cos_o`, interpolated in a table:
+// GGX with Schlick's Fresnel reflects f0 times the first value plus the
+// second, Burley's diffuse of a white base the third, and a sheen of 1
+// the fourth
+fn albedos(cos_o: f64, roughness: f64) -> [f64; 4] {
+    static TABLE: OnceLock<Vec<[f64; 4]>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let step = 1.0 / (ALBEDO_SIZE - 1) as f64;
+        (0..ALBEDO_SIZE * ALBEDO_SIZE).map(|k| {
+            integrate_albedos(((k / ALBEDO_SIZE) as f64 * step).max(1e-3), (k % ALBEDO_SIZE) as f64 * step)
+        }).collect()
+    });
+    let last = (ALBEDO_SIZE - 1) as f64;
+    let (x, y) = (cos_o.clamp(0.0, 1.0) * last, roughness.clamp(0.0, 1.0) * last);
+    let (i, j) = ((x as usize).min(ALBEDO_SIZE - 2), (y as usize).min(ALBEDO_SIZE - 2));
+    let (fx, fy) = (x - i as f64, y - j as f64);
+    let at = |i: usize, j: usize, c: usize| table[i * ALBEDO_SIZE + j][c];
+    std::array::from_fn(|c| {
+        (1.0 - fx) * ((1.0 - fy) * at(i, j, c) + fy * at(i, j + 1, c))
+            + fx * ((1.0 - fy) * at(i + 1, j, c) + fy * at(i + 1, j + 1, c))
+    })
+}
+
+// midpoint rule over the square of random numbers that sample the lobes:
+// reflected visible normals give F G / G1, cosine weighted directions pi
+// times the BRDF
+fn integrate_albedos(cos_o: f64, roughness: f64) -> [f64; 4] {
+    let n = 32;
+    let wo = vec3_((1.0 - cos_o * cos_o).sqrt(), 0., cos_o);
+    let ggx = Ggx::isotropic(roughness);
+    let mut sums = [0.0; 4];
+    for a in 0..n {
+        for b in 0..n {
+            let (u1, u2) = ((a as f64 + 0.5) / n as f64, (b as f64 + 0.5) / n as f64);
+            let wm = ggx.sample_visible(&wo, u1, u2);
+            let wi = reflect(&wo, &wm);
+            if wi.z > 0.0 {
+                let g = ggx.g(&wo, &wi) / ggx.g1(&wo);
+                let f = pow5(1.0 - wo.dot(&wm).clamp(0.0, 1.0));
+                sums[0] += g * (1.0 - f);
+                sums[1] += g * f;
+            }
+
+            let (phi, r) = (2.0 * PI * u1, u2.sqrt());
+            let wi = vec3_(r * phi.cos(), r * phi.sin(), (1.0 - u2).sqrt());
+            let cos_d = wi.dot(&(&wo + &wi).unit_vector());
+            sums[2] += burley_retro(roughness, cos_d, wi.z, cos_o);
+            sums[3] += PI * pow5(1.0 - cos_d);
+        }
+    }
+    sums.map(|s| s / (n * n) as f64)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::microfacet::tests::direction;
+    use crate::rtweekend::rng_from_seed;
+    use crate::sphere::Sphere;
+    use crate::vec3::point3;
+
+    fn materials() -> Vec<(&'static str, PrincipledBsdf)> {
+        let orange = color(0.9, 0.4, 0.1);
+        vec![
+            ("plastic", PrincipledBsdf::new(&orange, 0.0, 0.4, 0.5, 0.0, 0.0, 0.0, 0.0)),
+            ("brushed metal", PrincipledBsdf::new(&orange, 1.0, 0.5, 0.5, 0.0, 0.0, 0.0, 0.8)),
+            ("varnished cloth", PrincipledBsdf::new(&orange, 0.0, 0.9, 0.5, 1.0, 1.0, 0.0, 0.0)),
+            ("tinted glass", PrincipledBsdf::new(&orange, 0.0, 0.5, 0.5, 0.0, 0.0, 1.0, 0.0)),
+            ("a bit of everything", PrincipledBsdf::new(&orange, 0.3, 0.6, 0.7, 0.5, 0.5, 0.4, 0.3))
+        ]
+    }
+
+    // directions are drawn as the pdf says: E[f_cos / pdf] over samples is
+    // the integral of f_cos, here estimated with uniform directions too
+    #[test]
+    fn test_sampling_matches_pdf() {
+        let mut rng = rng_from_seed(6);
+        let n = 200_000;
+        for (name, bsdf) in materials() {
+            for wo in [direction(20.0, 0.0), direction(65.0, 120.0), direction(150.0, 30.0)] {
+                let sampled = (0..n).filter_map(|_| {
+                    let wi = bsdf.sample(&wo, &mut rng)?;
+                    let pdf = bsdf.pdf(&wo, &wi);
+                    assert!(pdf > 0.0, "{}: {:?} -> {:?}", name, wo, wi);
+                    Some(bsdf.f_cos(&wo, &wi).y / pdf)
+                }).sum::<f64>() / n as f64;
+                let (uniform, integral) = (0..n).map(|_| {
+                    let wi = Vec3::random_unit_vector(&mut rng);
+                    (bsdf.f_cos(&wo, &wi).y * 4.0 * PI, bsdf.pdf(&wo, &wi) * 4.0 * PI)
+                }).fold((0.0, 0.0), |(a, b), (f, p)| (a + f / n as f64, b + p / n as f64));
+                assert!(integral <= 1.05, "{} from {:?}: pdf integrates to {}", name, wo, integral);
+                // uniform directions rarely find a sharp highlight, hence the tolerance
+                assert!((sampled - uniform).abs() < 0.05 * sampled.max(0.2), "{} from {:?}: {} vs {}",
+                        name, wo, sampled, uniform);
+                // only glass lets more out than comes in, by squeezing radiance into it
+                if bsdf.weights[GLASS] == 0.0 {
+                    assert!(sampled < 1.0, "{} from {:?}: albedo {}", name, wo, sampled);
+                }
+            }
+        }
+    }
+
+    // a white base sends out at most the light it receives, at any angle:
+    // the coat and the specular layer each pass on what they don't reflect
+    #[test]
+    fn test_white_reflects_at_most_everything() {
+        let (n_theta, n_phi) = (300, 300);
+        let (d_theta, d_phi) = (PI / 2.0 / n_theta as f64, 2.0 * PI / n_phi as f64);
+        for (roughness, sheen, clearcoat) in [(0.2, 0.0, 0.0), (0.4, 0.0, 0.0), (0.7, 1.0, 0.0), (1.0, 1.0, 1.0),
+                                              (0.3, 0.5, 1.0)] {
+            let bsdf = PrincipledBsdf::new(&color(1., 1., 1.), 0.0, roughness, 0.5, sheen, clearcoat, 0.0, 0.0);
+            for theta_o in [20.0, 65.0, 80.0, 88.0] {
+                let wo = direction(theta_o, 0.0);
+                // midpoint rule over the upper hemisphere
+                let albedo: f64 = (0..n_theta).flat_map(|i| (0..n_phi).map(move |j| (i, j))).map(|(i, j)| {
+                    let theta = (i as f64 + 0.5) * d_theta;
+                    let wi = direction(theta.to_degrees(), ((j as f64 + 0.5) * d_phi).to_degrees());
+                    bsdf.f_cos(&wo, &wi).y * theta.sin() * d_theta * d_phi
+                }).sum();
+                // up to the error of the rule and of the albedo table's interpolation
+                assert!(albedo > 0.3 && albedo <= 1.0 + 1e-3, "roughness {}, sheen {}, clearcoat {} from {}°: {}",
+                        roughness, sheen, clearcoat, theta_o, albedo);
+            }
+        }
+    }
+
+    // brushed along the circles of latitude all the way round a sphere, with
+    // no seam where the normal alone would pick another tangent
+    #[test]
+    fn test_anisotropy_follows_the_surface() {
+        let brushed = Principled::new(PrincipledParams{metallic: constant(1.0), roughness: constant(0.3),
+                                                       anisotropy: constant(1.0), ..Default::default()});
+        let sphere = Sphere::new(point3(0., 0., 0.), 1.0, brushed.clone());
+        let (wo_deg, wi_deg) = (30f64.to_radians(), 40f64.to_radians());
+        for latitude in [-60.0, 0.0, 30.0] {
+            for k in 0..72 {
+                let (lat, lon) = (f64::to_radians(latitude), (k as f64 * 5.0).to_radians());
+                let n = vec3_(lat.cos() * lon.cos(), lat.sin(), lat.cos() * lon.sin());
+                let along = vec3_(n.z, 0., -n.x).unit_vector();
+                let across = n.cross(&along);
+                // from one side of the normal towards the other, in the plane of `t`
+                let f = |t: &Vec3| {
+                    let wo = &(wo_deg.cos() * &n) + &(wo_deg.sin() * t);
+                    let ray_in = Ray::new(&(&n + &(2.0 * &wo)), &-&wo);
+                    let mut rec = HitRecord::default();
+                    assert!(sphere.hit(&ray_in, 0.001, 10.0, &mut rec));
+                    let wi = &(wi_deg.cos() * &n) - &(wi_deg.sin() * t);
+                    brushed.eval_bsdf(&ray_in, &rec, &Ray::new(&rec.p, &wi)).x
+                };
+                let (f_along, f_across) = (f(&along), f(&across));
+                assert!(f_along > 2.0 * f_across, "at {:?}: {} vs {}", n, f_along, f_across);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parameters() {
+        let white = color(1., 1., 1.);
+        let wo = direction(30.0, 0.0);
+        let mirror = direction(30.0, 180.0);
+        // dielectrics reflect about 4% colorless straight on, metals their color
+        let plastic = PrincipledBsdf::new(&color(0.2, 0.5, 0.8), 0.0, 0.3, 0.5, 0.0, 0.0, 0.0, 0.0);
+        let metal = PrincipledBsdf::new(&color(0.2, 0.5, 0.8), 1.0, 0.3, 0.5, 0.0, 0.0, 0.0, 0.0);
+        assert_eq!(plastic.specular_f0, color(0.04, 0.04, 0.04));
+        let (p, m) = (plastic.f_cos(&wo, &mirror), metal.f_cos(&wo, &mirror));
+        assert!(m.z > 5.0 * p.z && m.z > m.x, "{:?} vs {:?}", p, m);
+        assert!(metal.f_cos(&wo, &direction(60.0, 90.0)).x > 0.0);
+        assert_eq!(metal.weights[DIFFUSE], 0.0);
+
+        // anisotropy stretches the highlight along x
+        let stretched = PrincipledBsdf::new(&white, 1.0, 0.3, 0.5, 0.0, 0.0, 0.0, 1.0);
+        let along_x = stretched.f_cos(&direction(30.0, 0.0), &direction(40.0, 180.0)).x;
+        let along_y = stretched.f_cos(&direction(30.0, 90.0), &direction(40.0, 270.0)).x;
+        assert!(along_x > 2.0 * along_y, "{} vs {}", along_x, along_y);
+
+        // sheen brightens grazing angles, clearcoat adds a sharp highlight
+        let cloth = PrincipledBsdf::new(&white, 0.0, 1.0, 0.5, 1.0, 0.0, 0.0, 0.0);
+        let felt = PrincipledBsdf::new(&white, 0.0, 1.0, 0.5, 0.0, 0.0, 0.0, 0.0);
+        let (grazing, back) = (direction(80.0, 0.0), direction(80.0, 180.0));
+        assert!(cloth.f_cos(&grazing, &back).x > 1.2 * felt.f_cos(&grazing, &back).x);
+        let coated = PrincipledBsdf::new(&white, 0.0, 1.0, 0.5, 0.0, 1.0, 0.0, 0.0);
+        assert!(coated.f_cos(&wo, &mirror).x > 2.0 * felt.f_cos(&wo, &mirror).x);
+
+        // glass passes light through, tinted; opaque materials don't
+        let through = direction(170.0, 180.0);
+        let glass = PrincipledBsdf::new(&color(1.0, 0.5, 0.5), 0.0, 0.2, 0.5, 0.0, 0.0, 1.0, 0.0);
+        let t = glass.f_cos(&wo, &through);
+        assert!(t.x > 0.0 && (t.y / t.x - 0.5).abs() < 1e-9, "{:?}", t);
+        assert_eq!(plastic.f_cos(&wo, &through), color(0., 0., 0.));
+    }
+}
@@ -0,0 +1,159 @@
+// parallelograms, "The Next Week" section 6 (fourth edition)
+use crate::aabb::{Aabb, surrounding_box};
+use crate::hittable::{HitRecord, Hittable, HittableList, hittable_list};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::{INF, RtRng, Shared};
+use crate::vec3::{Point3, Vec3, point3, vec3_};
+
+// the parallelogram with corner `q` and edges `u` and `v`; it faces the side
+// u x v points to
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    // n / (n . n) with n = u x v, maps a point of the plane to its (alpha, beta)
+    w: Vec3,
+    normal: Vec3,
+    // plane equation: normal . p = d
+    d: f64,
+    area: f64,
+    material: Shared<dyn Material>
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Shared<dyn Material>) -> Shared<Quad> {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        let d = normal.dot(&q);
+        let w = &n / n.dot(&n);
+        let area = n.length();
+        Shared::new(Quad{q, u, v, w, normal, d, area, material})
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&ray.dir);
+        if denom.abs() < 1e-8 {
+            // ray parallel to the plane
+            return false
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if t < t_min || t > t_max {
+            return false
+        }
+
+        // planar coordinates of the hit point along the two edges
+        let p = ray.at(t);
+        let planar = &p - &self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false
+        }
+
+        rec.t = t;
+        rec.p = p;
+        (rec.u, rec.v) = (alpha, beta);
+        rec.tangent = self.u.clone();
+        rec.set_face_normal(ray, &self.normal);
+        rec.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [&self.q + &self.u, &self.q + &self.v, &self.q + &self.u + &self.v];
+        let bbox = corners.iter().fold(Aabb::new(self.q.clone(), self.q.clone()),
+                                       |bbox, c| surrounding_box(&bbox, &Aabb::new(c.clone(), c.clone())));
+        Some(bbox.pad(1e-4))
+    }
+
+    // uniform over the area, "The Rest of Your Life" section 12.1: a patch dA
+    // at distance r and angle theta to the normal subtends dA cos(theta) / r^2
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(&Ray::new(origin, direction), 0.001, INF, &mut rec) {
+            return 0.0
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut RtRng) -> Vec3 {
+        let (a, b) = rng.unif_2d();
+        let p = &self.q + a * &self.u + b * &self.v;
+        p - origin
+    }
+}
+
+
+// the six sides of the axis aligned box with opposite corners `a` and `b`, facing out
+pub fn box_(a: &Point3, b: &Point3, material: Shared<dyn Material>) -> Shared<HittableList> {
+    let min = point3(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = point3(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = vec3_(max.x - min.x, 0., 0.);
+    let dy = vec3_(0., max.y - min.y, 0.);
+    let dz = vec3_(0., 0., max.z - min.z);
+
+    Shared::new(hittable_list(vec![
+        Quad::new(point3(min.x, min.y, max.z), dx.clone(), dy.clone(), material.clone()),   // front
+        Quad::new(point3(max.x, min.y, max.z), -&dz, dy.clone(), material.clone()),         // right
+        Quad::new(point3(max.x, min.y, min.z), -&dx, dy.clone(), material.clone()),         // back
+        Quad::new(point3(min.x, min.y, min.z), dz.clone(), dy, material.clone()),           // left
+        Quad::new(point3(min.x, max.y, max.z), dx.clone(), -&dz, material.clone()),         // top
+        Quad::new(point3(min.x, min.y, min.z), dx, dz, material)                            // bottom
+    ]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_quad_hit_and_uv() {
+        // 2 x 1 rectangle in the z = -1 plane, facing +z
+        let quad = Quad::new(point3(-1., 0., -1.), vec3_(2., 0., 0.), vec3_(0., 1., 0.),
+                             Lambertian::new(0.5, 0.5, 0.5));
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(&point3(0.5, 0.25, 0.), &vec3_(0., 0., -1.));
+        assert!(quad.hit(&ray, 0.001, 10.0, &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert_eq!(rec.tangent, vec3_(2., 0., 0.));
+        assert_eq!(rec.normal, vec3_(0., 0., 1.));
+        assert!(rec.front_face);
+
+        let outside = Ray::new(&point3(1.5, 0.25, 0.), &vec3_(0., 0., -1.));
+        assert!(!quad.hit(&outside, 0.001, 10.0, &mut rec));
+        let behind = Ray::new(&point3(0.5, 0.25, -2.), &vec3_(0., 0., 1.));
+        assert!(quad.hit(&behind, 0.001, 10.0, &mut rec));
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn test_box_faces_point_out() {
+        let cube = box_(&point3(1., 1., 1.), &point3(-1., -1., -1.), Lambertian::new(0.5, 0.5, 0.5));
+        assert_eq!(cube.objects.len(), 6);
+
+        // from outside along each axis, the hit is on the near face and faces the ray
+        for dir in [vec3_(1., 0., 0.), vec3_(0., 1., 0.), vec3_(0., 0., 1.)] {
+            for sign in [1.0, -1.0] {
+                let d = sign * &dir;
+                let ray = Ray::new(&(-3.0 * &d), &d);
+                let mut rec = HitRecord::default();
+                assert!(cube.hit(&ray, 0.001, 10.0, &mut rec));
+                assert!((rec.t - 2.0).abs() < 1e-12);
+                assert!(rec.front_face, "{:?}", d);
+                assert_eq!(rec.normal, -&d);
+            }
+        }
+    }
+}
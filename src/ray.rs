@@ -1,10 +1,14 @@
-
+use crate::spectrum::{LAMBDA_D, Wavelengths};
 use crate::vec3::{Vec3, Point3};
 
 #[derive(Default)]
 pub struct Ray {
     pub origin: Vec3,
-    pub dir: Vec3
+    pub dir: Vec3,
+    // when the ray was sent, for objects that move while the shutter is open
+    pub time: f64,
+    // in spectral mode, the wavelengths of the path the ray is part of
+    pub wavelengths: Option<Wavelengths>
 }
 
 impl Ray {
@@ -13,6 +17,20 @@ impl Ray {
     }
 
     pub fn new(origin: &Vec3, dir: &Vec3) -> Self {
-        Self{origin: origin.clone(), dir: dir.clone()}
+        Self::with_time(origin, dir, 0.0)
+    }
+
+    pub fn with_time(origin: &Vec3, dir: &Vec3, time: f64) -> Self {
+        Self{origin: origin.clone(), dir: dir.clone(), time, wavelengths: None}
+    }
+
+    // a ray of the same path, from `origin` towards `dir`
+    pub fn next(&self, origin: &Vec3, dir: &Vec3) -> Self {
+        Self{wavelengths: self.wavelengths, ..Self::with_time(origin, dir, self.time)}
+    }
+
+    // what dispersive materials refract at: the hero wavelength, or the d-line in RGB mode
+    pub fn wavelength(&self) -> f64 {
+        self.wavelengths.map_or(LAMBDA_D, |w| w.hero())
     }
 }
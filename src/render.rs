@@ -0,0 +1,436 @@
+// multi-threaded tile renderer
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use image::{ImageBuffer, Pixel, Rgb32FImage};
+
+use crate::aov::{AovBuffers, AovPixel};
+use crate::camera::CameraWithFocus;
+use crate::integrator::{Integrator, PathStats, SceneView, ray_color_iterative};
+use crate::rtweekend::{INF, RtRng, pixel_rng};
+use crate::sampler::{SamplerKind, WAVELENGTH_DIM};
+use crate::spectrum::{Wavelengths, luminance};
+use crate::vec3::{Color, color};
+
+pub const TILE_SIZE: u32 = 32;
+
+pub struct RenderParams {
+    pub aspect_ratio: f64,
+    pub img_width: u32,
+    pub img_height: u32,
+    pub samples_per_pixel: i32,
+    pub depth: i32,
+    pub seed: u64,
+    pub n_threads: usize,
+    // filter the image with the AOVs as guides once it's rendered
+    pub denoise: bool,
+    // stop sampling converged pixels early, samples_per_pixel is the maximum
+    pub adaptive: Option<Adaptive>,
+    // where the random numbers of each sample come from
+    pub sampler: SamplerKind,
+    // trace each path at random wavelengths rather than in RGB
+    pub spectral: bool
+}
+
+// pixels are sampled in rounds of `min_samples`; after each round, those
+// whose standard error of the mean luminance is above `threshold` times the
+// mean, or next to such a pixel, get another round
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adaptive {
+    pub min_samples: i32,
+    pub threshold: f64
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Adaptive{min_samples: 16, threshold: 0.02}
+    }
+}
+
+// darker pixels are held to the error allowed at this luminance: relative
+// to a mean near 0, any noise at all would be too much
+const ADAPTIVE_DARK: f64 = 0.1;
+
+impl RenderParams {
+    pub fn new(aspect_ratio: f64, img_width: u32, samples_per_pixel: i32)-> Self {
+        RenderParams {
+            aspect_ratio,
+            img_width,
+            img_height: ((img_width as f64) / aspect_ratio) as u32,
+            samples_per_pixel,
+            depth: 50,
+            seed: 0,
+            n_threads: available_threads(),
+            denoise: false,
+            adaptive: None,
+            sampler: SamplerKind::Independent,
+            spectral: false
+        }
+    }
+}
+
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32
+}
+
+fn make_tiles(img_width: u32, img_height: u32) -> Vec<Tile> {
+    let mut tiles = vec![];
+    for y0 in (0..img_height).step_by(TILE_SIZE as usize) {
+        for x0 in (0..img_width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile{x0, y0,
+                            x1: (x0 + TILE_SIZE).min(img_width),
+                            y1: (y0 + TILE_SIZE).min(img_height)});
+        }
+    }
+    tiles
+}
+
+// Drop-in replacement for `ImageBuffer::from_fn`: `pixel_fn(i, j, rng)` is
+// evaluated for every pixel, with tiles handed out to `n_threads` workers.
+// `rng` is the pixel's own stream (see `pixel_rng`), so the image only depends
+// on `seed`. Pixels can be 8-bit for the listings or float for the scenes.
+pub fn render_tiles<P, F>(img_width: u32, img_height: u32, seed: u64, n_threads: usize,
+                          pixel_fn: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where P: Pixel + Send,
+          F: Fn(u32, u32, &mut RtRng) -> P + Sync {
+    let pixels = render_pixels(img_width, img_height, seed, n_threads, pixel_fn);
+    let mut img = ImageBuffer::new(img_width, img_height);
+    for (dst, src) in img.pixels_mut().zip(pixels) {
+        *dst = src;
+    }
+    img
+}
+
+// running mean and variance of a pixel's luminance, Welford's algorithm
+#[derive(Default)]
+struct PixelVariance {
+    n: i32,
+    mean: f64,
+    m2: f64
+}
+
+impl PixelVariance {
+    fn add(&mut self, c: &Color) {
+        let x = luminance(c);
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    // standard error of the mean, relative to the mean
+    fn relative_error(&self) -> f64 {
+        if self.n < 2 {
+            return INF
+        }
+        (self.m2 / ((self.n - 1) as f64 * self.n as f64)).sqrt() / self.mean.max(ADAPTIVE_DARK)
+    }
+}
+
+// what a pixel has gathered so far, kept between rounds
+struct PixelState {
+    rng: RtRng,
+    color: Color,
+    samples: i32,
+    variance: PixelVariance,
+    aov: AovPixel
+}
+
+// every pixel of `scene` as the average of `rp.samples_per_pixel` paths (or
+// fewer, see `rp.adaptive`), and the AOVs of the camera rays if `with_aovs`
+// (else only the sample counts)
+pub fn render_scene(scene: &SceneView, camera: &CameraWithFocus, integrator: Integrator, rp: &RenderParams,
+                    with_aovs: bool, path_stats: &Mutex<PathStats>) -> (Rgb32FImage, AovBuffers) {
+    let (width, height) = (rp.img_width, rp.img_height);
+    let sampler = rp.sampler.sampler(rp.samples_per_pixel, rp.seed);
+    let add_samples = |pixel: &mut PixelState, i: u32, j: u32, n: i32| {
+        let rng = &mut pixel.rng;
+        let mut pixel_stats = PathStats::default();
+        for s in 0..n {
+            rng.start_sample(i, j, (pixel.samples + s) as u32);
+            let (du, dv) = rng.unif_2d();
+            let u = (i as f64 + du)
+                          / (width - 1) as f64;
+            let v = ((height - j) as f64 + dv)
+                          / (height - 1) as f64;
+            let mut ray = camera.get_ray(u, v, rng);
+            // the AOVs stay in RGB
+            if with_aovs {
+                pixel.aov.add_sample(&ray, scene);
+            }
+            if rp.spectral {
+                ray.wavelengths = Some(Wavelengths::sample(rng.unif_at(WAVELENGTH_DIM)));
+            }
+
+            let sample = match integrator {
+                Integrator::Recursive(ray_color) => ray_color(&ray, rng, scene, rp.depth),
+                Integrator::Iterative{min_bounces} =>
+                    ray_color_iterative(&ray, rng, scene, rp.depth, min_bounces, &mut pixel_stats)
+            };
+            let sample = match &ray.wavelengths {
+                Some(wavelengths) => wavelengths.rgb(&sample),
+                None => sample
+            };
+            pixel.color += &sample;
+            pixel.variance.add(&sample);
+        }
+        pixel.samples += n;
+        path_stats.lock().unwrap().merge(&pixel_stats);
+    };
+
+    let round = rp.adaptive.map_or(rp.samples_per_pixel, |a| a.min_samples.clamp(1, rp.samples_per_pixel));
+    let pixels: Vec<Mutex<PixelState>> =
+        render_pixels(width, height, rp.seed, rp.n_threads, |i, j, rng| {
+            let mut pixel = PixelState{rng: rng.clone(), color: color(0., 0., 0.), samples: 0,
+                                       variance: PixelVariance::default(), aov: AovPixel::default()};
+            if let Some(sampler) = &sampler {
+                pixel.rng.set_sampler(sampler.clone());
+            }
+            add_samples(&mut pixel, i, j, round);
+            Mutex::new(pixel)
+        });
+
+    if let Some(adaptive) = &rp.adaptive {
+        loop {
+            let active = unconverged(&pixels, width, height, adaptive.threshold, rp.samples_per_pixel);
+            if active.is_empty() {
+                break
+            }
+            // each pixel goes on with its own generator
+            for_each_pixel(&active, rp.n_threads, |k| {
+                let mut pixel = pixels[k].lock().unwrap();
+                let n = round.min(rp.samples_per_pixel - pixel.samples);
+                add_samples(&mut pixel, k as u32 % width, k as u32 / width, n);
+            });
+        }
+    }
+
+    let (colors, aovs): (Vec<_>, Vec<_>) = pixels.into_iter().map(|pixel| {
+        let mut pixel = pixel.into_inner().unwrap();
+        pixel.aov.set_samples(pixel.samples as u32);
+        (pixel.color.to_rgb32_sampled(pixel.samples), pixel.aov)
+    }).unzip();
+    let img = Rgb32FImage::from_vec(width, height, colors.iter().flat_map(|p| p.0).collect()).unwrap();
+    (img, AovBuffers::new(width, height, aovs))
+}
+
+// the pixels below the maximum with an error above `threshold` somewhere
+// in the 3x3 pixels around them, in row-major order: an edge whose first
+// samples all missed an object still gets more samples thanks to its
+// neighbours
+fn unconverged(pixels: &[Mutex<PixelState>], width: u32, height: u32, threshold: f64, max_samples: i32) -> Vec<usize> {
+    let states: Vec<(i32, f64)> = pixels.iter().map(|p| {
+        let p = p.lock().unwrap();
+        (p.samples, p.variance.relative_error())
+    }).collect();
+    (0..pixels.len()).filter(|&k| {
+        if states[k].0 >= max_samples {
+            return false
+        }
+        let (i, j) = ((k as u32 % width) as i64, (k as u32 / width) as i64);
+        (j - 1..=j + 1).filter(|y| (0..height as i64).contains(y))
+            .flat_map(|y| (i - 1..=i + 1).filter(|x| (0..width as i64).contains(x)).map(move |x| (x, y)))
+            .any(|(x, y)| states[(y * width as i64 + x) as usize].1 > threshold)
+    }).collect()
+}
+
+// `pixel_fn(k)` for the pixels at `indices` only, handed out to `n_threads`
+// workers a tile's worth at a time
+fn for_each_pixel<F>(indices: &[usize], n_threads: usize, pixel_fn: F)
+    where F: Fn(usize) + Sync {
+    let chunk = (TILE_SIZE * TILE_SIZE) as usize;
+    let next_chunk = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..n_threads.max(1) {
+            s.spawn(|| loop {
+                let start = next_chunk.fetch_add(1, Ordering::Relaxed) * chunk;
+                if start >= indices.len() {
+                    break
+                }
+                indices[start..(start + chunk).min(indices.len())].iter().for_each(|&k| pixel_fn(k));
+            });
+        }
+    });
+}
+
+// render_tiles for anything a pixel may compute, in row-major order
+pub fn render_pixels<T, F>(img_width: u32, img_height: u32, seed: u64, n_threads: usize,
+                           pixel_fn: F) -> Vec<T>
+    where T: Send,
+          F: Fn(u32, u32, &mut RtRng) -> T + Sync {
+
+    let tiles = make_tiles(img_width, img_height);
+    let next_tile = AtomicUsize::new(0);
+
+    let rendered: Vec<(usize, Vec<T>)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..n_threads.max(1)).map(|_| {
+            s.spawn(|| {
+                let mut done = vec![];
+                loop {
+                    let tile_idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if tile_idx >= tiles.len() {
+                        break
+                    }
+                    let tile = &tiles[tile_idx];
+                    let mut pixels = Vec::with_capacity(
+                        ((tile.x1 - tile.x0) * (tile.y1 - tile.y0)) as usize);
+                    for j in tile.y0..tile.y1 {
+                        for i in tile.x0..tile.x1 {
+                            let mut rng = pixel_rng(seed, i, j);
+                            pixels.push(pixel_fn(i, j, &mut rng));
+                        }
+                    }
+                    done.push((tile_idx, pixels));
+                }
+                done
+            })
+        }).collect();
+
+        workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+    });
+
+    let mut img: Vec<Option<T>> = (0..img_width * img_height).map(|_| None).collect();
+    for (tile_idx, pixels) in rendered {
+        let tile = &tiles[tile_idx];
+        let mut pixels = pixels.into_iter();
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                img[(j * img_width + i) as usize] = pixels.next();
+            }
+        }
+    }
+    img.into_iter().map(|p| p.unwrap()).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+    use crate::aov::Aov;
+    use crate::bvh::BvhNode;
+    use crate::hittable::{HitRecord, Hittable, hittable_list};
+    use crate::material::{Dielectric, Lambertian, Metal};
+    use crate::rtweekend::{INF, random_unif_1, rng_from_seed};
+    use crate::scenes::find_scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::{color, point3, vec3_};
+    use crate::vec3_img::Rgb8;
+
+    fn noise(i: u32, j: u32, rng: &mut RtRng) -> Rgb8 {
+        let r = random_unif_1(rng);
+        Rgb([(255.0 * r) as u8, i as u8, j as u8])
+    }
+
+    #[test]
+    fn test_same_image_for_any_thread_count() {
+        let img1 = render_tiles(100, 70, 42, 1, noise);
+        let img4 = render_tiles(100, 70, 42, 4, noise);
+        let img7 = render_tiles(100, 70, 42, 7, noise);
+        assert!(img1 == img4);
+        assert!(img1 == img7);
+
+        let other_seed = render_tiles(100, 70, 43, 4, noise);
+        assert!(img1 != other_seed);
+    }
+
+    #[test]
+    fn test_scene_render_is_reproducible() {
+        let world = hittable_list(vec![
+            Sphere::new(point3(0., -100.5, -1.), 100.0, Lambertian::new(0.8, 0.8, 0.)),
+            Sphere::new(point3(0., 0., -1.), 0.5, Dielectric::new(1.5)),
+            Sphere::new(point3(1., 0., -1.), 0.5, Metal::new(&color(0.8, 0.6, 0.2), 0.3)),
+        ]);
+        let camera = CameraWithFocus::new(&point3(3., 3., 2.), &point3(0., 0., -1.),
+                                          vec3_(0., 1., 0.), 20.0, 1.5, 2.0, 3.4);
+        let render = |seed, n_threads| render_tiles(48, 32, seed, n_threads, |i, j, rng| {
+            let mut pixel_color = color(0., 0., 0.);
+            for _ in 0..4 {
+                let u = (i as f64 + random_unif_1(rng)) / 47.0;
+                let v = ((32 - j) as f64 + random_unif_1(rng)) / 31.0;
+                let mut ray = camera.get_ray(u, v, rng);
+                let mut attenuation = color(1., 1., 1.);
+                for _ in 0..8 {
+                    let mut rec = HitRecord::default();
+                    if !world.hit(&ray, 0.001, INF, &mut rec) {
+                        pixel_color += &attenuation;
+                        break
+                    }
+                    match rec.material.scatter(&ray, &rec, rng) {
+                        Some(s_rec) => {
+                            attenuation = &attenuation * &s_rec.attenuation;
+                            ray = s_rec.scattered;
+                        },
+                        None => break
+                    }
+                }
+            }
+            pixel_color.to_rgb_sampled(4)
+        });
+
+        assert!(render(7, 1) == render(7, 3));
+        assert!(render(7, 2) != render(8, 2));
+    }
+
+    #[test]
+    fn test_adaptive_sampling() {
+        // diffuse spheres under the sky: the sky converges at once, the shadows don't
+        let scene = find_scene("four_sphere_world_50").unwrap();
+        let world = BvhNode::new(&(scene.build)(&mut rng_from_seed(0)));
+        let lights = (scene.lights)();
+        let view = SceneView{world: world.as_ref(), lights: &lights, background: &scene.background};
+        let render_with = |spp, adaptive, n_threads| {
+            let mut rp = RenderParams::new(scene.aspect_ratio, 32, spp);
+            rp.adaptive = adaptive;
+            rp.n_threads = n_threads;
+            render_scene(&view, &scene.camera.camera(rp.aspect_ratio), Integrator::Recursive(scene.ray_color),
+                         &rp, false, &Mutex::new(PathStats::default()))
+        };
+        let render = |spp, adaptive| render_with(spp, adaptive, 4);
+        let (adaptive, aovs) = render(256, Some(Adaptive{min_samples: 8, threshold: 0.02}));
+        // the later rounds go over the pixels in any order
+        assert!(adaptive == render_with(256, Some(Adaptive{min_samples: 8, threshold: 0.02}), 1).0);
+        let n = (adaptive.width() * adaptive.height()) as usize;
+        let counts: Vec<u32> = (0..n).map(|k| aovs.value(Aov::Samples, k).x as u32).collect();
+        assert_eq!(counts[0], 8, "sky pixel");
+        assert!(counts.iter().all(|n| (8..=256).contains(n)));
+        assert!(counts.contains(&256));
+
+        // closer to the reference than the same number of samples spread evenly
+        let mean = (aovs.total_samples() as f64 / n as f64).round() as i32;
+        let (reference, _) = render(1024, None);
+        let (fixed, _) = render(mean, None);
+        let mse = |img: &Rgb32FImage| img.pixels().zip(reference.pixels())
+            .map(|(p, q)| (0..3).map(|c| (p[c] - q[c]).powi(2) as f64).sum::<f64>()).sum::<f64>();
+        assert!(mse(&adaptive) < mse(&fixed), "{} spp: {} vs {}", mean, mse(&adaptive), mse(&fixed));
+    }
+
+    #[test]
+    fn test_spectral_aovs() {
+        let scene = find_scene("four_sphere_world_50").unwrap();
+        let world = BvhNode::new(&(scene.build)(&mut rng_from_seed(0)));
+        let lights = (scene.lights)();
+        let view = SceneView{world: world.as_ref(), lights: &lights, background: &scene.background};
+        let render = |spectral| {
+            let mut rp = RenderParams::new(scene.aspect_ratio, 16, 4);
+            rp.spectral = spectral;
+            render_scene(&view, &scene.camera.camera(rp.aspect_ratio), Integrator::Recursive(scene.ray_color),
+                         &rp, true, &Mutex::new(PathStats::default())).1
+        };
+        // the albedo is an RGB color whichever way the paths are traced, not
+        // the values of its spectrum at the sampled wavelengths
+        let (rgb, spectral) = (render(false), render(true));
+        let n = 16 * 8;
+        let difference = (0..n).map(|k| (&spectral.value(Aov::Albedo, k) - &rgb.value(Aov::Albedo, k)).length())
+            .sum::<f64>() / n as f64;
+        assert!(difference < 0.05, "{}", difference);
+    }
+}
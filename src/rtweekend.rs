@@ -1,14 +1,119 @@
 
-use std::f64::{INFINITY};
 use std::f64::consts::{PI};
-use std::rc::Rc;
+use std::sync::Arc;
 
-use rand::{rngs::ThreadRng, Rng};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_pcg::Pcg64;
 
+use crate::sampler::{CAMERA_DIMS, DIMS_PER_BOUNCE, SAMPLED_BOUNCES, Sampler};
 
-pub type Shared<T> = Rc<T>;
 
-pub const INF: f64 = INFINITY;
+pub type Shared<T> = Arc<T>;
+
+// The generator handed to materials and integrators. Helpers that don't go
+// through a trait object are generic over `rand::Rng` instead.
+// Pcg64's output is fixed for a given seed (unlike StdRng, which may change
+// between rand versions), so a seed always reproduces the same image.
+// With a sampler set, the numbers of a pixel sample come from the sampler
+// instead, dimension after dimension (see sampler.rs for the layout).
+#[derive(Clone)]
+pub struct RtRng {
+    pcg: Pcg64,
+    stream: Option<SampleStream>
+}
+
+#[derive(Clone)]
+struct SampleStream {
+    sampler: Shared<dyn Sampler>,
+    pixel: (u32, u32),
+    index: u32,
+    // bounces started so far
+    bounces: u32,
+    // next dimension, and the first one past what the sampler provides now
+    dim: u32,
+    end: u32
+}
+
+impl RtRng {
+    pub fn new(state: u128, stream: u128) -> Self {
+        RtRng{pcg: Pcg64::new(state, stream), stream: None}
+    }
+
+    pub fn set_sampler(&mut self, sampler: Shared<dyn Sampler>) {
+        self.stream = Some(SampleStream{sampler, pixel: (0, 0), index: 0, bounces: 0, dim: 0, end: 0});
+    }
+
+    // the camera dimensions of sample `index` of pixel (i, j) come next
+    pub fn start_sample(&mut self, i: u32, j: u32, index: u32) {
+        if let Some(s) = &mut self.stream {
+            (s.pixel, s.index, s.bounces, s.dim, s.end) = ((i, j), index, 0, 0, CAMERA_DIMS);
+        }
+    }
+
+    // the dimensions of the next bounce come next, however many the
+    // previous one used
+    pub fn start_bounce(&mut self) {
+        if let Some(s) = &mut self.stream {
+            s.dim = CAMERA_DIMS + s.bounces * DIMS_PER_BOUNCE;
+            s.end = if s.bounces < SAMPLED_BOUNCES { s.dim + DIMS_PER_BOUNCE } else { s.dim };
+            s.bounces += 1;
+        }
+    }
+
+    // the number of camera dimension `dim` (see sampler.rs), whichever of
+    // the ones before it were drawn
+    pub fn unif_at(&mut self, dim: u32) -> f64 {
+        if let Some(s) = &mut self.stream {
+            s.dim = dim;
+        }
+        random_unif_1(self)
+    }
+
+    // two numbers in [0, 1) meant to be used together, like the two angles
+    // of a direction: a sampler gives them as one of its 2D points
+    pub fn unif_2d(&mut self) -> (f64, f64) {
+        if let Some(s) = &mut self.stream {
+            s.dim += s.dim % 2;
+        }
+        (random_unif_1(self), random_unif_1(self))
+    }
+
+    fn next_dim(&mut self) -> Option<f64> {
+        let s = self.stream.as_mut()?;
+        if s.dim >= s.end {
+            return None
+        }
+        s.dim += 1;
+        Some(s.sampler.sample(s.pixel.0, s.pixel.1, s.index, s.dim - 1))
+    }
+}
+
+impl RngCore for RtRng {
+    fn next_u32(&mut self) -> u32 {
+        match self.next_dim() {
+            Some(u) => (u * (1u64 << 32) as f64) as u32,
+            None => self.pcg.next_u32()
+        }
+    }
+
+    // gen::<f64>() keeps the top 53 bits, `u` itself
+    fn next_u64(&mut self) -> u64 {
+        match self.next_dim() {
+            Some(u) => (u * 2f64.powi(64)) as u64,
+            None => self.pcg.next_u64()
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.pcg.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.pcg.try_fill_bytes(dest)
+    }
+}
+
+pub const INF: f64 = f64::INFINITY;
 pub const RADS_PER_DEG: f64 = PI/ 180.0;
 
 
@@ -17,12 +122,38 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * RADS_PER_DEG
 }
 
-pub fn random_unif_1(rng: &mut ThreadRng) -> f64 {
-    return rng.gen::<f64>()
+pub fn rng_from_seed(seed: u64) -> RtRng {
+    RtRng{pcg: Pcg64::seed_from_u64(seed), stream: None}
+}
+
+// Independent stream for pixel (i, j): a pixel sees the same random numbers
+// whichever thread or tile renders it.
+pub fn pixel_rng(seed: u64, i: u32, j: u32) -> RtRng {
+    let state = ((splitmix64(seed) as u128) << 64) | splitmix64(!seed) as u128;
+    let stream = ((j as u128) << 32) | i as u128;
+    RtRng::new(state, stream)
+}
+
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// uniform in (0, 1], decided by `values` alone: for code without a generator
+// at hand (Hittable::hit) that still has to give the same image on every run
+pub fn hash_unif(values: &[f64]) -> f64 {
+    let h = values.iter().fold(0x243F_6A88_85A3_08D3, |h, v| splitmix64(h ^ v.to_bits()));
+    ((h >> 11) + 1) as f64 / (1u64 << 53) as f64
+}
+
+pub fn random_unif_1<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    rng.gen::<f64>()
 }
 
-pub fn random_unif(rng: &mut ThreadRng, min: f64, max: f64) -> f64 {
-    return min + (max - min) * random_unif_1(rng)
+pub fn random_unif<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> f64 {
+    min + (max - min) * random_unif_1(rng)
 }
 
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
@@ -0,0 +1,482 @@
+// where the random numbers of a pixel sample come from. By default every
+// number is drawn independently from the pixel's Pcg64 stream; a Sampler
+// instead hands out the coordinates of well spread points, one dimension per
+// number, so that e.g. the 16 pixel positions or the 16 first diffuse
+// bounces of a pixel cover the square evenly instead of clumping.
+//
+// For that to help, a given dimension has to mean the same thing in every
+// sample of a pixel. RtRng lays them out as
+//
+//     0, 1   position in the pixel
+//     2, 3   point on the lens
+//     4      time in the shutter interval
+//     5      hero wavelength, in spectral mode
+//     6 + b * DIMS_PER_BOUNCE ...   bounce b, started with RtRng::start_bounce
+//
+// The time and the wavelength are only drawn when they are needed, and are
+// taken with RtRng::unif_at so they keep their dimension whether the other is
+// drawn or not.
+//
+// Within a bounce, numbers are taken in the order the material and the
+// integrator ask for them, RtRng::unif_2d moving to the next even dimension
+// so that both halves of a 2D sample come from the same 2D point. What a
+// bounce draws beyond its DIMS_PER_BOUNCE, and everything after
+// SAMPLED_BOUNCES bounces, comes from the Pcg64 stream again.
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use crate::rtweekend::{Shared, rng_from_seed, splitmix64};
+
+pub const TIME_DIM: u32 = 4;
+pub const WAVELENGTH_DIM: u32 = 5;
+pub const CAMERA_DIMS: u32 = 6;
+pub const DIMS_PER_BOUNCE: u32 = 8;
+pub const SAMPLED_BOUNCES: u32 = 8;
+const MAX_DIMS: u32 = CAMERA_DIMS + DIMS_PER_BOUNCE * SAMPLED_BOUNCES;
+
+pub trait Sampler: Send + Sync {
+    // coordinate `dim` of sample `index` of pixel (i, j), in [0, 1)
+    fn sample(&self, i: u32, j: u32, index: u32, dim: u32) -> f64;
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SamplerKind {
+    // the pixel's own generator for everything
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise
+}
+
+impl SamplerKind {
+    // None for Independent
+    pub fn sampler(&self, samples_per_pixel: i32, seed: u64) -> Option<Shared<dyn Sampler>> {
+        match self {
+            SamplerKind::Independent => None,
+            SamplerKind::Stratified => Some(Shared::new(StratifiedSampler::new(samples_per_pixel, seed))),
+            SamplerKind::Halton => Some(Shared::new(HaltonSampler{seed})),
+            SamplerKind::Sobol => Some(Shared::new(SobolSampler{seed})),
+            SamplerKind::BlueNoise => Some(Shared::new(BlueNoiseSampler{seed}))
+        }
+    }
+}
+
+
+// correlated multi-jittered sampling, Kensler 2013: the samples of each pair
+// of dimensions fall one per cell of an nx x ny grid, and one per column of
+// the nx * ny finer columns (and rows) too. Every pixel and every pair of
+// dimensions gets its own shuffle of the cells.
+pub struct StratifiedSampler {
+    nx: u32,
+    ny: u32,
+    seed: u64
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: i32, seed: u64) -> Self {
+        let n = samples_per_pixel.max(1) as u32;
+        let nx = (n as f64).sqrt().ceil() as u32;
+        StratifiedSampler{nx, ny: n.div_ceil(nx), seed}
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn sample(&self, i: u32, j: u32, index: u32, dim: u32) -> f64 {
+        let (nx, ny) = (self.nx, self.ny);
+        let p = hash(&[self.seed, i as u64, j as u64, (dim / 2) as u64]) as u32;
+        // more samples than cells (adaptive rounds can't ask for that) start the grid over
+        let s = permute(index % (nx * ny), nx * ny, p.wrapping_mul(0x51633e2d));
+        let (x, y) = (s % nx, s / nx);
+        if dim.is_multiple_of(2) {
+            let sy = permute(y, ny, p.wrapping_mul(0x68bc21eb));
+            let jitter = to_unit(hash(&[p as u64, s as u64, 0]));
+            (x as f64 + (sy as f64 + jitter) / ny as f64) / nx as f64
+        } else {
+            let sx = permute(x, nx, p.wrapping_mul(0x02e5be93));
+            let jitter = to_unit(hash(&[p as u64, s as u64, 1]));
+            (y as f64 + (sx as f64 + jitter) / nx as f64) / ny as f64
+        }
+    }
+}
+
+// Kensler's hash based permutation of 0..len, picked by `p`
+fn permute(mut i: u32, len: u32, p: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break
+        }
+    }
+    (i.wrapping_add(p)) % len
+}
+
+
+// the Halton sequence, dimension d in the base of the d-th prime. Each digit
+// goes through its own random permutation per pixel and dimension: that keeps
+// the points as evenly spread, breaks up the lines the first points of two
+// large bases fall on, and decorrelates the pixels.
+pub struct HaltonSampler {
+    seed: u64
+}
+
+impl Sampler for HaltonSampler {
+    fn sample(&self, i: u32, j: u32, index: u32, dim: u32) -> f64 {
+        let primes = primes();
+        let base = primes[dim as usize % primes.len()];
+        let key = hash(&[self.seed, i as u64, j as u64, dim as u64]);
+        let inv_base = 1.0 / base as f64;
+        let (mut index, mut scale, mut value) = (index, inv_base, 0.0);
+        // digits past 2^-32 no longer change anything
+        for digit_pos in 0..32 / base.ilog2() + 1 {
+            let digit = permute(index % base, base, splitmix64(key ^ digit_pos as u64) as u32);
+            value += digit as f64 * scale;
+            index /= base;
+            scale *= inv_base;
+        }
+        value.min(ONE_MINUS_EPS)
+    }
+}
+
+// enough for every dimension RtRng asks a sampler for
+fn primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        let mut primes: Vec<u32> = vec![];
+        let mut n = 2;
+        while primes.len() < MAX_DIMS as usize {
+            if primes.iter().all(|p| n % p != 0) {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        primes
+    })
+}
+
+
+// the first two dimensions of the Sobol sequence for every pair of
+// dimensions, Owen-scrambled and shuffled differently per pair (Burley 2020,
+// "Practical Hash-based Owen Scrambling"). Any 2^k consecutive samples of a
+// pair are a (0, k, 2)-net: one per cell of any 2^a x 2^b grid of 2^k cells.
+pub struct SobolSampler {
+    seed: u64
+}
+
+impl Sampler for SobolSampler {
+    fn sample(&self, i: u32, j: u32, index: u32, dim: u32) -> f64 {
+        sobol_owen(index, dim, hash(&[self.seed, i as u64, j as u64]))
+    }
+}
+
+fn sobol_owen(index: u32, dim: u32, seed: u64) -> f64 {
+    let shuffled = nested_uniform_scramble(index, hash(&[seed, (dim / 2) as u64]) as u32);
+    let x = if dim.is_multiple_of(2) { shuffled.reverse_bits() } else { sobol_1(shuffled) };
+    let x = nested_uniform_scramble(x, hash(&[seed, dim as u64, 1]) as u32);
+    x as f64 / (1u64 << 32) as f64
+}
+
+// second dimension of the Sobol sequence, as a 32 bit fraction
+fn sobol_1(index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut x = 0;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 == 1 {
+            x ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    x
+}
+
+// Owen scrambling of a 32 bit fraction: each bit flipped or not depending on
+// the bits before it
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+
+// the same Owen-scrambled Sobol points in every pixel, each pixel shifting
+// them (modulo 1) by its value in a blue noise mask, Georgiev & Fajardo 2016.
+// Neighbouring pixels get very different shifts, so their errors differ too
+// and what is left of the noise is fine grained, without clumps.
+pub struct BlueNoiseSampler {
+    seed: u64
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn sample(&self, i: u32, j: u32, index: u32, dim: u32) -> f64 {
+        let size = MASK_SIZE as u64;
+        // every dimension reads the mask at its own offset
+        let offset = hash(&[self.seed, dim as u64]);
+        let x = (i as u64 + offset % size) % size;
+        let y = (j as u64 + (offset >> 32) % size) % size;
+        let shift = blue_noise_mask()[(y * size + x) as usize];
+        (sobol_owen(index, dim, self.seed) + shift).fract().min(ONE_MINUS_EPS)
+    }
+}
+
+const MASK_SIZE: usize = 64;
+
+// values in (0, 1), each of MASK_SIZE^2 levels once, made once on first use
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(void_and_cluster)
+}
+
+// Ulichney 1993: pixels are ranked by adding them one by one where the
+// pattern so far has its largest void (and removing them from its tightest
+// cluster, below the initial pattern), measured on a Gaussian blur that
+// wraps around the edges so the mask tiles
+fn void_and_cluster() -> Vec<f64> {
+    const SIGMA: f64 = 1.5;
+    let n = MASK_SIZE * MASK_SIZE;
+    let kernel: Vec<f64> = (0..n).map(|k| {
+        let (dx, dy) = (k % MASK_SIZE, k / MASK_SIZE);
+        let (dx, dy) = (dx.min(MASK_SIZE - dx) as f64, dy.min(MASK_SIZE - dy) as f64);
+        (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+    }).collect();
+    let splat = |energy: &mut [f64], p: usize, sign: f64| {
+        let (px, py) = (p % MASK_SIZE, p / MASK_SIZE);
+        for (q, e) in energy.iter_mut().enumerate() {
+            let dx = (q % MASK_SIZE + MASK_SIZE - px) % MASK_SIZE;
+            let dy = (q / MASK_SIZE + MASK_SIZE - py) % MASK_SIZE;
+            *e += sign * kernel[dy * MASK_SIZE + dx];
+        }
+    };
+    // the highest energy among the set pixels, or the lowest among the others
+    let extreme = |pattern: &[bool], energy: &[f64], cluster: bool| -> usize {
+        let candidates = (0..n).filter(|&q| pattern[q] == cluster);
+        if cluster {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+        }
+    };
+
+    // a tenth of the pixels at random, then moved from clusters to voids until that changes nothing
+    let mut order: Vec<usize> = (0..n).collect();
+    order.shuffle(&mut rng_from_seed(0));
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0; n];
+    for &p in &order[..n / 10] {
+        pattern[p] = true;
+        splat(&mut energy, p, 1.0);
+    }
+    loop {
+        let cluster = extreme(&pattern, &energy, true);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = extreme(&pattern, &energy, false);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break
+        }
+    }
+
+    let mut rank = vec![0; n];
+    let ones = n / 10;
+    let (mut shrinking, mut shrinking_energy) = (pattern.clone(), energy.clone());
+    for r in (0..ones).rev() {
+        let cluster = extreme(&shrinking, &shrinking_energy, true);
+        shrinking[cluster] = false;
+        splat(&mut shrinking_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    // past half the pixels, the largest void of the set pixels is also the
+    // tightest cluster of the others: the blur of all pixels is uniform
+    for r in ones..n {
+        let void = extreme(&pattern, &energy, false);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+    rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect()
+}
+
+
+const ONE_MINUS_EPS: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9E37_79B9_7F4A_7C15, |h, v| splitmix64(h ^ v))
+}
+
+fn to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use image::Rgb32FImage;
+
+    use super::*;
+    use crate::bvh::BvhNode;
+    use crate::integrator::{Integrator, PathStats, SceneView};
+    use crate::render::{RenderParams, render_scene};
+    use crate::rtweekend::{pixel_rng, random_unif_1, rng_from_seed};
+    use crate::scenes::find_scene;
+
+    // how many of the first `n` samples of the pair (dim, dim + 1) fall in each
+    // cell of a cols x rows grid
+    fn cell_counts(sampler: &dyn Sampler, n: u32, dim: u32, cols: u32, rows: u32) -> Vec<u32> {
+        let mut counts = vec![0; (cols * rows) as usize];
+        for index in 0..n {
+            let x = (sampler.sample(3, 5, index, dim) * cols as f64) as u32;
+            let y = (sampler.sample(3, 5, index, dim + 1) * rows as f64) as u32;
+            counts[(y * cols + x) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_samples_are_stratified() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let sampler = kind.sampler(64, 0).unwrap();
+            // the pixel position and a pair of the third bounce
+            for dim in [0, CAMERA_DIMS + 2 * DIMS_PER_BOUNCE] {
+                assert!(cell_counts(sampler.as_ref(), 64, dim, 8, 8).iter().all(|c| *c == 1), "{:?}", kind);
+                // 1D: one sample per 1/64
+                assert!(cell_counts(sampler.as_ref(), 64, dim, 64, 1).iter().all(|c| *c == 1), "{:?}", kind);
+            }
+        }
+        // Sobol: any grid of 2^k cells, for any 2^k consecutive samples
+        let sobol = SamplerKind::Sobol.sampler(64, 0).unwrap();
+        assert!(cell_counts(sobol.as_ref(), 32, 2, 2, 16).iter().all(|c| *c == 1));
+
+        // Halton: base 2 and 3
+        let halton = SamplerKind::Halton.sampler(64, 0).unwrap();
+        assert!(cell_counts(halton.as_ref(), 64, 0, 64, 1).iter().all(|c| *c == 1));
+        assert!(cell_counts(halton.as_ref(), 27, 0, 1, 27).iter().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn test_bounce_dimensions() {
+        let sampler = SamplerKind::Sobol.sampler(16, 0).unwrap();
+        for index in 0..16 {
+            let mut rng = pixel_rng(0, 3, 5);
+            rng.set_sampler(sampler.clone());
+            rng.start_sample(3, 5, index);
+            let (u, v) = rng.unif_2d();
+            assert_eq!((u, v), (sampler.sample(3, 5, index, 0), sampler.sample(3, 5, index, 1)));
+            // a bounce that draws a varying amount, past its share too
+            rng.start_bounce();
+            for _ in 0..index {
+                random_unif_1(&mut rng);
+            }
+            rng.start_bounce();
+            let first = random_unif_1(&mut rng);
+            let (a, b) = rng.unif_2d();
+            let base = CAMERA_DIMS + DIMS_PER_BOUNCE;
+            assert_eq!(first, sampler.sample(3, 5, index, base));
+            // skips base + 1, to start a pair
+            assert_eq!((a, b), (sampler.sample(3, 5, index, base + 2), sampler.sample(3, 5, index, base + 3)));
+        }
+    }
+
+    #[test]
+    fn test_camera_dimensions() {
+        let sampler = SamplerKind::Sobol.sampler(16, 0).unwrap();
+        for moving in [false, true] {
+            let mut rng = pixel_rng(0, 3, 5);
+            rng.set_sampler(sampler.clone());
+            rng.start_sample(3, 5, 7);
+            rng.unif_2d();
+            if moving {
+                assert_eq!(rng.unif_at(TIME_DIM), sampler.sample(3, 5, 7, TIME_DIM));
+            }
+            // the wavelength has its own dimension, with or without a time before it
+            assert_eq!(rng.unif_at(WAVELENGTH_DIM), sampler.sample(3, 5, 7, WAVELENGTH_DIM));
+            rng.start_bounce();
+            assert_eq!(random_unif_1(&mut rng), sampler.sample(3, 5, 7, CAMERA_DIMS));
+        }
+    }
+
+    #[test]
+    fn test_blue_noise_mask() {
+        let mask = blue_noise_mask();
+        let mut levels: Vec<usize> = mask.iter().map(|m| (m * mask.len() as f64) as usize).collect();
+        levels.sort();
+        assert!(levels.iter().enumerate().all(|(k, l)| k == *l));
+
+        // little low frequency content: the means of 3x3 blocks vary much less
+        // than for white noise, whose variance would be 1 / 12 / 9
+        let block_mean = |x: usize, y: usize| -> f64 {
+            (0..9).map(|k| mask[((y + k / 3) % MASK_SIZE) * MASK_SIZE + (x + k % 3) % MASK_SIZE]).sum::<f64>() / 9.0
+        };
+        let variance = (0..MASK_SIZE * MASK_SIZE)
+            .map(|k| (block_mean(k % MASK_SIZE, k / MASK_SIZE) - 0.5).powi(2))
+            .sum::<f64>() / (MASK_SIZE * MASK_SIZE) as f64;
+        assert!(variance < 0.25 / 12.0 / 9.0, "{}", variance);
+    }
+
+    fn render(kind: SamplerKind, spp: i32, seed: u64) -> Rgb32FImage {
+        let scene = find_scene("two_sphere_world").unwrap();
+        let mut rp = RenderParams::new(scene.aspect_ratio, 32, spp);
+        rp.seed = seed;
+        rp.sampler = kind;
+        let world = BvhNode::new(&(scene.build)(&mut rng_from_seed(0)));
+        let lights = (scene.lights)();
+        let view = SceneView{world: world.as_ref(), lights: &lights, background: &scene.background};
+        let camera = scene.camera.camera(rp.aspect_ratio);
+        render_scene(&view, &camera, Integrator::Recursive(scene.ray_color), &rp, false,
+                     &Mutex::new(PathStats::default())).0
+    }
+
+    #[test]
+    fn test_samplers_lower_error() {
+        let reference = render(SamplerKind::Independent, 512, 1);
+        let mse = |img: &Rgb32FImage| -> f64 {
+            img.pixels().zip(reference.pixels())
+                .flat_map(|(p, q)| (0..3).map(move |c| (p[c] as f64 - q[c] as f64).powi(2)))
+                .sum::<f64>() / (3 * img.width() * img.height()) as f64
+        };
+        let independent = mse(&render(SamplerKind::Independent, 16, 2));
+        for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+            let error = mse(&render(kind, 16, 2));
+            assert!(error < 0.8 * independent, "{:?}: {} vs {}", kind, error, independent);
+        }
+    }
+}
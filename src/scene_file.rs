@@ -0,0 +1,1218 @@
+// declarative scene files (TOML): camera, named materials, objects and render settings.
+//
+//     [render]
+//     aspect_ratio = 1.5
+//     width = 600
+//     samples_per_pixel = 100
+//     sampler = "sobol"
+//     denoise = true
+//
+//     [camera]
+//     lookfrom = [13, 2, 3]
+//     lookat = [0, 0, 0]
+//     vfov = 20
+//     aperture = 0.1
+//
+//     [textures.checks]
+//     type = "checker"
+//     scale = 0.5
+//     even = [0.2, 0.3, 0.1]
+//     odd = [0.9, 0.9, 0.9]
+//
+//     [materials.ground]
+//     type = "lambertian"
+//     albedo = "checks"
+//
+//     [[objects]]
+//     type = "sphere"
+//     center = [0, -1000, 0]
+//     radius = 1000
+//     material = "ground"
+//
+// `background = [0, 0, 0]` at the top turns off the sky, for scenes lit by
+// `diffuse_light` materials only; `background = { environment = "sky.hdr",
+// rotation = 90, intensity = 2 }` lights the scene with an equirectangular
+// HDR image (.hdr or .exr, path relative to the scene file), and
+// `background = { sky = { sun_elevation = 20, sun_azimuth = 90,
+// turbidity = 3 } }` with daylight and a sun. Colors can be
+// given as `[r, g, b]` or as the name of a texture: checker,
+// image (path relative to the scene file) or Perlin noise. Objects are
+// spheres, triangles, quads (corner `q` and edges `u`, `v`), axis aligned
+// boxes or OBJ meshes; a mesh's `usemtl` names are mapped to the
+// file's materials with a `usemtl` table. Any object can be placed with a list
+// of steps applied in order, e.g.
+//
+//     transform = [{ scale = 2 }, { rotate_y = 15 }, { translate = [265, 0, 295] }]
+//
+// Meshes are loaded once per path and material mapping, further objects with
+// the same ones are instances of the same triangles. An object with a
+// `density` is filled with fog of that density instead, its material
+// `isotropic`.
+//
+// Things can move during the time interval [0, 1]: a sphere with a
+// `center_end` goes there in a straight line, and an object with a
+// `transform_end` (the same steps as `transform`, other amounts) is blended
+// from one to the other. They only blur with an open camera `shutter`, e.g.
+// `shutter = [0, 1]`.
+//
+// Spheres, quads and boxes with a `diffuse_light` material that are neither
+// moving, transformed nor filled with fog are also sampled directly as
+// lights; other emitters still light the scene, only with more noise.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::bvh::BvhNode;
+use crate::camera::CameraParams;
+use crate::environment::EnvironmentMap;
+use crate::hittable::{HittableList, SharedHittable, hittable_list};
+use crate::integrator::Background;
+use crate::material::{Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, RoughDielectric};
+use crate::medium::ConstantMedium;
+use crate::obj::load_obj;
+use crate::quad::{Quad, box_};
+use crate::principled::{Principled, PrincipledParams, constant};
+use crate::rtweekend::{Shared, rng_from_seed};
+use crate::sampler::SamplerKind;
+use crate::sky::Sky;
+use crate::spectrum::{Ior, LAMBDA_MAX, LAMBDA_MIN};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseKind, NoiseTexture, SolidColor, Texture};
+use crate::transform::{Transform, TransformStep, compose};
+use crate::triangle::Triangle;
+use crate::vec3::{Vec3, vec3_};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(String, std::io::Error),
+    // syntax errors and wrong / missing fields, with line and column
+    Parse(String, toml::de::Error),
+    // well formed file describing something that can't be built, and
+    // wrong / missing fields of objects, with their line
+    Invalid(String, String)
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io(path, err) => write!(f, "{}: {}", path, err),
+            SceneFileError::Parse(path, err) => write!(f, "{}: {}", path, err),
+            SceneFileError::Invalid(path, msg) => write!(f, "{}: {}", path, msg)
+        }
+    }
+}
+
+// what a scene file turns into
+pub struct LoadedScene {
+    pub world: HittableList,
+    // the emitters of `world` the integrator can sample
+    pub lights: HittableList,
+    pub camera: CameraParams,
+    pub background: Background,
+    pub render: RenderSettings
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSettings {
+    pub aspect_ratio: Option<f64>,
+    pub width: Option<u32>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub denoise: Option<bool>,
+    // samples_per_pixel is the maximum with adaptive sampling
+    pub adaptive: Option<bool>,
+    pub min_samples_per_pixel: Option<i32>,
+    pub adaptive_threshold: Option<f64>,
+    pub sampler: Option<SamplerKind>,
+    pub spectral: Option<bool>
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    render: RenderSettings,
+    camera: CameraDesc,
+    #[serde(default)]
+    background: BackgroundDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    // see PlacedObjectDesc::from_table
+    #[serde(default)]
+    objects: Vec<toml::Spanned<toml::Table>>
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    focus_dist: Option<f64>,
+    // open and close times, closed at 0 by default
+    #[serde(default)]
+    shutter: [f64; 2]
+}
+
+fn default_vup() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+// `"gradient"` (the default), `[r, g, b]`, an environment map or a sky
+#[derive(Deserialize, Debug)]
+#[serde(try_from = "toml::Value")]
+enum BackgroundDesc {
+    Rgb([f64; 3]),
+    Named(BackgroundName),
+    Environment(EnvironmentDesc),
+    Sky(SkyBackgroundDesc)
+}
+
+const BACKGROUND_EXPECTING: &str =
+    "\"gradient\", an [r, g, b] color, { environment = \"<path>\" } or { sky = { ... } }";
+
+// not #[serde(untagged)]: that would take a misspelled key for a table of
+// another kind and drop it. Tables are told apart by the key each must have,
+// then read with deny_unknown_fields.
+impl TryFrom<toml::Value> for BackgroundDesc {
+    type Error = String;
+
+    fn try_from(value: toml::Value) -> Result<Self, String> {
+        if value.get("environment").is_some() {
+            value.try_into().map(BackgroundDesc::Environment).map_err(|err| err.to_string())
+        } else if value.get("sky").is_some() {
+            value.try_into().map(BackgroundDesc::Sky).map_err(|err| err.to_string())
+        } else if value.is_array() {
+            value.try_into().map(BackgroundDesc::Rgb).map_err(|_| format!("expected {}", BACKGROUND_EXPECTING))
+        } else {
+            value.try_into().map(BackgroundDesc::Named).map_err(|_| format!("expected {}", BACKGROUND_EXPECTING))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDesc {
+    // .hdr or .exr, relative to the scene file
+    environment: String,
+    // degrees about +y
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "default_intensity")]
+    intensity: f64
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SkyBackgroundDesc {
+    sky: SkyDesc
+}
+
+// in degrees, see Sky::new
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SkyDesc {
+    #[serde(default = "default_sun_elevation")]
+    sun_elevation: f64,
+    #[serde(default)]
+    sun_azimuth: f64,
+    #[serde(default = "default_turbidity")]
+    turbidity: f64
+}
+
+fn default_sun_elevation() -> f64 {
+    45.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum BackgroundName {
+    Gradient
+}
+
+impl Default for BackgroundDesc {
+    fn default() -> Self {
+        BackgroundDesc::Named(BackgroundName::Gradient)
+    }
+}
+
+// `[r, g, b]` or the name of a texture
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "an [r, g, b] color or the name of a texture")]
+enum ColorDesc {
+    Rgb([f64; 3]),
+    Texture(String)
+}
+
+// `1.5`, `{ cauchy = [a, b] }` or `{ sellmeier = { b = [b1, b2, b3], c = [c1, c2, c3] } }`,
+// with wavelengths in µm
+#[derive(Deserialize, Debug)]
+#[serde(try_from = "toml::Value")]
+enum IorDesc {
+    Constant(f64),
+    Cauchy(CauchyDesc),
+    Sellmeier(SellmeierIorDesc)
+}
+
+const IOR_EXPECTING: &str = "a number, { cauchy = [a, b] } or { sellmeier = { b = [...], c = [...] } }";
+
+// not untagged, for the same reason as BackgroundDesc
+impl TryFrom<toml::Value> for IorDesc {
+    type Error = String;
+
+    fn try_from(value: toml::Value) -> Result<Self, String> {
+        if value.get("cauchy").is_some() {
+            value.try_into().map(IorDesc::Cauchy).map_err(|err| err.to_string())
+        } else if value.get("sellmeier").is_some() {
+            value.try_into().map(IorDesc::Sellmeier).map_err(|err| err.to_string())
+        } else {
+            value.try_into().map(IorDesc::Constant).map_err(|_| format!("expected {}", IOR_EXPECTING))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CauchyDesc {
+    cauchy: [f64; 2]
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SellmeierIorDesc {
+    sellmeier: SellmeierDesc
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDesc {
+    b: [f64; 3],
+    c: [f64; 3]
+}
+
+// a number from 0 to 1 or the name of a texture, whose channels are averaged
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "a number from 0 to 1 or the name of a texture")]
+enum ValueDesc {
+    Number(f64),
+    Texture(String)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum TextureDesc {
+    Checker {
+        // side of the cubes
+        scale: f64,
+        even: ColorDesc,
+        odd: ColorDesc
+    },
+    Image {
+        // relative to the scene file
+        path: String,
+        // true for data such as roughness maps, which aren't sRGB encoded
+        #[serde(default)]
+        linear: bool
+    },
+    Noise {
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default)]
+        kind: NoiseKindDesc,
+        // picks the random gradients, so that the pattern doesn't depend on --seed
+        #[serde(default)]
+        seed: u64
+    }
+}
+
+fn default_noise_scale() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum NoiseKindDesc {
+    #[default]
+    Smooth,
+    Turbulence,
+    Marble
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo: ColorDesc },
+    Metal {
+        albedo: ColorDesc,
+        #[serde(default)]
+        fuzz: f64
+    },
+    Dielectric {
+        ior: IorDesc,
+        // 0 for clear glass, up to 1 for frosted
+        #[serde(default)]
+        roughness: f64
+    },
+    // one of the named metals, or the complex index of refraction of another
+    Conductor {
+        metal: Option<String>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64
+    },
+    // every parameter is optional, see PrincipledParams for the defaults
+    Principled {
+        base_color: Option<ColorDesc>,
+        metallic: Option<ValueDesc>,
+        roughness: Option<ValueDesc>,
+        specular: Option<ValueDesc>,
+        sheen: Option<ValueDesc>,
+        clearcoat: Option<ValueDesc>,
+        transmission: Option<ValueDesc>,
+        anisotropy: Option<ValueDesc>
+    },
+    #[serde(rename = "diffuse_light")]
+    DiffuseLight { emit: ColorDesc },
+    Isotropic { albedo: ColorDesc }
+}
+
+#[derive(Debug)]
+struct PlacedObjectDesc {
+    object: ObjectDesc,
+    transform: Vec<TransformStepDesc>,
+    transform_end: Vec<TransformStepDesc>,
+    density: Option<f64>
+}
+
+// the fields any object can have besides its own
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PlacementDesc {
+    #[serde(default)]
+    transform: Vec<TransformStepDesc>,
+    // where `transform` ends up at time 1
+    #[serde(default)]
+    transform_end: Vec<TransformStepDesc>,
+    // fills the object with fog of this density
+    density: Option<f64>
+}
+const PLACEMENT_FIELDS: [&str; 3] = ["transform", "transform_end", "density"];
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformStepDesc {
+    Translate([f64; 3]),
+    Scale(ScaleDesc),
+    // degrees
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate { axis: [f64; 3], degrees: f64 }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "a number or an [x, y, z] array")]
+enum ScaleDesc {
+    Uniform(f64),
+    PerAxis([f64; 3])
+}
+
+// the `type` of an object
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ObjectKind {
+    Sphere,
+    Triangle,
+    Quad,
+    Box,
+    Mesh
+}
+
+#[derive(Debug)]
+enum ObjectDesc {
+    Sphere(SphereDesc),
+    Triangle(TriangleDesc),
+    Quad(QuadDesc),
+    Box(BoxDesc),
+    Mesh(MeshDesc)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SphereDesc {
+    center: [f64; 3],
+    radius: f64,
+    material: String,
+    // center at time 1, for a moving sphere
+    center_end: Option<[f64; 3]>
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TriangleDesc {
+    vertices: [[f64; 3]; 3],
+    material: String
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct QuadDesc {
+    q: [f64; 3],
+    u: [f64; 3],
+    v: [f64; 3],
+    material: String
+}
+
+// opposite corners, min below max on every axis
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct BoxDesc {
+    min: [f64; 3],
+    max: [f64; 3],
+    material: String
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct MeshDesc {
+    // relative to the scene file
+    path: String,
+    material: String,
+    #[serde(default)]
+    usemtl: BTreeMap<String, String>
+}
+
+fn vec3_from(v: &[f64; 3]) -> Vec3 {
+    vec3_(v[0], v[1], v[2])
+}
+
+fn check_roughness(roughness: f64, material: &str) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&roughness) {
+        Ok(roughness)
+    } else {
+        Err(format!("materials.{}.roughness: expected a number from 0 to 1, got {}", material, roughness))
+    }
+}
+
+impl ObjectDesc {
+    fn material(&self) -> &str {
+        match self {
+            ObjectDesc::Sphere(SphereDesc{material, ..}) | ObjectDesc::Triangle(TriangleDesc{material, ..})
+            | ObjectDesc::Quad(QuadDesc{material, ..}) | ObjectDesc::Box(BoxDesc{material, ..})
+            | ObjectDesc::Mesh(MeshDesc{material, ..}) => material
+        }
+    }
+}
+
+impl PlacedObjectDesc {
+    // an `[[objects]]` entry: its `type` first, then its own fields and the
+    // placement, each checked for unknown fields. Serde's internally tagged
+    // enums would report a wrong field as a bad `type` and let unknown ones through.
+    fn from_table(table: &toml::Table) -> Result<Self, toml::de::Error> {
+        let mut fields = table.clone();
+        let kind: ObjectKind = fields.remove("type")
+            .ok_or_else(|| <toml::de::Error as serde::de::Error>::missing_field("type"))?
+            .try_into()?;
+        let placement: toml::Table = PLACEMENT_FIELDS.iter()
+            .filter_map(|field| fields.remove(*field).map(|value| (field.to_string(), value)))
+            .collect();
+        let PlacementDesc{transform, transform_end, density} = toml::Value::Table(placement).try_into()?;
+        let fields = toml::Value::Table(fields);
+        let object = match kind {
+            ObjectKind::Sphere => ObjectDesc::Sphere(fields.try_into()?),
+            ObjectKind::Triangle => ObjectDesc::Triangle(fields.try_into()?),
+            ObjectKind::Quad => ObjectDesc::Quad(fields.try_into()?),
+            ObjectKind::Box => ObjectDesc::Box(fields.try_into()?),
+            ObjectKind::Mesh => ObjectDesc::Mesh(fields.try_into()?)
+        };
+        Ok(PlacedObjectDesc{object, transform, transform_end, density})
+    }
+}
+
+fn transform_steps(steps: &[TransformStepDesc]) -> Vec<TransformStep> {
+    steps.iter().map(|step| match step {
+        TransformStepDesc::Translate(offset) => TransformStep::Translate(vec3_from(offset)),
+        TransformStepDesc::Scale(ScaleDesc::Uniform(f)) => TransformStep::Scale(vec3_(*f, *f, *f)),
+        TransformStepDesc::Scale(ScaleDesc::PerAxis(f)) => TransformStep::Scale(vec3_from(f)),
+        TransformStepDesc::RotateX(degrees) => TransformStep::Rotate(vec3_(1., 0., 0.), *degrees),
+        TransformStepDesc::RotateY(degrees) => TransformStep::Rotate(vec3_(0., 1., 0.), *degrees),
+        TransformStepDesc::RotateZ(degrees) => TransformStep::Rotate(vec3_(0., 0., 1.), *degrees),
+        TransformStepDesc::Rotate{axis, degrees} => TransformStep::Rotate(vec3_from(axis), *degrees)
+    }).collect()
+}
+
+// `field` is only used in error messages
+fn check_transform_steps(steps: &[TransformStep], field: &str) -> Result<(), String> {
+    for step in steps {
+        match step {
+            TransformStep::Rotate(axis, _) if axis.near_zero() => {
+                return Err(format!("{}: rotation axis is zero", field))
+            },
+            TransformStep::Scale(f) if f.x == 0.0 || f.y == 0.0 || f.z == 0.0 => {
+                return Err(format!("{}: flattens the object (zero scale)", field))
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn load_scene_file(path: &str) -> Result<LoadedScene, SceneFileError> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| SceneFileError::Io(path.to_string(), err))?;
+    parse_scene(&text, path)
+}
+
+// `origin` is only used in error messages
+pub fn parse_scene(text: &str, origin: &str) -> Result<LoadedScene, SceneFileError> {
+    let desc: SceneDesc = toml::from_str(text)
+        .map_err(|err| SceneFileError::Parse(origin.to_string(), err))?;
+    let invalid = |msg: String| SceneFileError::Invalid(origin.to_string(), msg);
+    let objects = desc.objects.iter().enumerate().map(|(k, table)| {
+        PlacedObjectDesc::from_table(table.get_ref()).map_err(|err| {
+            let line = text[..table.span().start].matches('\n').count() + 1;
+            invalid(format!("objects[{}] at line {}: {}", k, line, err.to_string().trim().replace('\n', " ")))
+        })
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    let mut textures = TextureBuilder{
+        descs: &desc.textures, origin, built: BTreeMap::new(), in_progress: BTreeSet::new()
+    };
+    let mut materials: BTreeMap<&str, Shared<dyn Material>> = BTreeMap::new();
+    for (name, mat) in &desc.materials {
+        let material = match mat {
+            MaterialDesc::Lambertian{albedo} => {
+                Lambertian::with_texture(textures.color(albedo, &format!("materials.{}.albedo", name))?)
+            },
+            MaterialDesc::Metal{albedo, fuzz} => {
+                let albedo = textures.color(albedo, &format!("materials.{}.albedo", name))?;
+                Metal::with_texture(albedo, *fuzz)
+            },
+            MaterialDesc::Dielectric{ior, roughness} => {
+                let ior = match ior {
+                    IorDesc::Constant(eta) => Ior::Constant(*eta),
+                    IorDesc::Cauchy(CauchyDesc{cauchy: [a, b]}) => Ior::Cauchy{a: *a, b: *b},
+                    IorDesc::Sellmeier(SellmeierIorDesc{sellmeier}) => Ior::Sellmeier{b: sellmeier.b, c: sellmeier.c}
+                };
+                // checked across the wavelengths that are traced: between
+                // the ends unless a Sellmeier term has its pole there
+                if let Ior::Sellmeier{c, ..} = &ior {
+                    let visible = (LAMBDA_MIN / 1000.0).powi(2)..=(LAMBDA_MAX / 1000.0).powi(2);
+                    if let Some(pole) = c.iter().find(|c| visible.contains(*c)) {
+                        return Err(invalid(format!("materials.{}.ior: sellmeier c = {} puts a pole at {:.0} nm, \
+                                                    inside the traced wavelengths {} to {} nm",
+                                                   name, pole, 1000.0 * pole.sqrt(), LAMBDA_MIN, LAMBDA_MAX)))
+                    }
+                }
+                let eta = ior.at(LAMBDA_MIN).min(ior.at(LAMBDA_MAX));
+                if eta.is_nan() || eta <= 0.0 {
+                    return Err(invalid(format!("materials.{}.ior: expected a positive index of refraction, got {}",
+                                               name, eta)))
+                }
+                // the book's glass unless it is rough
+                if check_roughness(*roughness, name).map_err(invalid)? > 0.0 {
+                    RoughDielectric::with_ior(ior, *roughness)
+                } else {
+                    Dielectric::with_ior(ior)
+                }
+            },
+            MaterialDesc::Conductor{metal, eta, k, roughness} => {
+                let roughness = check_roughness(*roughness, name).map_err(invalid)?;
+                match (metal, eta, k) {
+                    (Some(metal), None, None) => Conductor::metal(metal, roughness).ok_or_else(|| {
+                        invalid(format!("materials.{}.metal: unknown metal `{}`, expected one of: {}",
+                                        name, metal, Conductor::metal_names().join(", ")))
+                    })?,
+                    (None, Some(eta), Some(k)) => Conductor::new(vec3_from(eta), vec3_from(k), roughness),
+                    _ => return Err(invalid(format!("materials.{}: expected either `metal` or both `eta` and `k`",
+                                                    name)))
+                }
+            },
+            MaterialDesc::Principled{base_color, metallic, roughness, specular, sheen, clearcoat, transmission,
+                                     anisotropy} => {
+                let mut params = PrincipledParams::default();
+                if let Some(base_color) = base_color {
+                    params.base_color = textures.color(base_color, &format!("materials.{}.base_color", name))?;
+                }
+                for (desc, param, field) in [(metallic, &mut params.metallic, "metallic"),
+                                             (roughness, &mut params.roughness, "roughness"),
+                                             (specular, &mut params.specular, "specular"),
+                                             (sheen, &mut params.sheen, "sheen"),
+                                             (clearcoat, &mut params.clearcoat, "clearcoat"),
+                                             (transmission, &mut params.transmission, "transmission"),
+                                             (anisotropy, &mut params.anisotropy, "anisotropy")] {
+                    if let Some(desc) = desc {
+                        *param = textures.value(desc, &format!("materials.{}.{}", name, field))?;
+                    }
+                }
+                Principled::new(params)
+            },
+            MaterialDesc::DiffuseLight{emit} => {
+                DiffuseLight::with_texture(textures.color(emit, &format!("materials.{}.emit", name))?)
+            },
+            MaterialDesc::Isotropic{albedo} => {
+                Isotropic::with_texture(textures.color(albedo, &format!("materials.{}.albedo", name))?)
+            }
+        };
+        materials.insert(name, material);
+    }
+
+    let find_material = |k: usize, field: &str, name: &str| {
+        materials.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = materials.keys().copied().collect();
+            invalid(format!("objects[{}].{}: unknown material `{}`, expected one of: {}",
+                            k, field, name, known.join(", ")))
+        })
+    };
+
+    let mut world = hittable_list(vec![]);
+    let mut lights = hittable_list(vec![]);
+    let mut meshes: HashMap<(&String, &String, &BTreeMap<String, String>), SharedHittable> = HashMap::new();
+    for (k, placed) in objects.iter().enumerate() {
+        let object: SharedHittable = match &placed.object {
+            ObjectDesc::Sphere(SphereDesc{center, radius, material, center_end}) => {
+                let material = find_material(k, "material", material)?;
+                match center_end {
+                    Some(end) => MovingSphere::new(vec3_from(center), vec3_from(end), 0.0, 1.0, *radius, material),
+                    None => Sphere::new(vec3_from(center), *radius, material)
+                }
+            },
+            ObjectDesc::Triangle(TriangleDesc{vertices, material}) => {
+                let material = find_material(k, "material", material)?;
+                Triangle::new(vec3_from(&vertices[0]), vec3_from(&vertices[1]),
+                              vec3_from(&vertices[2]), material)
+            },
+            ObjectDesc::Quad(QuadDesc{q, u, v, material}) => {
+                let material = find_material(k, "material", material)?;
+                if vec3_from(u).cross(&vec3_from(v)).near_zero() {
+                    return Err(invalid(format!("objects[{}]: edges u and v are parallel", k)))
+                }
+                Quad::new(vec3_from(q), vec3_from(u), vec3_from(v), material)
+            },
+            ObjectDesc::Box(BoxDesc{min, max, material}) => {
+                let material = find_material(k, "material", material)?;
+                if (0..3).any(|axis| max[axis] <= min[axis]) {
+                    return Err(invalid(format!("objects[{}]: max {:?} is not above min {:?} on every axis",
+                                               k, max, min)))
+                }
+                box_(&vec3_from(min), &vec3_from(max), material)
+            },
+            ObjectDesc::Mesh(MeshDesc{path, material, usemtl}) => {
+                if let Some(mesh) = meshes.get(&(path, material, usemtl)) {
+                    mesh.clone()
+                } else {
+                    let default_material = find_material(k, "material", material)?;
+                    let mut mesh_materials = HashMap::new();
+                    for (obj_name, name) in usemtl {
+                        let field = format!("usemtl.{}", obj_name);
+                        mesh_materials.insert(obj_name.clone(), find_material(k, &field, name)?);
+                    }
+                    let obj_path = Path::new(origin).parent().unwrap_or(Path::new("")).join(path);
+                    let mesh = load_obj(&obj_path.to_string_lossy(), &mesh_materials, &default_material)
+                        .map_err(|err| invalid(format!("objects[{}].path: {}", k, err)))?;
+                    if mesh.objects.is_empty() {
+                        return Err(invalid(format!("objects[{}].path: {} has no faces", k, path)))
+                    }
+                    let mesh: SharedHittable = BvhNode::new(&mesh);
+                    meshes.insert((path, material, usemtl), mesh.clone());
+                    mesh
+                }
+            }
+        };
+
+        let is_light = matches!(desc.materials.get(placed.object.material()), Some(MaterialDesc::DiffuseLight{..}));
+        let can_sample = matches!(placed.object, ObjectDesc::Sphere(SphereDesc{center_end: None, ..})
+                                                 | ObjectDesc::Quad(_) | ObjectDesc::Box(_));
+        if is_light && can_sample && placed.transform.is_empty() && placed.transform_end.is_empty()
+            && placed.density.is_none() {
+            lights.add(object.clone());
+        }
+
+        let start = transform_steps(&placed.transform);
+        let end = transform_steps(&placed.transform_end);
+        check_transform_steps(&start, &format!("objects[{}].transform", k)).map_err(invalid)?;
+        check_transform_steps(&end, &format!("objects[{}].transform_end", k)).map_err(invalid)?;
+        let placed_object: SharedHittable = if end.is_empty() {
+            if start.is_empty() { object } else { Transform::new(object, compose(&start)) }
+        } else {
+            let same_steps = start.len() == end.len() && start.iter().zip(&end).all(|(a, b)| a.lerp(b, 0.).is_some());
+            if !same_steps {
+                return Err(invalid(format!("objects[{}].transform_end: expected the same kinds of steps \
+                                            as transform, in the same order", k)))
+            }
+            Transform::animated(object, start, end, 0.0, 1.0)
+        };
+
+        match placed.density {
+            None => world.add(placed_object),
+            Some(density) if density > 0.0 => {
+                // the fog scatters light evenly in all directions, whatever the
+                // material would do at a surface
+                let name = placed.object.material();
+                if !matches!(desc.materials.get(name), Some(MaterialDesc::Isotropic{..}) | None) {
+                    return Err(invalid(format!("objects[{}].material: `{}` is not isotropic, \
+                                                which an object with a density needs", k, name)))
+                }
+                let phase_function = find_material(k, "material", name)?;
+                world.add(ConstantMedium::new(placed_object, density, phase_function));
+            },
+            Some(density) => {
+                return Err(invalid(format!("objects[{}].density: expected a positive number, got {}", k, density)))
+            }
+        }
+    }
+
+    let cam = &desc.camera;
+    let camera = CameraParams{
+        lookfrom: vec3_from(&cam.lookfrom),
+        lookat: vec3_from(&cam.lookat),
+        vup: vec3_from(&cam.vup),
+        vfov_deg: cam.vfov,
+        aperture: cam.aperture,
+        focus_dist: cam.focus_dist,
+        shutter_open: cam.shutter[0],
+        shutter_close: cam.shutter[1]
+    };
+    if cam.shutter[1] < cam.shutter[0] {
+        return Err(invalid(format!("camera.shutter: closes at {} before it opens at {}",
+                                   cam.shutter[1], cam.shutter[0])))
+    }
+
+    let background = match desc.background {
+        BackgroundDesc::Rgb(rgb) => Background::Solid(vec3_from(&rgb)),
+        BackgroundDesc::Named(BackgroundName::Gradient) => Background::Gradient,
+        BackgroundDesc::Environment(EnvironmentDesc{environment, rotation, intensity}) => {
+            let env_path = Path::new(origin).parent().unwrap_or(Path::new("")).join(environment);
+            let env_path = env_path.to_string_lossy();
+            let env = EnvironmentMap::load(&env_path, rotation, intensity)
+                .map_err(|err| invalid(format!("background.environment: {}: {}", env_path, err)))?;
+            Background::Environment(Shared::new(env))
+        },
+        BackgroundDesc::Sky(SkyBackgroundDesc{sky}) => {
+            let sky = Sky::new(sky.sun_elevation, sky.sun_azimuth, sky.turbidity)
+                .map_err(|err| invalid(format!("background.sky: {}", err)))?;
+            Background::Sky(Shared::new(sky))
+        }
+    };
+
+    Ok(LoadedScene{world, lights, camera, background, render: desc.render})
+}
+
+// builds the textures on demand, so checkers can refer to textures defined anywhere in the file
+struct TextureBuilder<'a> {
+    descs: &'a BTreeMap<String, TextureDesc>,
+    origin: &'a str,
+    built: BTreeMap<&'a str, Shared<dyn Texture>>,
+    // names being built, to catch checkers that contain themselves
+    in_progress: BTreeSet<&'a str>
+}
+
+impl<'a> TextureBuilder<'a> {
+    fn invalid(&self, msg: String) -> SceneFileError {
+        SceneFileError::Invalid(self.origin.to_string(), msg)
+    }
+
+    // `field` is only used in error messages
+    fn color(&mut self, desc: &ColorDesc, field: &str) -> Result<Shared<dyn Texture>, SceneFileError> {
+        match desc {
+            ColorDesc::Rgb(rgb) => Ok(SolidColor::new(vec3_from(rgb))),
+            ColorDesc::Texture(name) => self.texture(name, field)
+        }
+    }
+
+    fn value(&mut self, desc: &ValueDesc, field: &str) -> Result<Shared<dyn Texture>, SceneFileError> {
+        match desc {
+            ValueDesc::Number(x) if (0.0..=1.0).contains(x) => Ok(constant(*x)),
+            ValueDesc::Number(x) => Err(self.invalid(format!("{}: expected a number from 0 to 1, got {}", field, x))),
+            ValueDesc::Texture(name) => self.texture(name, field)
+        }
+    }
+
+    fn texture(&mut self, name: &str, field: &str) -> Result<Shared<dyn Texture>, SceneFileError> {
+        let (name, desc) = match self.descs.get_key_value(name) {
+            Some((name, desc)) => (name.as_str(), desc),
+            None => {
+                let known: Vec<&str> = self.descs.keys().map(|k| k.as_str()).collect();
+                return Err(self.invalid(format!("{}: unknown texture `{}`, expected one of: {}",
+                                                field, name, known.join(", "))))
+            }
+        };
+        if let Some(texture) = self.built.get(name) {
+            return Ok(texture.clone())
+        }
+        if !self.in_progress.insert(name) {
+            return Err(self.invalid(format!("{}: texture `{}` contains itself", field, name)))
+        }
+
+        let texture = match desc {
+            TextureDesc::Checker{scale, even, odd} => {
+                if *scale <= 0.0 {
+                    return Err(self.invalid(format!("textures.{}.scale: expected a positive number, got {}",
+                                                    name, scale)))
+                }
+                let even = self.color(even, &format!("textures.{}.even", name))?;
+                let odd = self.color(odd, &format!("textures.{}.odd", name))?;
+                CheckerTexture::new(*scale, even, odd)
+            },
+            TextureDesc::Image{path, linear} => {
+                let img_path = Path::new(self.origin).parent().unwrap_or(Path::new("")).join(path);
+                let img_path = img_path.to_string_lossy();
+                ImageTexture::load(&img_path, !linear)
+                    .map_err(|err| self.invalid(format!("textures.{}.path: {}: {}", name, img_path, err)))?
+            },
+            TextureDesc::Noise{scale, kind, seed} => {
+                let kind = match kind {
+                    NoiseKindDesc::Smooth => NoiseKind::Smooth,
+                    NoiseKindDesc::Turbulence => NoiseKind::Turbulence,
+                    NoiseKindDesc::Marble => NoiseKind::Marble
+                };
+                NoiseTexture::new(&mut rng_from_seed(*seed), *scale, kind)
+            }
+        };
+        self.in_progress.remove(name);
+        self.built.insert(name, texture.clone());
+        Ok(texture)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DisplaySettings;
+    use crate::hittable::HitRecord;
+    use crate::output::{OutputFormat, save_image};
+    use crate::ray::Ray;
+    use crate::rtweekend::INF;
+    use crate::vec3::point3;
+
+    const CAMERA: &str = "[camera]\nlookfrom = [0, 0, 0]\nlookat = [0, 0, -1]\nvfov = 90\n";
+
+    fn error_message(text: &str) -> String {
+        match parse_scene(text, "test.toml") {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string()
+        }
+    }
+
+    #[test]
+    fn test_example_scene_loads() {
+        let scene = load_scene_file("scenes/four_spheres.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 5);
+        assert_eq!(scene.render.width, Some(400));
+        assert_eq!(scene.camera.vfov_deg, 20.0);
+    }
+
+    #[test]
+    fn test_microfacet_materials() {
+        let scene = load_scene_file("scenes/microfacets.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 6);
+        assert!(matches!(scene.background, Background::Sky(_)));
+
+        let material = |desc: &str| {
+            parse_scene(&format!("{}\n[materials.m]\n{}\n", CAMERA, desc), "test.toml").map(|_| ())
+                .map_err(|err| err.to_string())
+        };
+        assert!(material("type = \"conductor\"\nmetal = \"copper\"").is_ok());
+        assert!(material("type = \"conductor\"\neta = [0.2, 0.9, 1.1]\nk = [3.9, 2.5, 2.1]\nroughness = 1").is_ok());
+        let msg = material("type = \"conductor\"\nmetal = \"tin\"").unwrap_err();
+        assert!(msg.contains("materials.m.metal: unknown metal `tin`, expected one of: gold, copper, aluminum"),
+                "{}", msg);
+        let msg = material("type = \"conductor\"\neta = [0.2, 0.9, 1.1]").unwrap_err();
+        assert!(msg.contains("expected either `metal` or both `eta` and `k`"), "{}", msg);
+        let msg = material("type = \"dielectric\"\nior = 1.5\nroughness = 2").unwrap_err();
+        assert!(msg.contains("materials.m.roughness: expected a number from 0 to 1, got 2"), "{}", msg);
+    }
+
+    #[test]
+    fn test_dispersive_glass() {
+        let scene = load_scene_file("scenes/prism.toml").unwrap();
+        assert_eq!(scene.render.spectral, Some(true));
+
+        let material = |desc: &str| {
+            parse_scene(&format!("{}\n[materials.m]\ntype = \"dielectric\"\n{}\n", CAMERA, desc), "test.toml")
+                .map(|_| ()).map_err(|err| err.to_string())
+        };
+        assert!(material("ior = { cauchy = [1.5, 0.004] }\nroughness = 0.2").is_ok());
+        assert!(material("ior = { sellmeier = { b = [1.04, 0.23, 1.01], c = [0.006, 0.02, 103.6] } }").is_ok());
+        let msg = material("ior = -1").unwrap_err();
+        assert!(msg.contains("materials.m.ior: expected a positive index of refraction, got -1"), "{}", msg);
+        let msg = material("ior = { sellmeier = { b = [1.04, 0.23, 1.01], c = [0.006, 0.25, 103.6] } }").unwrap_err();
+        assert!(msg.contains("materials.m.ior: sellmeier c = 0.25 puts a pole at 500 nm"), "{}", msg);
+        let msg = material("ior = { cauchy = [1.5] }").unwrap_err();
+        assert!(msg.contains("invalid length 1, expected an array of length 2"), "{}", msg);
+        let msg = material("ior = { cauchy = [1.5, 0.004], typo = 1 }").unwrap_err();
+        assert!(msg.contains("unknown field `typo`, expected `cauchy`"), "{}", msg);
+        let msg = material("ior = { sellmeier = { b = [1.04, 0.23, 1.01], c = [0.006, 0.02, 103.6] }, typo = 1 }")
+            .unwrap_err();
+        assert!(msg.contains("unknown field `typo`, expected `sellmeier`"), "{}", msg);
+    }
+
+    #[test]
+    fn test_principled_material() {
+        let scene = load_scene_file("scenes/principled.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 6);
+
+        let material = |desc: &str| {
+            parse_scene(&format!("{}\n[materials.m]\ntype = \"principled\"\n{}\n", CAMERA, desc), "test.toml")
+                .map(|_| ()).map_err(|err| err.to_string())
+        };
+        assert!(material("").is_ok());
+        assert!(material("base_color = [0.9, 0.5, 0.1]\nmetallic = 1\nanisotropy = 0.5").is_ok());
+        let msg = material("sheen = 1.5").unwrap_err();
+        assert!(msg.contains("materials.m.sheen: expected a number from 0 to 1, got 1.5"), "{}", msg);
+        let msg = material("roughness = \"scratches\"").unwrap_err();
+        assert!(msg.contains("materials.m.roughness: unknown texture `scratches`"), "{}", msg);
+        let msg = material("glossiness = 0.5").unwrap_err();
+        assert!(msg.contains("unknown field `glossiness`"), "{}", msg);
+    }
+
+    #[test]
+    fn test_mesh_scene_loads() {
+        let scene = load_scene_file("scenes/octahedron.toml").unwrap();
+        // ground sphere, the mesh (as one BVH), an instance of it and a triangle
+        assert_eq!(scene.world.objects.len(), 4);
+
+        // the instance is half size, standing at x = 1.8, z = 0.8
+        let mut rec = HitRecord::default();
+        let down = Ray::new(&point3(1.8, 5., 0.8), &vec3_(0., -1., 0.));
+        assert!(scene.world.objects[2].hit(&down, 0.001, INF, &mut rec));
+        assert!((rec.p.y - 1.0).abs() < 1e-9, "{:?}", rec.p);
+    }
+
+    #[test]
+    fn test_motion_scene_loads() {
+        let scene = load_scene_file("scenes/motion.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 3);
+        assert_eq!((scene.camera.shutter_open, scene.camera.shutter_close), (0.0, 1.0));
+
+        // the sphere is where `center` says at time 0 and where `center_end` says at time 1
+        let mut rec = HitRecord::default();
+        let at = |x: f64, time: f64| Ray::with_time(&point3(x, 5., 0.), &vec3_(0., -1., 0.), time);
+        assert!(scene.world.objects[1].hit(&at(-2.5, 0.0), 0.001, INF, &mut rec));
+        assert!(!scene.world.objects[1].hit(&at(-2.5, 1.0), 0.001, INF, &mut rec));
+        assert!(scene.world.objects[1].hit(&at(-1.5, 1.0), 0.001, INF, &mut rec));
+
+        let text = scene_with_box("transform = [{ rotate_y = 0 }]\ntransform_end = [{ translate = [1, 0, 0] }]");
+        let msg = error_message(&text);
+        assert!(msg.contains("objects[0].transform_end: expected the same kinds of steps"), "{}", msg);
+    }
+
+    fn scene_with_box(extra: &str) -> String {
+        format!("{}\n[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\n\
+                 [[objects]]\ntype = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\nmaterial = \"m\"\n{}\n",
+                CAMERA, extra)
+    }
+
+    #[test]
+    fn test_fog_objects() {
+        let text = scene_with_box("density = 0.5").replace("lambertian", "isotropic");
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        // fog in the unit box: some rays through it scatter inside, some get through
+        let mut rec = HitRecord::default();
+        let hits = (0..100).filter(|k| {
+            let ray = Ray::new(&point3(0.5, 0.005 * *k as f64 + 0.25, -1.), &vec3_(0., 0., 1.));
+            scene.world.objects[0].hit(&ray, 0.001, INF, &mut rec)
+        }).count();
+        assert!(hits > 20 && hits < 60, "{}", hits);
+        assert!(rec.p.z > 0.0 && rec.p.z < 1.0);
+
+        let msg = error_message(&scene_with_box("density = 0.5"));
+        assert!(msg.contains("objects[0].material: `m` is not isotropic"), "{}", msg);
+        let msg = error_message(&scene_with_box("density = 0"));
+        assert!(msg.contains("objects[0].density: expected a positive number"), "{}", msg);
+    }
+
+    #[test]
+    fn test_flat_boxes_are_rejected() {
+        let msg = error_message(&scene_with_box("").replace("max = [1, 1, 1]", "max = [1, 0, 1]"));
+        assert!(msg.contains("objects[0]: max [1.0, 0.0, 1.0] is not above min [0.0, 0.0, 0.0] on every axis"),
+                "{}", msg);
+        assert!(parse_scene(&scene_with_box("").replace("min = [0, 0, 0]", "min = [2, 0, 0]"), "test.toml").is_err());
+    }
+
+    #[test]
+    fn test_quad_scene_loads() {
+        let scene = load_scene_file("scenes/cornell_box.toml").unwrap();
+        // six quads and two boxes
+        assert_eq!(scene.world.objects.len(), 8);
+        assert!(matches!(scene.background, Background::Solid(_)));
+    }
+
+    #[test]
+    fn test_parse_errors_report_line_and_expectation() {
+        let text = format!("{}\n[materials.glass]\ntype = \"dielectric\"\nior = \"high\"\n", CAMERA);
+        let msg = error_message(&text);
+        assert!(msg.contains("line 6"), "{}", msg);
+        assert!(msg.contains("a number, { cauchy = [a, b] } or { sellmeier"), "{}", msg);
+
+        let text = format!("{}\n[materials.glass]\ntype = \"glass\"\n", CAMERA);
+        let msg = error_message(&text);
+        assert!(msg.contains("line 7"), "{}", msg);
+        assert!(msg.contains("`lambertian`, `metal`, `dielectric`"), "{}", msg);
+
+        let msg = error_message("[camera]\nlookfrom = [0, 0, 0]\nvfov = 90\n");
+        assert!(msg.contains("missing field `lookat`"), "{}", msg);
+    }
+
+    #[test]
+    fn test_object_errors_name_the_field() {
+        let sphere = |fields: &str| format!("{}\n[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\n\
+                                             [[objects]]\ntype = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\n\
+                                             material = \"red\"\n\n[[objects]]\n{}\n", CAMERA, fields);
+        let msg = error_message(&sphere("type = \"sphere\"\ncenter = [0, 0, -1]\nradus = 0.5\nmaterial = \"red\""));
+        assert!(msg.contains("objects[1] at line 16: unknown field `radus`, expected one of `center`, `radius`"),
+                "{}", msg);
+        let msg = error_message(&sphere("type = \"sphere\"\ncenter = [0, 0, -1]\nradius = \"big\"\nmaterial = \"red\""));
+        assert!(msg.contains("objects[1] at line 16: invalid type: string \"big\", expected f64 in `radius`"),
+                "{}", msg);
+        let msg = error_message(&sphere("type = \"cylinder\"\nmaterial = \"red\""));
+        assert!(msg.contains("objects[1] at line 16: unknown variant `cylinder`, expected one of `sphere`"), "{}", msg);
+        let msg = error_message(&sphere("center = [0, 0, -1]"));
+        assert!(msg.contains("objects[1] at line 16: missing field `type`"), "{}", msg);
+        let msg = error_message(&sphere("type = \"quad\"\nq = [0, 0, 0]\nu = [1, 0, 0]\nmaterial = \"red\""));
+        assert!(msg.contains("objects[1] at line 16: missing field `v`"), "{}", msg);
+        // the placement is checked too
+        let msg = error_message(&sphere("type = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5\nmaterial = \"red\"\n\
+                                         density = \"thick\""));
+        assert!(msg.contains("expected f64 in `density`"), "{}", msg);
+    }
+
+    #[test]
+    fn test_unknown_material_is_reported() {
+        let text = format!("{}\n[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\n\
+                            [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5\n\
+                            material = \"blue\"\n", CAMERA);
+        let msg = error_message(&text);
+        assert!(msg.contains("objects[0].material: unknown material `blue`, expected one of: red"), "{}", msg);
+    }
+
+    #[test]
+    fn test_texture_references() {
+        // a checker may use a texture defined after it
+        let text = format!(r#"{}
+            [textures.a]
+            type = "checker"
+            scale = 1
+            even = "b"
+            odd = [0, 0, 0]
+
+            [textures.b]
+            type = "noise"
+            kind = "marble"
+
+            [materials.m]
+            type = "lambertian"
+            albedo = "a"
+            "#, CAMERA);
+        assert!(parse_scene(&text, "test.toml").is_ok());
+
+        let text = format!(r#"{}
+            [textures.a]
+            type = "checker"
+            scale = 1
+            even = "a"
+            odd = [0, 0, 0]
+
+            [materials.m]
+            type = "metal"
+            albedo = "a"
+            "#, CAMERA);
+        let msg = error_message(&text);
+        assert!(msg.contains("textures.a.even: texture `a` contains itself"), "{}", msg);
+
+        let text = format!("{}\n[materials.m]\ntype = \"lambertian\"\nalbedo = \"wood\"\n", CAMERA);
+        let msg = error_message(&text);
+        assert!(msg.contains("materials.m.albedo: unknown texture `wood`"), "{}", msg);
+    }
+
+    #[test]
+    fn test_lights_and_background() {
+        let text = format!(r#"background = [0, 0, 0]
+            {}
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -1]
+            radius = 0.5
+            material = "lamp"
+            "#, CAMERA);
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert!(matches!(scene.background, Background::Solid(ref c) if *c == vec3_(0., 0., 0.)));
+
+        let msg = error_message(&format!("background = \"night\"\n{}", CAMERA));
+        assert!(msg.contains("\"gradient\", an [r, g, b] color, { environment"), "{}", msg);
+    }
+
+    #[test]
+    fn test_environment_background() {
+        let dir = std::env::temp_dir().join("rust_tracing_environment_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut img = image::Rgb32FImage::from_pixel(8, 4, image::Rgb([0.5, 0.5, 0.5]));
+        img.put_pixel(4, 1, image::Rgb([50., 40., 30.]));
+        save_image(&img, dir.join("sky.hdr").to_str().unwrap(), OutputFormat::Hdr, &DisplaySettings::default()).unwrap();
+
+        let origin = dir.join("scene.toml");
+        let origin = origin.to_str().unwrap();
+        let text = format!("background = {{ environment = \"sky.hdr\", intensity = 2 }}\n{}", CAMERA);
+        let scene = parse_scene(&text, origin).unwrap();
+        let Background::Environment(env) = &scene.background else { panic!("{:?}", scene.background) };
+        // still above 1, not tone mapped on the way in; texel (4, 1) is a little right of -z and up
+        let sun = env.color(&vec3_(0.35, 0.38, -0.85));
+        assert!((sun.x - 100.0).abs() < 1.0, "{:?}", sun);
+
+        // a misspelled key is named, with its line, rather than left out
+        let text = format!("background = {{ environment = \"sky.hdr\", intensty = 2 }}\n{}", CAMERA);
+        let msg = parse_scene(&text, origin).err().unwrap().to_string();
+        assert!(msg.contains("line 1") && msg.contains("unknown field `intensty`, expected one of `environment`"),
+                "{}", msg);
+
+        let text = format!("background = {{ environment = \"missing.hdr\" }}\n{}", CAMERA);
+        let msg = parse_scene(&text, origin).err().unwrap().to_string();
+        assert!(msg.contains("background.environment:") && msg.contains("missing.hdr"), "{}", msg);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sky_background() {
+        let text = format!("background = {{ sky = {{ sun_elevation = 15, turbidity = 4 }} }}\n{}", CAMERA);
+        let scene = parse_scene(&text, "scene.toml").unwrap();
+        assert!(matches!(scene.background, Background::Sky(_)));
+        // towards the sun, -z by default
+        let sun = scene.background.color(&Ray::new(&point3(0., 0., 0.), &vec3_(0., 15f64.to_radians().tan(), -1.)));
+        assert!(sun.x > 1000.0 && sun.x > sun.z, "{:?}", sun);
+
+        let text = format!("background = {{ sky = {{ sun_elevation = 120 }} }}\n{}", CAMERA);
+        let msg = parse_scene(&text, "scene.toml").err().unwrap().to_string();
+        assert!(msg.contains("background.sky: sun elevation 120"), "{}", msg);
+        // misspelled keys are named, inside the sky or next to it
+        let text = format!("background = {{ sky = {{ elevation = 10 }} }}\n{}", CAMERA);
+        let msg = parse_scene(&text, "scene.toml").err().unwrap().to_string();
+        assert!(msg.contains("unknown field `elevation`, expected one of `sun_elevation`"), "{}", msg);
+        let text = format!("background = {{ sky = {{ sun_elevation = 30 }}, intensty = 2 }}\n{}", CAMERA);
+        let msg = parse_scene(&text, "scene.toml").err().unwrap().to_string();
+        assert!(msg.contains("line 1") && msg.contains("unknown field `intensty`, expected `sky`"), "{}", msg);
+    }
+}
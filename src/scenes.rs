@@ -0,0 +1,507 @@
+// the worlds that can be rendered by name from the command line
+use std::ops::Range;
+
+use rand::Rng;
+
+use crate::camera::CameraParams;
+use crate::hittable::{HittableList, SharedHittable, hittable_list, hittable_single};
+use crate::integrator::{Background, RayColorFn, ray_color_49, ray_color_71, ray_color_mixture, ray_color_nee};
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::medium::ConstantMedium;
+use crate::quad::{Quad, box_};
+use crate::rtweekend::{RtRng, Shared, random_unif, random_unif_1};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, NoiseKind, NoiseTexture};
+use crate::transform::{Mat4, Transform};
+use crate::vec3::{Color, Vec3, color, point3, vec3_};
+
+pub struct Scene {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub build: fn(&mut RtRng) -> HittableList,
+    // the emitters of the world that integrators may sample directly
+    pub lights: fn() -> HittableList,
+    pub ray_color: RayColorFn,
+    pub background: Background,
+    pub camera: CameraParams,
+    // defaults, all of which can be overridden from the command line
+    pub aspect_ratio: f64,
+    pub img_width: u32,
+    pub samples_per_pixel: i32
+}
+
+pub fn all_scenes() -> Vec<Scene> {
+    let camera_at_origin = CameraParams{
+        lookfrom: point3(0., 0., 0.),
+        lookat: point3(0., 0., -1.),
+        vup: vec3_(0., 1., 0.),
+        vfov_deg: 90.0,
+        aperture: 0.0,
+        focus_dist: None,
+        shutter_open: 0.0,
+        shutter_close: 0.0
+    };
+    let camera_far = CameraParams{
+        lookfrom: point3(13., 2., 3.),
+        lookat: point3(0., 0., 0.),
+        vup: vec3_(0., 1., 0.),
+        vfov_deg: 20.0,
+        aperture: 0.1,
+        focus_dist: Some(10.0),
+        shutter_open: 0.0,
+        shutter_close: 0.0
+    };
+    let camera_cornell = CameraParams{
+        lookfrom: point3(278., 278., -800.),
+        lookat: point3(278., 278., 0.),
+        vfov_deg: 40.0,
+        ..camera_at_origin.clone()
+    };
+    let camera_pinhole = CameraParams{aperture: 0.0, focus_dist: None, ..camera_far.clone()};
+    let small_world = |name, description, build| Scene{
+        name, description, build,
+        lights: no_lights,
+        ray_color: ray_color_49,
+        background: Background::Gradient,
+        camera: camera_at_origin.clone(),
+        aspect_ratio: 16.0 / 9.0,
+        img_width: 400,
+        samples_per_pixel: 100
+    };
+
+    vec![
+        small_world("two_sphere_world",
+                    "listing 24: a sphere resting on a huge ground sphere",
+                    |_| two_sphere_world()),
+        small_world("four_sphere_world_50",
+                    "listing 50: diffuse spheres flanked by two mirrors",
+                    |_| four_sphere_world_50()),
+        small_world("four_sphere_world_52",
+                    "listing 52: like four_sphere_world_50 but with fuzzy metal",
+                    |_| four_sphere_world_52()),
+        small_world("four_sphere_world_55",
+                    "listing 55: two glass spheres and a metal one",
+                    |_| four_sphere_world_55()),
+        small_world("four_sphere_world_60",
+                    "listing 60: hollow glass bubble on the left",
+                    |_| four_sphere_world_60()),
+        Scene{
+            name: "four_sphere_world_65",
+            description: "listing 69: blue diffuse sphere, glass bubble and gold metal, with defocus blur",
+            build: |_| four_sphere_world_65(),
+            lights: no_lights,
+            ray_color: ray_color_49,
+            background: Background::Gradient,
+            camera: CameraParams{
+                lookfrom: point3(3., 3., 2.),
+                lookat: point3(0., 0., -1.),
+                vup: vec3_(0., 1., 0.),
+                vfov_deg: 20.0,
+                aperture: 2.0,
+                ..camera_at_origin.clone()
+            },
+            aspect_ratio: 16.0 / 6.0,
+            img_width: 400,
+            samples_per_pixel: 100
+        },
+        Scene{
+            name: "many_sphere_world_70",
+            description: "listing 70: the final scene of the book, hundreds of random small spheres",
+            build: |rng| many_sphere_world_70(rng),
+            lights: no_lights,
+            ray_color: ray_color_49,
+            background: Background::Gradient,
+            camera: camera_far.clone(),
+            aspect_ratio: 3.0 / 2.0,
+            img_width: 1200,
+            samples_per_pixel: 500
+        },
+        Scene{
+            name: "bouncing_spheres",
+            description: "The Next Week, section 2: many_sphere_world_70 with motion blurred diffuse spheres",
+            build: |rng| bouncing_spheres(rng),
+            lights: no_lights,
+            ray_color: ray_color_49,
+            background: Background::Gradient,
+            camera: CameraParams{shutter_open: 0.0, shutter_close: 1.0, ..camera_pinhole.clone()},
+            aspect_ratio: 16.0 / 9.0,
+            img_width: 400,
+            samples_per_pixel: 100
+        },
+        Scene{
+            name: "marble_v1",
+            description: "fifty metal beads inside a glass marble, lit from above",
+            build: |rng| marble_v1(rng),
+            lights: no_lights,
+            ray_color: ray_color_71,
+            background: Background::Gradient,
+            camera: camera_far,
+            aspect_ratio: 3.0 / 2.0,
+            img_width: 900,
+            samples_per_pixel: 200
+        },
+        Scene{
+            name: "checkered_spheres",
+            description: "The Next Week, section 4: two spheres sharing one 3D checker texture",
+            build: |_| checkered_spheres(),
+            lights: no_lights,
+            ray_color: ray_color_49,
+            background: Background::Gradient,
+            camera: camera_pinhole.clone(),
+            aspect_ratio: 16.0 / 9.0,
+            img_width: 400,
+            samples_per_pixel: 100
+        },
+        Scene{
+            name: "simple_light",
+            description: "The Next Week, section 7: perlin_spheres lit only by two spherical lights",
+            build: |rng| simple_light(rng),
+            lights: simple_light_lights,
+            ray_color: ray_color_nee,
+            background: Background::Solid(color(0., 0., 0.)),
+            camera: CameraParams{
+                lookfrom: point3(26., 3., 6.),
+                lookat: point3(0., 2., 0.),
+                ..camera_pinhole.clone()
+            },
+            aspect_ratio: 16.0 / 9.0,
+            img_width: 400,
+            samples_per_pixel: 400
+        },
+        Scene{
+            name: "cornell_box",
+            description: "The Next Week, section 8: red and green walls, a ceiling light and two turned boxes",
+            build: |_| cornell_box(),
+            lights: || hittable_single(cornell_light()),
+            ray_color: ray_color_nee,
+            background: Background::Solid(color(0., 0., 0.)),
+            camera: camera_cornell.clone(),
+            aspect_ratio: 1.0,
+            img_width: 600,
+            samples_per_pixel: 200
+        },
+        Scene{
+            name: "cornell_glass",
+            description: "The Rest of Your Life, section 12: an aluminium box and a glass ball, bounces drawn \
+                          half towards the light and the ball",
+            build: |_| cornell_glass(),
+            lights: cornell_glass_lights,
+            ray_color: ray_color_mixture,
+            background: Background::Solid(color(0., 0., 0.)),
+            camera: camera_cornell.clone(),
+            aspect_ratio: 1.0,
+            img_width: 600,
+            samples_per_pixel: 100
+        },
+        Scene{
+            name: "cornell_smoke",
+            description: "The Next Week, section 9: cornell_box with a block of smoke and a block of fog",
+            build: |_| cornell_smoke(),
+            lights: || hittable_single(cornell_smoke_light()),
+            ray_color: ray_color_nee,
+            background: Background::Solid(color(0., 0., 0.)),
+            camera: camera_cornell,
+            aspect_ratio: 1.0,
+            img_width: 600,
+            samples_per_pixel: 200
+        },
+        Scene{
+            name: "perlin_spheres",
+            description: "The Next Week, section 5: a marble sphere on turbulent Perlin noise ground",
+            build: |rng| perlin_spheres(rng),
+            lights: no_lights,
+            ray_color: ray_color_49,
+            background: Background::Gradient,
+            camera: camera_pinhole,
+            aspect_ratio: 16.0 / 9.0,
+            img_width: 400,
+            samples_per_pixel: 100
+        },
+    ]
+}
+
+fn no_lights() -> HittableList {
+    hittable_list(vec![])
+}
+
+pub fn find_scene(name: &str) -> Option<Scene> {
+    all_scenes().into_iter().find(|scene| scene.name == name)
+}
+
+
+pub fn marble_v1<R: Rng + ?Sized>(rng: &mut R) -> HittableList {
+    let mut world = hittable_list(vec![]);
+
+    let glass = Dielectric::new(1.5);
+
+    let rc = 2.0;
+    world.add(Sphere::new(point3(0., 0.,0.), rc, glass));
+
+
+    for _i in (Range{start: 0, end: 50}) {
+        let center = (rc - 0.5) * Vec3::rand_in_sphere_1(rng);
+        let albedo = Color::random(rng, 0.5, 1.0);
+        let metal = Metal::new(&albedo, 0.1);
+
+        world.add(Sphere::new(center, 0.15, metal));
+    }
+    world
+}
+
+pub fn checkered_spheres() -> HittableList {
+    let checker = CheckerTexture::with_colors(0.32, color(0.2, 0.3, 0.1), color(0.9, 0.9, 0.9));
+    let material = Lambertian::with_texture(checker);
+    hittable_list(vec![
+        Sphere::new(point3(0., -10., 0.), 10.0, material.clone()),
+        Sphere::new(point3(0.,  10., 0.), 10.0, material)
+    ])
+}
+
+pub fn perlin_spheres<R: Rng + ?Sized>(rng: &mut R) -> HittableList {
+    let ground = Lambertian::with_texture(NoiseTexture::new(rng, 4.0, NoiseKind::Turbulence));
+    let marble = Lambertian::with_texture(NoiseTexture::new(rng, 4.0, NoiseKind::Marble));
+    hittable_list(vec![
+        Sphere::new(point3(0., -1000., 0.), 1000.0, ground),
+        Sphere::new(point3(0., 2., 0.), 2.0, marble)
+    ])
+}
+
+pub fn simple_light<R: Rng + ?Sized>(rng: &mut R) -> HittableList {
+    let mut world = perlin_spheres(rng);
+    for light in simple_light_lights().objects {
+        world.add(light);
+    }
+    world
+}
+
+fn simple_light_lights() -> HittableList {
+    // brighter than 1 so it lights its surroundings
+    let light = DiffuseLight::new(color(4., 4., 4.));
+    hittable_list(vec![
+        Sphere::new(point3(0., 7., 0.), 2.0, light.clone()),
+        Sphere::new(point3(4., 1.5, 3.), 0.7, light)
+    ])
+}
+
+pub fn cornell_box() -> HittableList {
+    let white = Lambertian::new(0.73, 0.73, 0.73);
+    let (tall, short) = cornell_boxes(white);
+
+    let mut world = cornell_walls();
+    world.add(cornell_light());
+    world.add(tall);
+    world.add(short);
+    world
+}
+
+pub fn cornell_glass() -> HittableList {
+    let aluminium = Metal::new(&color(0.8, 0.85, 0.88), 0.0);
+    let (tall, _) = cornell_boxes(aluminium);
+
+    let mut world = cornell_walls();
+    world.add(cornell_light());
+    world.add(tall);
+    world.add(cornell_glass_ball());
+    world
+}
+
+// the ball isn't a light, but sending some bounces to it gives its caustic less noise
+fn cornell_glass_lights() -> HittableList {
+    hittable_list(vec![cornell_light(), cornell_glass_ball()])
+}
+
+fn cornell_glass_ball() -> SharedHittable {
+    Sphere::new(point3(190., 90., 190.), 90.0, Dielectric::new(1.5))
+}
+
+// "The Next Week", section 9.2: the boxes turned into smoke and fog, under a
+// larger and dimmer light
+pub fn cornell_smoke() -> HittableList {
+    let (tall, short) = cornell_boxes(Lambertian::new(0.73, 0.73, 0.73));
+
+    let mut world = cornell_walls();
+    world.add(cornell_smoke_light());
+    world.add(ConstantMedium::new(tall, 0.01, Isotropic::new(color(0., 0., 0.))));
+    world.add(ConstantMedium::new(short, 0.01, Isotropic::new(color(1., 1., 1.))));
+    world
+}
+
+fn cornell_light() -> SharedHittable {
+    Quad::new(point3(343., 554., 332.), vec3_(-130., 0., 0.), vec3_(0., 0., -105.),
+              DiffuseLight::new(color(15., 15., 15.)))
+}
+
+fn cornell_smoke_light() -> SharedHittable {
+    Quad::new(point3(113., 554., 127.), vec3_(330., 0., 0.), vec3_(0., 0., 305.),
+              DiffuseLight::new(color(7., 7., 7.)))
+}
+
+// the empty 555 x 555 x 555 room, open towards the camera
+fn cornell_walls() -> HittableList {
+    let red = Lambertian::new(0.65, 0.05, 0.05);
+    let white = Lambertian::new(0.73, 0.73, 0.73);
+    let green = Lambertian::new(0.12, 0.45, 0.15);
+
+    hittable_list(vec![
+        Quad::new(point3(555., 0., 0.), vec3_(0., 555., 0.), vec3_(0., 0., 555.), green),
+        Quad::new(point3(0., 0., 0.), vec3_(0., 555., 0.), vec3_(0., 0., 555.), red),
+        Quad::new(point3(0., 0., 0.), vec3_(555., 0., 0.), vec3_(0., 0., 555.), white.clone()),
+        Quad::new(point3(555., 555., 555.), vec3_(-555., 0., 0.), vec3_(0., 0., -555.), white.clone()),
+        Quad::new(point3(0., 0., 555.), vec3_(555., 0., 0.), vec3_(0., 555., 0.), white)
+    ])
+}
+
+// "The Next Week", section 8: both boxes built at the origin, then turned and moved
+fn cornell_boxes(material: Shared<dyn Material>) -> (SharedHittable, SharedHittable) {
+    let tall = Transform::new(box_(&point3(0., 0., 0.), &point3(165., 330., 165.), material.clone()),
+                              &Mat4::translate(&vec3_(265., 0., 295.)) * &Mat4::rotate(&vec3_(0., 1., 0.), 15.));
+    let short = Transform::new(box_(&point3(0., 0., 0.), &point3(165., 165., 165.), material),
+                               &Mat4::translate(&vec3_(130., 0., 65.)) * &Mat4::rotate(&vec3_(0., 1., 0.), -18.));
+    (tall, short)
+}
+
+pub fn many_sphere_world_70<R: Rng + ?Sized>(rng: &mut R) -> HittableList {
+    random_spheres(rng, false)
+}
+
+// "The Next Week", section 2: many_sphere_world_70 with the diffuse spheres
+// jumping up while the shutter is open
+pub fn bouncing_spheres<R: Rng + ?Sized>(rng: &mut R) -> HittableList {
+    random_spheres(rng, true)
+}
+
+fn random_spheres<R: Rng + ?Sized>(rng: &mut R, bouncing: bool) -> HittableList {
+    let mut world = hittable_list(vec![]);
+
+    let ground_material = Lambertian::new(0.5, 0.5, 0.5);
+
+    world.add(Sphere::new(point3(0.,-1000.,0.), 1000.0, ground_material));
+
+    for a in (Range{start: -11, end: 11}) {
+        for b in (Range{start: -11, end: 11}) {
+            let choose_mat = random_unif_1(rng);
+            let center =  point3(
+                (a as f64) + 0.9 * random_unif_1(rng),
+                0.2, (b as f64) + 0.9 * random_unif_1(rng));
+
+            if (&center - point3(4., 0.2, 0.)).length() > 0.9 {
+
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = &Color::random(rng, 0., 1.0)
+                                       * &Color::random(rng, 0., 1.0);
+                    let sphere_material = Lambertian::with_color(albedo);
+                    if bouncing {
+                        let center1 = &center + vec3_(0., random_unif(rng, 0., 0.5), 0.);
+                        world.add(MovingSphere::new(center, center1, 0.0, 1.0, 0.2, sphere_material));
+                    } else {
+                        world.add(Sphere::new(center, 0.2, sphere_material));
+                    }
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::random(rng, 0.5, 1.0);
+                    let fuzz = random_unif(rng, 0., 0.5);
+                    let sphere_material = Metal::new(&albedo, fuzz);
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                } else {
+                    // glass
+                    let sphere_material = Dielectric::new(1.5);
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                }
+            }
+        }
+    }
+
+    let material1 = Dielectric::new(1.5);
+    world.add(Sphere::new(point3(0., 1., 0.), 1.0, material1));
+
+    let material2 = Lambertian::new(0.4, 0.2, 0.1);
+    world.add(Sphere::new(point3(-4.0, 1.0, 0.), 1.0, material2));
+
+    let material3 = Metal::new(&point3(0.7, 0.6, 0.5), 0.0);
+    world.add(Sphere::new(point3(4.0, 1.0, 0.0), 1.0, material3));
+
+    world
+}
+
+
+pub fn four_sphere_world_65() -> HittableList {
+
+    four_spheres_given_mats_60(
+        Lambertian::new(0.8, 0.8, 0.0),
+        Lambertian::new(0.1, 0.2, 0.5),
+        Dielectric::new(1.5),
+        Metal::new_rgb(0.8, 0.6, 0.2)
+    )
+}
+
+
+pub fn four_sphere_world_60() -> HittableList {
+
+    four_spheres_given_mats_60(
+        Lambertian::new(0.8, 0.8, 0.0),
+        Dielectric::new(1.5),
+        Dielectric::new(1.5),
+        Metal::new_rgb(0.8, 0.6, 0.2)
+    )
+}
+
+pub fn four_sphere_world_55() -> HittableList {
+
+    four_spheres_given_mats(
+        Lambertian::new(0.8, 0.8, 0.0),
+        Dielectric::new(1.5),
+        Dielectric::new(1.5),
+        Metal::new_rgb(0.8, 0.6, 0.2)
+    )
+}
+
+pub fn four_sphere_world_52() -> HittableList {
+    four_spheres_given_mats(Lambertian::new(0.8, 0.8, 0.0),
+            Lambertian::new(0.7, 0.3, 0.3),
+            Metal::new(&color(0.8, 0.8, 0.8), 0.3),
+            Metal::new(&color(0.8, 0.6, 0.2), 1.0))
+}
+
+
+pub fn four_sphere_world_50() -> HittableList {
+    let material_ground = Lambertian::new(0.8, 0.8, 0.0);
+    let material_center = Lambertian::new(0.7, 0.3, 0.3);
+    let material_left = Metal::new_rgb(0.8, 0.8, 0.8);
+    let material_right = Metal::new_rgb(0.8, 0.6, 0.2);
+
+    four_spheres_given_mats(material_ground, material_center,
+        material_left, material_right)
+}
+
+
+fn four_spheres_given_mats(mat_ground: Shared<dyn Material>, mat_center: Shared<dyn Material>,
+                           mat_left: Shared<dyn Material>, mat_right: Shared<dyn Material>) -> HittableList {
+    hittable_list(vec![
+        Sphere::new(point3( 0., -100.5, -1.0), 100.0, mat_ground),
+        Sphere::new(point3( 0.,   0.0, -1.0), 0.5, mat_center),
+        Sphere::new(point3(-1.,   0.0, -1.0), 0.5, mat_left),
+        Sphere::new(point3( 1.,   0.0, -1.0), 0.5, mat_right)
+    ])
+}
+
+fn four_spheres_given_mats_60(mat_ground: Shared<dyn Material>,
+                           mat_center: Shared<dyn Material>,
+                           mat_left: Shared<dyn Material>,
+                           mat_right: Shared<dyn Material>) -> HittableList {
+    hittable_list(vec![
+        Sphere::new(point3( 0., -100.5, -1.0), 100.0, mat_ground),
+        Sphere::new(point3( 0.,   0.0, -1.0), 0.5, mat_center),
+        Sphere::new(point3(-1.,   0.0, -1.0), 0.5, mat_left.clone()),
+        Sphere::new(point3(-1.,   0.0, -1.0), -0.45, mat_left),
+        Sphere::new(point3( 1.,   0.0, -1.0), 0.5, mat_right)
+    ])
+}
+
+
+pub fn two_sphere_world() -> HittableList {
+    hittable_list(vec![
+        Sphere::new_cr( vec3_(0., 0., -1.), 0.5),
+        Sphere::new_cr( vec3_(0., -100.5, -1.), 100.0)
+    ])
+}
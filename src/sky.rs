@@ -0,0 +1,194 @@
+// daylight from the analytic sky model of Preetham, Shirley and Smits, "A
+// Practical Analytic Model for Daylight" (1999), with the sun itself as a
+// disk of finite size that light samples aim at. Radiance is in kcd/m^2
+// scaled by SKY_SCALE; the sun is as much brighter than the sky as the
+// model says, so noon has hard shadows and a low sun comes out orange.
+use std::f64::consts::PI;
+
+use crate::pdf::{Onb, random_to_sphere};
+use crate::rtweekend::{RtRng, clamp};
+use crate::spectrum::xyz_to_srgb;
+use crate::vec3::{Color, Vec3, color, vec3_};
+
+// scene radiance per kcd/m^2: a white diffuse surface facing a high sun
+// comes out near 1
+const SKY_SCALE: f64 = 1.0 / 40.0;
+// half the angle the sun covers, seen from the earth
+const SUN_RADIUS_DEG: f64 = 0.2665;
+// luminance of the sun before the atmosphere, kcd/m^2
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+#[derive(Debug)]
+pub struct Sky {
+    // towards the middle of the sun
+    sun: Vec3,
+    // angle between the sun and the zenith
+    theta_sun: f64,
+    cos_sun_radius: f64,
+    sun_radiance: Color,
+    // luminance Y and chromaticity x, y at the zenith, and the coefficients
+    // A to E of the distribution of each over the sky
+    zenith: [f64; 3],
+    coefficients: [[f64; 5]; 3]
+}
+
+impl Sky {
+    // the sun `elevation_deg` above the horizon, `azimuth_deg` from -z
+    // towards +x; `turbidity` from 2 (very clear) to 10 (hazy)
+    pub fn new(elevation_deg: f64, azimuth_deg: f64, turbidity: f64) -> Result<Self, String> {
+        if !(0.0..=90.0).contains(&elevation_deg) {
+            return Err(format!("sun elevation {} is not between 0 and 90 degrees", elevation_deg))
+        }
+        if !(1.7..=10.0).contains(&turbidity) {
+            return Err(format!("turbidity {} is not between 1.7 and 10", turbidity))
+        }
+        let (elevation, azimuth) = (elevation_deg.to_radians(), azimuth_deg.to_radians());
+        let sun = vec3_(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        let theta_sun = PI / 2.0 - elevation;
+        let t = turbidity;
+
+        let coefficients = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let cubic = |c: [f64; 4]| ((c[0] * theta_sun + c[1]) * theta_sun + c[2]) * theta_sun + c[3];
+        let zenith = [
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t * t * cubic([0.00166, -0.00375, 0.00209, 0.0]) + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+                + cubic([0.11693, -0.21196, 0.06052, 0.25886]),
+            t * t * cubic([0.00275, -0.00610, 0.00317, 0.0]) + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+                + cubic([0.15346, -0.26756, 0.06670, 0.26688])
+        ];
+
+        let sun_radiance = SKY_SCALE * SUN_LUMINANCE * &sun_transmittance(theta_sun, turbidity);
+        Ok(Sky{sun, theta_sun, cos_sun_radius: SUN_RADIUS_DEG.to_radians().cos(), sun_radiance, zenith,
+               coefficients})
+    }
+
+    pub fn color(&self, direction: &Vec3) -> Color {
+        let d = direction.unit_vector();
+        let sky = self.sky(&d);
+        if d.dot(&self.sun) >= self.cos_sun_radius { &sky + &self.sun_radiance } else { sky }
+    }
+
+    // a direction towards the sun
+    pub fn sample(&self, rng: &mut RtRng) -> Vec3 {
+        let sin_radius = (1.0 - self.cos_sun_radius * self.cos_sun_radius).sqrt();
+        Onb::new(&self.sun).local(&random_to_sphere(rng, sin_radius, 1.0))
+    }
+
+    // density of `sample`, per unit solid angle
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        if direction.unit_vector().dot(&self.sun) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        } else {
+            0.0
+        }
+    }
+
+    // the sky without the sun; below the horizon it keeps the color at the
+    // horizon, for scenes without a ground
+    fn sky(&self, d: &Vec3) -> Color {
+        let cos_theta = d.y.max(1e-3);
+        let gamma = clamp(d.dot(&self.sun), -1.0, 1.0).acos();
+        let [y, cx, cy] = [0, 1, 2].map(|i| {
+            let perez = |cos_theta: f64, gamma: f64| {
+                let [a, b, c, d, e] = self.coefficients[i];
+                (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+            };
+            self.zenith[i] * perez(cos_theta, gamma) / perez(1.0, self.theta_sun)
+        });
+        SKY_SCALE * &xyz_to_rgb(&vec3_(cx / cy * y, y, (1.0 - cx - cy) / cy * y))
+    }
+}
+
+// linear sRGB, D65 white
+fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    let rgb = xyz_to_srgb(xyz);
+    color(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+// part of the sunlight that gets through the air at the wavelengths of the
+// red, green and blue primaries (Preetham et al. appendix A.2: Rayleigh
+// scattering and Angstrom's haze; ozone and water vapor are left out)
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    // relative optical mass of the air along the way
+    let m = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |lambda_um: f64| {
+        let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * m).exp();
+        let aerosol = (-beta * lambda_um.powf(-1.3) * m).exp();
+        rayleigh * aerosol
+    };
+    color(transmittance(0.612), transmittance(0.549), transmittance(0.465))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::rng_from_seed;
+    use crate::spectrum::luminance;
+
+    #[test]
+    fn test_sky_colors() {
+        let noon = Sky::new(80.0, 0.0, 3.0).unwrap();
+        let up = noon.color(&vec3_(0., 1., 0.));
+        // the clear sky is blue overhead and paler at the horizon
+        assert!(up.z > up.x, "{:?}", up);
+        let horizon = noon.color(&vec3_(0., 0.05, 1.));
+        assert!(horizon.x / horizon.z > up.x / up.z, "{:?} vs {:?}", horizon, up);
+        // brightest around the sun
+        let near_sun = noon.sky(&vec3_(0.05, 0.98, -0.17).unit_vector());
+        assert!(luminance(&near_sun) > luminance(&up), "{:?} vs {:?}", near_sun, up);
+
+        // the sun is far brighter than the sky, and turns red when low
+        let sun = noon.color(&noon.sun);
+        assert!(luminance(&sun) > 1e4 * luminance(&up), "{:?}", sun);
+        let sunset = Sky::new(2.0, 90.0, 3.0).unwrap();
+        let low_sun = sunset.sun_radiance.clone();
+        assert!(low_sun.x > 2.0 * low_sun.z && low_sun.x < sun.x, "{:?}", low_sun);
+        assert!(sunset.color(&vec3_(1., 0.03, 0.)).x > sunset.color(&vec3_(-1., 0.03, 0.)).x);
+
+        assert!(Sky::new(-5.0, 0.0, 3.0).is_err());
+        assert!(Sky::new(30.0, 0.0, 20.0).is_err());
+    }
+
+    // the distribution is normalized so that straight up is the zenith value
+    #[test]
+    fn test_zenith() {
+        for (elevation, turbidity) in [(80.0, 3.0), (30.0, 2.0), (5.0, 8.0)] {
+            let sky = Sky::new(elevation, 20.0, turbidity).unwrap();
+            let [y, cx, cy] = sky.zenith;
+            let expected = SKY_SCALE * &xyz_to_rgb(&vec3_(cx / cy * y, y, (1.0 - cx - cy) / cy * y));
+            let up = sky.sky(&vec3_(0., 1., 0.));
+            assert!((&up - &expected).length() < 1e-9 * expected.length(), "{:?} vs {:?}", up, expected);
+        }
+    }
+
+    #[test]
+    fn test_sun_sampling() {
+        let sky = Sky::new(30.0, 45.0, 2.5).unwrap();
+        let mut rng = rng_from_seed(2);
+        let sun_solid_angle = 2.0 * PI * (1.0 - sky.cos_sun_radius);
+        for _ in 0..1000 {
+            let dir = sky.sample(&mut rng);
+            assert!((sky.pdf(&dir) * sun_solid_angle - 1.0).abs() < 1e-9);
+            assert!((luminance(&(&sky.color(&dir) - &sky.sun_radiance)) - luminance(&sky.sky(&dir))).abs() < 1e-6);
+        }
+        assert_eq!(sky.pdf(&vec3_(0., 1., 0.)), 0.0);
+
+        // a white surface facing a high sun is lit to about 1
+        let high = Sky::new(90.0, 0.0, 3.0).unwrap();
+        let n = 200_000;
+        let sky_light: f64 = (0..n).map(|_| {
+            let d = Vec3::random_unit_vector(&mut rng);
+            luminance(&high.sky(&d)) * d.y.max(0.0) * 4.0 * PI
+        }).sum::<f64>() / n as f64;
+        let sun_light = luminance(&high.sun_radiance) * 2.0 * PI * (1.0 - high.cos_sun_radius);
+        let white = (sky_light + sun_light) / PI;
+        assert!((0.7..1.5).contains(&white), "{}", white);
+    }
+}
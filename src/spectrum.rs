@@ -0,0 +1,254 @@
+// spectral rendering: a path carries the radiance at three wavelengths
+// instead of red, green and blue, in the three channels of a Color (hero
+// wavelength sampling, Wilkie et al. 2014: the first, the "hero", is drawn
+// at random and the others follow at equal steps). RGB colors of materials,
+// lights and backgrounds are turned into spectra by Smits' method (1999),
+// and the film turns the radiance back into sRGB through the CIE 1931
+// color matching functions.
+use std::sync::OnceLock;
+
+use crate::vec3::{Color, Vec3, color, vec3_};
+
+// the visible range the wavelengths are drawn from, in nm
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+// the sodium d-line, where indices of refraction are usually given; RGB
+// rendering refracts everything at this wavelength
+pub const LAMBDA_D: f64 = 587.6;
+
+const N_WAVELENGTHS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wavelengths {
+    // in nm, the hero first
+    pub lambda: [f64; N_WAVELENGTHS],
+    // only the hero goes on, after a surface that bends each wavelength its own way
+    pub terminated: bool
+}
+
+impl Wavelengths {
+    // `u` uniform in [0, 1), the others wrap around the range
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let lambda = std::array::from_fn(|i| {
+            LAMBDA_MIN + (u + i as f64 / N_WAVELENGTHS as f64).fract() * range
+        });
+        Wavelengths{lambda, terminated: false}
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // the values at these wavelengths of a spectrum that looks like `rgb`
+    pub fn upsample(&self, rgb: &Color) -> Color {
+        let [a, b, c] = self.lambda.map(|lambda| smits(rgb, lambda));
+        color(a, b, c)
+    }
+
+    // for a surface whose light can't follow the same direction at every
+    // wavelength: the wavelengths that go on and the factor of the path's
+    // radiance, which now counts the hero for all of them
+    pub fn terminate_secondary(&self) -> (Wavelengths, Color) {
+        if self.terminated {
+            (*self, color(1., 1., 1.))
+        } else {
+            (Wavelengths{terminated: true, ..*self}, color(N_WAVELENGTHS as f64, 0., 0.))
+        }
+    }
+
+    // the linear sRGB color of `radiance` at these wavelengths, white
+    // balanced so that a flat spectrum of 1 is white (1, 1, 1)
+    pub fn rgb(&self, radiance: &Color) -> Color {
+        let values = [radiance.x, radiance.y, radiance.z];
+        let mut xyz = vec3_(0., 0., 0.);
+        for (lambda, value) in self.lambda.iter().zip(values) {
+            // divided by the density of each wavelength, averaged over the three
+            xyz += &((value * (LAMBDA_MAX - LAMBDA_MIN) / N_WAVELENGTHS as f64) * color_matching(*lambda));
+        }
+        let white = white_rgb();
+        let rgb = xyz_to_srgb(&xyz);
+        color(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+    }
+}
+
+// the CIE 1931 2° observer's x̄, ȳ and z̄ at `lambda` (nm), as fitted with
+// piecewise Gaussians by Wyman, Sloan and Shirley (2013)
+pub fn color_matching(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let t = (lambda - mu) / if lambda < mu { sigma_below } else { sigma_above };
+        (-0.5 * t * t).exp()
+    };
+    vec3_(1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+          0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+          1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8))
+}
+
+// linear sRGB (D65) from CIE XYZ; colors outside the sRGB gamut get negative components
+pub fn xyz_to_srgb(xyz: &Vec3) -> Color {
+    color(3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+          -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+          0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z)
+}
+
+// Y of a linear sRGB color
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+// the sRGB color of a flat spectrum of 1 over the sampled range
+fn white_rgb() -> &'static Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    WHITE.get_or_init(|| {
+        let n = 3400;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / n as f64;
+        let mut xyz = vec3_(0., 0., 0.);
+        for k in 0..n {
+            xyz += &(step * color_matching(LAMBDA_MIN + (k as f64 + 0.5) * step));
+        }
+        xyz_to_srgb(&xyz)
+    })
+}
+
+// Smits' spectra of white, cyan, magenta, yellow, red, green and blue, in
+// ten bins from LAMBDA_MIN to LAMBDA_MAX
+const SMITS_BINS: [[f64; 7]; 10] = [
+    [1.0000, 0.9710, 1.0000, 0.0001, 0.1088, 0.0000, 1.0000],
+    [1.0000, 0.9426, 1.0000, 0.0000, 0.0659, 0.0000, 1.0000],
+    [0.9999, 1.0007, 0.9685, 0.1088, 0.0000, 0.0273, 0.8916],
+    [0.9993, 1.0007, 0.2229, 0.6651, 0.0000, 0.7937, 0.3323],
+    [0.9992, 1.0007, 0.0000, 1.0000, 0.0000, 1.0000, 0.0000],
+    [0.9998, 1.0007, 0.0458, 1.0000, 0.0000, 0.9418, 0.0000],
+    [1.0000, 0.1564, 0.8369, 0.9996, 0.8325, 0.1719, 0.0003],
+    [1.0000, 0.0000, 1.0000, 0.9586, 1.0149, 0.0000, 0.0369],
+    [1.0000, 0.0000, 1.0000, 0.9685, 1.0149, 0.0000, 0.0483],
+    [1.0000, 0.0000, 0.9959, 0.9840, 1.0149, 0.0000, 0.0496]
+];
+const WHITE: usize = 0;
+const CYAN: usize = 1;
+const MAGENTA: usize = 2;
+const YELLOW: usize = 3;
+const RED: usize = 4;
+const GREEN: usize = 5;
+const BLUE: usize = 6;
+
+// the value at `lambda` of a smooth spectrum with the color `rgb`: as
+// much white as the smallest component, then as much of the secondary
+// color of the two largest as the middle one has left, and the rest of
+// the largest. Between bin centers the spectra are interpolated.
+fn smits(rgb: &Color, lambda: f64) -> f64 {
+    let bin_width = (LAMBDA_MAX - LAMBDA_MIN) / SMITS_BINS.len() as f64;
+    let x = ((lambda - LAMBDA_MIN) / bin_width - 0.5).clamp(0.0, (SMITS_BINS.len() - 1) as f64);
+    let k = (x as usize).min(SMITS_BINS.len() - 2);
+    let t = x - k as f64;
+    let basis = |spectrum: usize| (1.0 - t) * SMITS_BINS[k][spectrum] + t * SMITS_BINS[k + 1][spectrum];
+
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    if r <= g && r <= b {
+        r * basis(WHITE) + if g <= b {
+            (g - r) * basis(CYAN) + (b - g) * basis(BLUE)
+        } else {
+            (b - r) * basis(CYAN) + (g - b) * basis(GREEN)
+        }
+    } else if g <= r && g <= b {
+        g * basis(WHITE) + if r <= b {
+            (r - g) * basis(MAGENTA) + (b - r) * basis(BLUE)
+        } else {
+            (b - g) * basis(MAGENTA) + (r - b) * basis(RED)
+        }
+    } else {
+        b * basis(WHITE) + if r <= g {
+            (r - b) * basis(YELLOW) + (g - r) * basis(GREEN)
+        } else {
+            (g - b) * basis(YELLOW) + (r - g) * basis(RED)
+        }
+    }
+}
+
+// index of refraction as a function of the wavelength
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / λ², λ in µm
+    Cauchy{a: f64, b: f64},
+    // n² = 1 + Σ b λ² / (λ² - c), λ in µm
+    Sellmeier{b: [f64; 3], c: [f64; 3]}
+}
+
+impl Ior {
+    pub fn at(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.0).powi(2);
+        match self {
+            Ior::Constant(eta) => *eta,
+            Ior::Cauchy{a, b} => a + b / l2,
+            Ior::Sellmeier{b, c} => {
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).max(1.0).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the fit is close to the tabulated functions, whose ȳ integrates to 106.857
+    #[test]
+    fn test_color_matching() {
+        let y: f64 = (360..830).map(|lambda| color_matching(lambda as f64 + 0.5).y).sum();
+        assert!((y - 106.857).abs() < 0.5, "{}", y);
+        let peak = color_matching(555.0).y;
+        assert!((peak - 1.0).abs() < 0.01, "{}", peak);
+    }
+
+    // upsampled colors come back through the film about as they were
+    #[test]
+    fn test_round_trip() {
+        let n = 6000;
+        for rgb in [color(1., 1., 1.), color(0.5, 0.5, 0.5), color(1., 0., 0.), color(0., 1., 0.), color(0., 0., 1.),
+                    color(0.9, 0.4, 0.1), color(0.2, 0.5, 0.8), color(3., 2., 1.)] {
+            let mut mean = color(0., 0., 0.);
+            for k in 0..n {
+                let wavelengths = Wavelengths::sample((k as f64 + 0.5) / n as f64);
+                mean += &(wavelengths.rgb(&wavelengths.upsample(&rgb)) / n as f64);
+            }
+            let tolerance = if rgb.x == rgb.y && rgb.y == rgb.z { 0.01 } else { 0.15 };
+            assert!((&mean - &rgb).length() < tolerance * rgb.length(), "{:?} -> {:?}", rgb, mean);
+        }
+    }
+
+    #[test]
+    fn test_wavelengths() {
+        let wavelengths = Wavelengths::sample(0.9);
+        assert!((wavelengths.hero() - 686.0).abs() < 1e-9);
+        // the next ones a third of the range further, wrapped around
+        assert!((wavelengths.lambda[1] - (LAMBDA_MIN + (0.9 + 1.0 / 3.0 - 1.0) * 340.0)).abs() < 1e-9);
+        assert!(wavelengths.lambda.iter().all(|l| (LAMBDA_MIN..LAMBDA_MAX).contains(l)));
+
+        // only the first termination counts the hero three times
+        let (terminated, factor) = wavelengths.terminate_secondary();
+        assert_eq!(factor, color(3., 0., 0.));
+        assert_eq!(terminated.terminate_secondary(), (terminated, color(1., 1., 1.)));
+    }
+
+    #[test]
+    fn test_ior() {
+        // BK7 glass, given both ways
+        let cauchy = Ior::Cauchy{a: 1.5046, b: 0.00420};
+        let sellmeier = Ior::Sellmeier{b: [1.03961212, 0.231792344, 1.01046945],
+                                       c: [0.00600069867, 0.0200179144, 103.560653]};
+        for ior in [&cauchy, &sellmeier] {
+            assert!((ior.at(LAMBDA_D) - 1.5168).abs() < 2e-3, "{:?}", ior);
+            // blue bends more than red
+            assert!(ior.at(450.0) > ior.at(650.0) + 0.005);
+            assert!(ior.is_dispersive());
+        }
+        assert_eq!(Ior::Constant(1.5).at(450.0), 1.5);
+        assert!(!Ior::Constant(1.5).is_dispersive());
+    }
+}
@@ -1,9 +1,13 @@
 
+use std::f64::consts::PI;
+
 use crate::hittable::{Hittable, HitRecord};
-use crate::vec3::{Point3};
+use crate::vec3::{Point3, Vec3, vec3_};
 use crate::ray::Ray;
-use crate::rtweekend::Shared;
+use crate::pdf::{Onb, random_to_sphere};
+use crate::rtweekend::{INF, RtRng, Shared};
 use crate::material::{Material, Lambertian};
+use crate::aabb::{Aabb, surrounding_box};
 
 // listing 15
 pub struct Sphere {
@@ -25,34 +29,127 @@ impl Sphere {
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc = &ray.origin - &self.center;
-        let a = ray.dir.length_squared();
-        let half_b = oc.dot(&ray.dir);
-        let c = oc.dot(&oc) - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
-            false
+        hit_sphere(&self.center, self.radius, &self.material, ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(sphere_box(&self.center, self.radius))
+    }
+
+    // uniform over the cone the sphere fills seen from `origin`, "The Rest
+    // of Your Life" section 12.3
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(&Ray::new(origin, direction), 0.001, INF, &mut rec) {
+            return 0.0
+        }
+
+        let distance_squared = (&self.center - origin).length_squared();
+        let r2 = self.radius * self.radius;
+        if distance_squared <= r2 {
+            // from inside, every direction hits
+            return 1.0 / (4.0 * PI)
+        }
+        let cos_theta_max = (1.0 - r2 / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut RtRng) -> Vec3 {
+        let direction = &self.center - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector(rng)
+        }
+        Onb::new(&direction).local(&random_to_sphere(rng, self.radius, distance_squared))
+    }
+}
+
+
+// "The Next Week", section 2: a sphere whose center moves in a straight line
+// from center0 at time0 to center1 at time1
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Shared<dyn Material>
+}
+
+impl MovingSphere {
+    pub fn new(center0: Point3, center1: Point3, time0: f64, time1: f64, radius: f64,
+               material: Shared<dyn Material>) -> Shared<MovingSphere> {
+        Shared::new(MovingSphere{center0, center1, time0, time1, radius, material})
+    }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        // stays at the ends outside [time0, time1], inside the bounding box
+        let s = if self.time1 > self.time0 {
+            ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.)
         } else {
-            let sqrtd = discriminant.sqrt();
-            let mut root = (-half_b - sqrtd)/a;
+            0.
+        };
+        &self.center0 + s * (&self.center1 - &self.center0)
+    }
+}
 
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        hit_sphere(&self.center(ray.time), self.radius, &self.material, ray, t_min, t_max, rec)
+    }
+
+    // the whole sweep, whatever the shutter
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(surrounding_box(&sphere_box(&self.center0, self.radius), &sphere_box(&self.center1, self.radius)))
+    }
+}
+
+
+fn hit_sphere(center: &Point3, radius: f64, material: &Shared<dyn Material>,
+              ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    let oc = &ray.origin - center;
+    let a = ray.dir.length_squared();
+    let half_b = oc.dot(&ray.dir);
+    let c = oc.dot(&oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {
+        false
+    } else {
+        let sqrtd = discriminant.sqrt();
+        let mut root = (-half_b - sqrtd)/a;
+
+        if root < t_min || t_max < root {
+            root = (-half_b + sqrtd) / a;
             if root < t_min || t_max < root {
-                root = (-half_b + sqrtd) / a;
-                if root < t_min || t_max < root {
-                    return false
-                }
+                return false
             }
-            rec.t = root;
-            rec.p = ray.at(rec.t);
-            let outward_normal= (&rec.p - &self.center) / self.radius;
-            rec.set_face_normal(ray, &outward_normal);
-            rec.material = self.material.clone();
-            true
         }
-
+        rec.t = root;
+        rec.p = ray.at(rec.t);
+        let outward_normal= (&rec.p - center) / radius;
+        rec.set_face_normal(ray, &outward_normal);
+        let on_unit = (&rec.p - center) / radius.abs();
+        (rec.u, rec.v) = sphere_uv(&on_unit);
+        // along the circle of latitude, as u goes once around
+        rec.tangent = (2.0 * PI * radius.abs()) * &vec3_(on_unit.z, 0., -on_unit.x);
+        rec.material = material.clone();
+        true
     }
 }
 
+fn sphere_box(center: &Point3, radius: f64) -> Aabb {
+    let r = radius.abs();
+    let rvec = vec3_(r, r, r);
+    Aabb::new(center - &rvec, center + &rvec)
+}
+
+// (u, v) in [0, 1]^2 of a point on the unit sphere: u is the angle around the
+// y axis starting from x = -1, v the angle from y = -1 up to y = 1
+pub fn sphere_uv(p: &Vec3) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
 
 
 // listing 10
@@ -76,9 +173,9 @@ pub fn hit_sphere_11(center: &Point3, radius: f64, r: &Ray) -> f64 {
 
 
     if discriminant > 0. {
-        return (-b - discriminant.sqrt()) / 2.0
+        (-b - discriminant.sqrt()) / 2.0
     } else {
-        return -1.
+        -1.
     }
 }
 
@@ -91,8 +188,35 @@ pub fn hit_sphere_12(center: &Point3, radius: f64, r: &Ray) -> f64 {
     let discriminant = half_b * half_b - a * c;
 
     if discriminant < 0.0 {
-        return -1.0
+        -1.0
     } else {
-        return (-half_b - discriminant.sqrt()) / a;
+        (-half_b - discriminant.sqrt()) / a
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::point3;
+
+    #[test]
+    fn test_sphere_uv() {
+        // the table from "The Next Week", section 4.5
+        let cases = [((1., 0., 0.), (0.5, 0.5)), ((0., 1., 0.), (0.5, 1.0)), ((0., 0., 1.), (0.25, 0.5)),
+                     ((-1., 0., 0.), (0.0, 0.5)), ((0., -1., 0.), (0.5, 0.0)), ((0., 0., -1.), (0.75, 0.5))];
+        for ((x, y, z), (eu, ev)) in cases {
+            let (u, v) = sphere_uv(&vec3_(x, y, z));
+            assert!((u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12, "{:?} -> {:?}", (x, y, z), (u, v));
+        }
+    }
+
+    #[test]
+    fn test_moving_sphere_stops_at_the_ends() {
+        let sphere = MovingSphere::new(point3(0., 0., 0.), point3(2., 0., 0.), 0.0, 1.0, 0.5,
+                                       Lambertian::new(0.5, 0.5, 0.5));
+        assert_eq!(sphere.center(0.5), point3(1., 0., 0.));
+        assert_eq!(sphere.center(-1.0), point3(0., 0., 0.));
+        assert_eq!(sphere.center(3.0), point3(2., 0., 0.));
     }
 }
@@ -0,0 +1,190 @@
+// textures, "The Next Week" sections 4 to 6
+use image::RgbImage;
+use rand::Rng;
+
+use crate::display::srgb_eotf;
+use crate::perlin::Perlin;
+use crate::rtweekend::{Shared, clamp};
+use crate::vec3::{Color, Point3, color};
+
+pub trait Texture: Send + Sync {
+    // color at surface coordinates (u, v) of hit point p
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    color_value: Color
+}
+
+impl SolidColor {
+    pub fn new(color_value: Color) -> Shared<dyn Texture> {
+        Shared::new(SolidColor{color_value})
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color_value.clone()
+    }
+}
+
+
+// 3D checker board: cubes of side `scale` alternate between `even` and `odd`
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Shared<dyn Texture>,
+    odd: Shared<dyn Texture>
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Shared<dyn Texture>, odd: Shared<dyn Texture>) -> Shared<dyn Texture> {
+        Shared::new(CheckerTexture{inv_scale: 1.0 / scale, even, odd})
+    }
+
+    pub fn with_colors(scale: f64, even: Color, odd: Color) -> Shared<dyn Texture> {
+        Self::new(scale, SolidColor::new(even), SolidColor::new(odd))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x).floor() as i64;
+        let y = (self.inv_scale * p.y).floor() as i64;
+        let z = (self.inv_scale * p.z).floor() as i64;
+
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+
+pub struct ImageTexture {
+    img: RgbImage,
+    // colors are sRGB encoded, other data such as roughness maps linear
+    srgb: bool
+}
+
+impl ImageTexture {
+    pub fn new(img: RgbImage, srgb: bool) -> Shared<dyn Texture> {
+        Shared::new(ImageTexture{img, srgb})
+    }
+
+    pub fn load(path: &str, srgb: bool) -> Result<Shared<dyn Texture>, image::ImageError> {
+        Ok(Self::new(image::open(path)?.to_rgb8(), srgb))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let (width, height) = self.img.dimensions();
+        if width == 0 || height == 0 {
+            // debugging aid: solid cyan
+            return color(0., 1., 1.)
+        }
+
+        // v = 0 is the bottom of the image
+        let u = clamp(u, 0.0, 1.0);
+        let v = 1.0 - clamp(v, 0.0, 1.0);
+        let i = ((u * width as f64) as u32).min(width - 1);
+        let j = ((v * height as f64) as u32).min(height - 1);
+
+        let pixel = self.img.get_pixel(i, j);
+        let decode = |c: u8| if self.srgb { srgb_eotf(c as f64 / 255.0) } else { c as f64 / 255.0 };
+        color(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]))
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseKind {
+    // plain Perlin noise
+    Smooth,
+    // several octaves of noise
+    Turbulence,
+    // turbulence phase-shifting a sine along z: marble veins
+    Marble
+}
+
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+    kind: NoiseKind
+}
+
+impl NoiseTexture {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R, scale: f64, kind: NoiseKind) -> Shared<dyn Texture> {
+        Shared::new(NoiseTexture{noise: Perlin::new(rng), scale, kind})
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let grey = match self.kind {
+            NoiseKind::Smooth => 0.5 * (1.0 + self.noise.noise(&(self.scale * p))),
+            // the octaves add up to a bit under 1 in practice, but nothing bounds them by 1
+            NoiseKind::Turbulence => self.noise.turb(&(self.scale * p), 7).min(1.0),
+            NoiseKind::Marble => 0.5 * (1.0 + (self.scale * p.z + 10.0 * self.noise.turb(p, 7)).sin())
+        };
+        color(grey, grey, grey)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtweekend::rng_from_seed;
+    use crate::vec3::point3;
+
+    #[test]
+    fn test_checker_alternates() {
+        let checker = CheckerTexture::with_colors(1.0, color(1., 1., 1.), color(0., 0., 0.));
+        assert_eq!(checker.value(0., 0., &point3(0.5, 0.5, 0.5)), color(1., 1., 1.));
+        assert_eq!(checker.value(0., 0., &point3(1.5, 0.5, 0.5)), color(0., 0., 0.));
+        assert_eq!(checker.value(0., 0., &point3(-0.5, 0.5, 0.5)), color(0., 0., 0.));
+        assert_eq!(checker.value(0., 0., &point3(-0.5, -0.5, 0.5)), color(1., 1., 1.));
+    }
+
+    #[test]
+    fn test_image_texture_lookup() {
+        // 2x2 image: top row red, green; bottom row blue, white
+        let img = RgbImage::from_fn(2, 2, |i, j| match (i, j) {
+            (0, 0) => image::Rgb([255, 0, 0]),
+            (1, 0) => image::Rgb([0, 255, 0]),
+            (0, 1) => image::Rgb([0, 0, 255]),
+            _ => image::Rgb([255, 255, 255])
+        });
+        let tex = ImageTexture::new(img.clone(), true);
+        let p = point3(0., 0., 0.);
+        assert_eq!(tex.value(0.25, 0.75, &p), color(1., 0., 0.));
+        assert_eq!(tex.value(0.75, 0.75, &p), color(0., 1., 0.));
+        assert_eq!(tex.value(0.25, 0.25, &p), color(0., 0., 1.));
+        assert_eq!(tex.value(1.0, 0.0, &p), color(1., 1., 1.));
+
+        // data maps are read as they are, colors decoded from sRGB
+        let gray = RgbImage::from_pixel(1, 1, image::Rgb([128, 128, 128]));
+        assert!((ImageTexture::new(gray.clone(), false).value(0.5, 0.5, &p).x - 128.0 / 255.0).abs() < 1e-12);
+        assert!((ImageTexture::new(gray, true).value(0.5, 0.5, &p).x - 0.2158).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_noise_in_range() {
+        let mut rng = rng_from_seed(3);
+        for kind in [NoiseKind::Smooth, NoiseKind::Turbulence, NoiseKind::Marble] {
+            let tex = NoiseTexture::new(&mut rng, 1.0, kind);
+            // every eighth of a lattice cell over 3 x 3 x 3 cells, off the lattice points
+            for a in 0..24 {
+                for b in 0..24 {
+                    for c in 0..24 {
+                        let p = point3(a as f64 / 8.0 + 0.03, b as f64 / 8.0 + 0.05, c as f64 / 8.0 + 0.07);
+                        let grey = tex.value(0., 0., &p).x;
+                        assert!((0.0..=1.0).contains(&grey), "{:?} at {:?}: {}", kind, p, grey);
+                    }
+                }
+            }
+        }
+    }
+}
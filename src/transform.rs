@@ -0,0 +1,397 @@
+// instancing: any Hittable placed in the world by an affine 4x4 matrix, fixed
+// or changing while the shutter is open
+use std::borrow::Cow;
+use std::ops::Mul;
+
+use crate::aabb::{Aabb, surrounding_box};
+use crate::hittable::{HitRecord, Hittable, SharedHittable};
+use crate::ray::Ray;
+use crate::rtweekend::{Shared, degrees_to_radians};
+use crate::vec3::{Point3, Vec3, point3, vec3_};
+
+// row major, acting on column vectors: m * (x, y, z, 1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4]
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Self::from_3x3([[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]])
+    }
+
+    fn from_3x3(a: [[f64; 3]; 3]) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for i in 0..3 {
+            m[i][..3].copy_from_slice(&a[i]);
+        }
+        m[3][3] = 1.;
+        Mat4{m}
+    }
+
+    pub fn translate(offset: &Vec3) -> Mat4 {
+        let mut t = Self::identity();
+        t.m[0][3] = offset.x;
+        t.m[1][3] = offset.y;
+        t.m[2][3] = offset.z;
+        t
+    }
+
+    pub fn scale(factors: &Vec3) -> Mat4 {
+        Self::from_3x3([[factors.x, 0., 0.], [0., factors.y, 0.], [0., 0., factors.z]])
+    }
+
+    // counterclockwise when looking down `axis` towards the origin (Rodrigues)
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Mat4 {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let k = 1. - cos;
+        Self::from_3x3([
+            [cos + a.x * a.x * k,       a.x * a.y * k - a.z * sin, a.x * a.z * k + a.y * sin],
+            [a.y * a.x * k + a.z * sin, cos + a.y * a.y * k,       a.y * a.z * k - a.x * sin],
+            [a.z * a.x * k - a.y * sin, a.z * a.y * k + a.x * sin, cos + a.z * a.z * k]
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.m[j][i];
+            }
+        }
+        Mat4{m}
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&r, &s| a[r][col].abs().total_cmp(&a[s][col].abs())).unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4{m: inv})
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 { point3(x, y, z) } else { point3(x / w, y / w, z / w) }
+    }
+
+    // directions ignore the translation
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        vec3_(m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+              m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+              m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z)
+    }
+}
+
+// `a * b` applies b first, then a
+impl Mul<&Mat4> for &Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: &Mat4) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4{m}
+    }
+}
+
+
+// one step of a placement, kept as parameters so that two keyframes can be blended
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformStep {
+    Translate(Vec3),
+    Scale(Vec3),
+    // axis, degrees
+    Rotate(Vec3, f64)
+}
+
+impl TransformStep {
+    pub fn matrix(&self) -> Mat4 {
+        match self {
+            TransformStep::Translate(offset) => Mat4::translate(offset),
+            TransformStep::Scale(factors) => Mat4::scale(factors),
+            TransformStep::Rotate(axis, degrees) => Mat4::rotate(axis, *degrees)
+        }
+    }
+
+    fn inverse_matrix(&self) -> Mat4 {
+        match self {
+            TransformStep::Translate(offset) => Mat4::translate(&-offset),
+            TransformStep::Scale(f) => Mat4::scale(&vec3_(1. / f.x, 1. / f.y, 1. / f.z)),
+            TransformStep::Rotate(axis, degrees) => Mat4::rotate(axis, -degrees)
+        }
+    }
+
+    // `s` of the way from self to `end`; None unless both are the same kind of step
+    pub fn lerp(&self, end: &TransformStep, s: f64) -> Option<TransformStep> {
+        let mix = |a: &Vec3, b: &Vec3| (1. - s) * a + s * b;
+        match (self, end) {
+            (TransformStep::Translate(a), TransformStep::Translate(b)) => Some(TransformStep::Translate(mix(a, b))),
+            (TransformStep::Scale(a), TransformStep::Scale(b)) => Some(TransformStep::Scale(mix(a, b))),
+            // angles are blended, not matrices, so a turning object keeps its shape.
+            // A turn about -axis is the opposite turn about axis: axes pointing
+            // apart are flipped first, or they would blend through zero.
+            (TransformStep::Rotate(axis_a, a), TransformStep::Rotate(axis_b, b)) => {
+                let (axis_a, axis_b) = (axis_a.unit_vector(), axis_b.unit_vector());
+                let (axis_b, b) = if axis_a.dot(&axis_b) < 0. { (-&axis_b, -b) } else { (axis_b, *b) };
+                Some(TransformStep::Rotate(mix(&axis_a, &axis_b), (1. - s) * a + s * b))
+            },
+            _ => None
+        }
+    }
+}
+
+// the steps as one matrix, the first step applied first
+pub fn compose(steps: &[TransformStep]) -> Mat4 {
+    steps.iter().fold(Mat4::identity(), |m, step| &step.matrix() * &m)
+}
+
+#[derive(Clone)]
+struct Placement {
+    to_world: Mat4,
+    to_object: Mat4,
+    // inverse transpose of to_world, for normals
+    normal_to_world: Mat4
+}
+
+impl Placement {
+    fn new(to_world: Mat4, to_object: Mat4) -> Placement {
+        let normal_to_world = to_object.transpose();
+        Placement{to_world, to_object, normal_to_world}
+    }
+
+    fn from_steps(steps: &[TransformStep]) -> Placement {
+        let to_object = steps.iter().rev().fold(Mat4::identity(), |m, step| &step.inverse_matrix() * &m);
+        Placement::new(compose(steps), to_object)
+    }
+}
+
+enum Motion {
+    Fixed(Box<Placement>),
+    // keyframes at time0 and time1, blended step by step in between
+    Keyframes { start: Vec<TransformStep>, end: Vec<TransformStep>, time0: f64, time1: f64 }
+}
+
+impl Motion {
+    fn steps_at(start: &[TransformStep], end: &[TransformStep], s: f64) -> Vec<TransformStep> {
+        start.iter().zip(end).map(|(a, b)| a.lerp(b, s).unwrap()).collect()
+    }
+
+    fn placement(&self, time: f64) -> Cow<'_, Placement> {
+        match self {
+            Motion::Fixed(placement) => Cow::Borrowed(placement.as_ref()),
+            Motion::Keyframes{start, end, time0, time1} => {
+                let s = if time1 > time0 { ((time - time0) / (time1 - time0)).clamp(0., 1.) } else { 0. };
+                Cow::Owned(Placement::from_steps(&Self::steps_at(start, end, s)))
+            }
+        }
+    }
+}
+
+// `object` as placed in the world by a matrix, or moving between two
+// keyframes; the object itself is shared, not copied, so one mesh can be
+// placed many times
+pub struct Transform {
+    object: SharedHittable,
+    motion: Motion,
+    bbox: Option<Aabb>
+}
+
+impl Transform {
+    pub fn new(object: SharedHittable, to_world: Mat4) -> Shared<Transform> {
+        let to_object = to_world.inverse().expect("transform matrix is singular");
+        let bbox = object.bounding_box().map(|b| transformed_box(&b, &to_world));
+        Shared::new(Transform{object, motion: Motion::Fixed(Box::new(Placement::new(to_world, to_object))), bbox})
+    }
+
+    // moves from `start` at time0 to `end` at time1, which must have the same
+    // kinds of steps in the same order
+    pub fn animated(object: SharedHittable, start: Vec<TransformStep>, end: Vec<TransformStep>,
+                    time0: f64, time1: f64) -> Shared<Transform> {
+        assert!(start.len() == end.len() && start.iter().zip(&end).all(|(a, b)| a.lerp(b, 0.).is_some()),
+                "keyframes need the same kinds of steps in the same order");
+
+        // the box at evenly spaced times, grown by the most any corner moves
+        // between two of them so that it holds the paths in between too
+        const N: usize = 32;
+        let bbox = object.bounding_box().map(|b| {
+            let boxes: Vec<Aabb> = (0..=N)
+                .map(|k| transformed_box(&b, &compose(&Motion::steps_at(&start, &end, k as f64 / N as f64))))
+                .collect();
+            let step = boxes.windows(2)
+                .map(|w| (&w[1].minimum - &w[0].minimum).length().max((&w[1].maximum - &w[0].maximum).length()))
+                .fold(0.0, f64::max);
+            let union = boxes.into_iter().reduce(|acc, b| surrounding_box(&acc, &b)).unwrap();
+            let grow = vec3_(step, step, step);
+            Aabb::new(&union.minimum - &grow, &union.maximum + &grow)
+        });
+        Shared::new(Transform{object, motion: Motion::Keyframes{start, end, time0, time1}, bbox})
+    }
+}
+
+fn transformed_box(b: &Aabb, m: &Mat4) -> Aabb {
+    let corners = (0..8).map(|k| m.point(&point3(
+        if k & 1 == 0 { b.minimum.x } else { b.maximum.x },
+        if k & 2 == 0 { b.minimum.y } else { b.maximum.y },
+        if k & 4 == 0 { b.minimum.z } else { b.maximum.z })));
+    corners.map(|c| Aabb::new(c.clone(), c))
+           .reduce(|acc, c| surrounding_box(&acc, &c))
+           .unwrap()
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let placement = self.motion.placement(ray.time);
+        // the direction is not renormalized, so t means the same in both spaces
+        let object_ray = Ray::with_time(&placement.to_object.point(&ray.origin),
+                                        &placement.to_object.vector(&ray.dir), ray.time);
+        if !self.object.hit(&object_ray, t_min, t_max, rec) {
+            return false
+        }
+
+        // front_face carries over: ray . (M^-T n) == (M^-1 ray) . n
+        rec.p = placement.to_world.point(&rec.p);
+        rec.normal = placement.normal_to_world.vector(&rec.normal).unit_vector();
+        rec.tangent = placement.to_world.vector(&rec.tangent);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_inverse_and_composition() {
+        let m = &(&Mat4::translate(&vec3_(1., 2., 3.)) * &Mat4::rotate(&vec3_(1., 1., 0.), 30.))
+              * &Mat4::scale(&vec3_(2., 0.5, 3.));
+        let product = &m * &m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1. } else { 0. };
+                assert!((product.m[i][j] - expected).abs() < 1e-12);
+            }
+        }
+        assert!(Mat4::scale(&vec3_(1., 0., 1.)).inverse().is_none());
+
+        // +90 degrees about y takes +z to +x, and translation only moves points
+        let rot = Mat4::rotate(&vec3_(0., 1., 0.), 90.);
+        assert_close(&rot.vector(&vec3_(0., 0., 1.)), &vec3_(1., 0., 0.));
+        let tr = Mat4::translate(&vec3_(1., 2., 3.));
+        assert_close(&tr.point(&point3(0., 0., 0.)), &point3(1., 2., 3.));
+        assert_close(&tr.vector(&vec3_(0., 0., 1.)), &vec3_(0., 0., 1.));
+    }
+
+    #[test]
+    fn test_scaled_sphere_normals() {
+        // unit sphere squashed to half height and moved to z = -5
+        let sphere = Sphere::new(point3(0., 0., 0.), 1.0, Lambertian::new(0.5, 0.5, 0.5));
+        let ellipsoid = Transform::new(sphere, &Mat4::translate(&vec3_(0., 0., -5.))
+                                             * &Mat4::scale(&vec3_(1., 0.5, 1.)));
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(&point3(0., 0., 0.), &vec3_(0., 0., -1.));
+        assert!(ellipsoid.hit(&ray, 0.001, 100.0, &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_close(&rec.p, &point3(0., 0., -4.));
+        assert_close(&rec.normal, &vec3_(0., 0., 1.));
+        assert!(rec.front_face);
+
+        // on the flattened side the normal tilts towards y much more than the position does
+        let ray = Ray::new(&point3(0., 0.25, 0.), &vec3_(0., 0., -1.));
+        assert!(ellipsoid.hit(&ray, 0.001, 100.0, &mut rec));
+        let z = rec.p.z + 5.0;
+        assert_close(&rec.normal, &vec3_(0., 4.0 * 0.25, z).unit_vector());
+
+        let bbox = ellipsoid.bounding_box().unwrap();
+        assert_close(&bbox.minimum, &point3(-1., -0.5, -6.));
+        assert_close(&bbox.maximum, &point3(1., 0.5, -4.));
+    }
+
+    #[test]
+    fn test_keyframes_blend_steps() {
+        // a unit cube turning a quarter turn about y while moving 10 along x
+        let cube = crate::quad::box_(&point3(-0.5, -0.5, -0.5), &point3(0.5, 0.5, 0.5),
+                                     Lambertian::new(0.5, 0.5, 0.5));
+        let y = vec3_(0., 1., 0.);
+        let start = vec![TransformStep::Rotate(y.clone(), 0.), TransformStep::Translate(vec3_(0., 0., -5.))];
+        let end = vec![TransformStep::Rotate(y, 90.), TransformStep::Translate(vec3_(10., 0., -5.))];
+        let moving = Transform::animated(cube, start, end, 0.0, 1.0);
+        let mut rec = HitRecord::default();
+
+        // half way it is turned 45 degrees: the nearest edge is sqrt(1/2) from the center
+        let ray = Ray::with_time(&point3(5., 0., 0.), &vec3_(0., 0., -1.), 0.5);
+        assert!(moving.hit(&ray, 0.001, 100.0, &mut rec));
+        assert!((rec.t - (5.0 - 0.5f64.sqrt())).abs() < 1e-9, "{}", rec.t);
+        let ray = Ray::with_time(&point3(5., 0., 0.), &vec3_(0., 0., -1.), 0.0);
+        assert!(!moving.hit(&ray, 0.001, 100.0, &mut rec));
+
+        // every position along the way is inside the box
+        let bbox = moving.bounding_box().unwrap();
+        for k in 0..=100 {
+            let ray = Ray::with_time(&point3(k as f64 / 10., 0., 0.), &vec3_(0., 0., -1.), k as f64 / 100.);
+            if moving.hit(&ray, 0.001, 100.0, &mut rec) {
+                assert!(bbox.hit(&ray, 0.001, 100.0));
+                assert!(rec.p.x >= bbox.minimum.x && rec.p.x <= bbox.maximum.x);
+            }
+        }
+        assert!(bbox.minimum.x < -0.7 && bbox.maximum.x > 10.7);
+    }
+
+    #[test]
+    fn test_lerp_opposite_axes() {
+        // 30 degrees about -y is -30 degrees about y, so half way is no turn at all
+        let start = TransformStep::Rotate(vec3_(0., 1., 0.), -30.);
+        let end = TransformStep::Rotate(vec3_(0., -2., 0.), 30.);
+        for k in 0..=10 {
+            let Some(TransformStep::Rotate(axis, degrees)) = start.lerp(&end, k as f64 / 10.) else { panic!() };
+            assert!(axis.length() > 0.5, "{:?}", axis);
+            assert!((degrees + 30.).abs() < 1e-9, "{}", degrees);
+        }
+        let m = start.lerp(&end, 0.5).unwrap().matrix();
+        let p = m.point(&point3(1., 2., 3.));
+        assert_close(&p, &Mat4::rotate(&vec3_(0., 1., 0.), -30.).point(&point3(1., 2., 3.)));
+    }
+}
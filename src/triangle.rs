@@ -0,0 +1,141 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::Shared;
+use crate::vec3::{Point3, Vec3, point3};
+
+// vertex buffers shared by all the triangles of a mesh
+#[derive(Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>
+}
+
+// indices into a TriangleMesh's buffers
+#[derive(Debug, Clone)]
+pub struct MeshFace {
+    pub v: [usize; 3],
+    pub n: Option<[usize; 3]>,
+    pub uv: Option<[usize; 3]>
+}
+
+pub struct Triangle {
+    mesh: Shared<TriangleMesh>,
+    face: MeshFace,
+    material: Shared<dyn Material>
+}
+
+impl Triangle {
+    // a lone triangle, with flat shading and barycentric uv coordinates
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, material: Shared<dyn Material>) -> Shared<Triangle> {
+        let mesh = TriangleMesh{positions: vec![p0, p1, p2], ..Default::default()};
+        Self::in_mesh(Shared::new(mesh), MeshFace{v: [0, 1, 2], n: None, uv: None}, material)
+    }
+
+    pub fn in_mesh(mesh: Shared<TriangleMesh>, face: MeshFace,
+                   material: Shared<dyn Material>) -> Shared<Triangle> {
+        Shared::new(Triangle{mesh, face, material})
+    }
+
+    fn vertex(&self, k: usize) -> &Point3 {
+        &self.mesh.positions[self.face.v[k]]
+    }
+}
+
+impl Hittable for Triangle {
+    // Moller-Trumbore
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let p0 = self.vertex(0);
+        let edge1 = self.vertex(1) - p0;
+        let edge2 = self.vertex(2) - p0;
+
+        let pvec = ray.dir.cross(&edge2);
+        let det = edge1.dot(&pvec);
+        if det.abs() < 1e-12 {
+            // ray parallel to the triangle
+            return false
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = &ray.origin - p0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return false
+        }
+        let qvec = tvec.cross(&edge1);
+        let b2 = ray.dir.dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return false
+        }
+        let t = edge2.dot(&qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return false
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.set_face_normal(ray, &edge1.cross(&edge2).unit_vector());
+        if let Some(n) = self.face.n {
+            // interpolated shading normal, kept on the same side as the geometric one
+            let normals = &self.mesh.normals;
+            let shading = (b0 * &normals[n[0]] + b1 * &normals[n[1]] + b2 * &normals[n[2]]).unit_vector();
+            rec.normal = if shading.dot(&rec.normal) < 0.0 { -shading } else { shading };
+        }
+        (rec.u, rec.v, rec.tangent) = match self.face.uv {
+            Some(uv) => {
+                let uvs = &self.mesh.uvs;
+                // dp/du solves edge_k = du_k dp/du + dv_k dp/dv for both edges
+                let (du1, dv1) = (uvs[uv[1]].0 - uvs[uv[0]].0, uvs[uv[1]].1 - uvs[uv[0]].1);
+                let (du2, dv2) = (uvs[uv[2]].0 - uvs[uv[0]].0, uvs[uv[2]].1 - uvs[uv[0]].1);
+                let det = du1 * dv2 - du2 * dv1;
+                let tangent = if det != 0.0 { (dv2 * &edge1 - dv1 * &edge2) / det } else { Vec3::default() };
+                (b0 * uvs[uv[0]].0 + b1 * uvs[uv[1]].0 + b2 * uvs[uv[2]].0,
+                 b0 * uvs[uv[0]].1 + b1 * uvs[uv[1]].1 + b2 * uvs[uv[2]].1,
+                 tangent)
+            },
+            None => (b1, b2, edge1.clone())
+        };
+        rec.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (p0, p1, p2) = (self.vertex(0), self.vertex(1), self.vertex(2));
+        let minimum = point3(p0.x.min(p1.x).min(p2.x), p0.y.min(p1.y).min(p2.y), p0.z.min(p1.z).min(p2.z));
+        let maximum = point3(p0.x.max(p1.x).max(p2.x), p0.y.max(p1.y).max(p2.y), p0.z.max(p1.z).max(p2.z));
+        Some(Aabb::new(minimum, maximum).pad(1e-4))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::vec3_;
+
+    #[test]
+    fn test_triangle_hit() {
+        let tri = Triangle::new(point3(0., 0., -1.), point3(1., 0., -1.), point3(0., 1., -1.),
+                                Lambertian::new(0.5, 0.5, 0.5));
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(&point3(0.25, 0.25, 0.), &vec3_(0., 0., -1.));
+        assert!(tri.hit(&ray, 0.001, 10.0, &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert_eq!(rec.normal, vec3_(0., 0., 1.));
+        assert!(rec.front_face);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert_eq!(rec.tangent, vec3_(1., 0., 0.));
+
+        let miss = Ray::new(&point3(0.75, 0.75, 0.), &vec3_(0., 0., -1.));
+        assert!(!tri.hit(&miss, 0.001, 10.0, &mut rec));
+
+        // the box of a triangle lying in a z plane must still be hit
+        let bbox = tri.bounding_box().unwrap();
+        assert!(bbox.hit(&ray, 0.001, 10.0));
+    }
+}
@@ -1,7 +1,9 @@
 
 use std::{ops::{AddAssign, MulAssign, DivAssign, Add, Mul, Div, Sub, Neg}, fmt::Debug};
-use crate::rtweekend::{random_unif};
-use rand::rngs::ThreadRng;
+use std::f64::consts::PI;
+
+use crate::rtweekend::{RtRng, random_unif, random_unif_1};
+use rand::Rng;
 
 
 #[derive(Debug, Clone)]
@@ -44,6 +46,16 @@ impl Vec3 {
             z: self.x * rhs.y - self.y * rhs.x,
         }
     }
+
+    // component by index: 0 -> x, 1 -> y, 2 -> z
+    pub fn axis(&self, i: usize) -> f64 {
+        match i {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("axis index {} out of range", i)
+        }
+    }
 }
 
 impl Default for Vec3 {
@@ -226,13 +238,13 @@ impl Vec3 {
         self.clone() / self.length()
     }
 
-    pub fn rand_unif(rng: &mut ThreadRng, min: f64, max: f64) -> Self {
+    pub fn rand_unif<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Self {
         vec3_(random_unif(rng, min, max),
               random_unif(rng, min, max),
               random_unif(rng, min, max))
     }
 
-    pub fn rand_in_sphere_1(rng: &mut ThreadRng) -> Self {
+    pub fn rand_in_sphere_1<R: Rng + ?Sized>(rng: &mut R) -> Self {
         loop {
             let vec = vec3_(random_unif(rng, -1.0, 1.0),
                                   random_unif(rng, -1.0, 1.0),
@@ -245,7 +257,7 @@ impl Vec3 {
         }
     }
 
-    pub fn rand_hemisphere(rng: &mut ThreadRng, normal: &Vec3) -> Self {
+    pub fn rand_hemisphere<R: Rng + ?Sized>(rng: &mut R, normal: &Vec3) -> Self {
         // listing 39
         let in_unit_sphere = Vec3::rand_in_sphere_1(rng);
 
@@ -256,35 +268,48 @@ impl Vec3 {
         }
     }
 
-    pub fn random_unit_vector(rng: &mut ThreadRng) -> Self {
-        Self::rand_in_sphere_1(rng).unit_vector()
+    // rand_in_sphere_1 for materials: a direction and a radius, always three
+    // numbers (a rejection loop takes a varying number, which would mix up a
+    // sampler's dimensions)
+    pub fn random_in_unit_ball(rng: &mut RtRng) -> Self {
+        let direction = Self::random_unit_vector(rng);
+        random_unif_1(rng).cbrt() * direction
     }
 
-    // listint 68
-    pub fn random_in_disk_1(rng: &mut ThreadRng) -> Self {
-        loop {
-            let p = vec3_(
-                random_unif(rng, -1., 1.),
-                random_unif(rng, -1.0, 1.0),
-                0.0
-            );
-            if p.length_squared() >= 1.0 {
-                continue
-            } else {
-                return p
-            }
+    // uniform on the unit sphere from one 2D sample
+    pub fn random_unit_vector(rng: &mut RtRng) -> Self {
+        let (r1, r2) = rng.unif_2d();
+        let z = 1.0 - 2.0 * r1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * r2;
+        vec3_(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // listint 68, as Shirley and Chiu's concentric mapping of one 2D sample:
+    // neighbouring points of the square stay neighbours on the disk
+    pub fn random_in_disk_1(rng: &mut RtRng) -> Self {
+        let (r1, r2) = rng.unif_2d();
+        let (a, b) = (2.0 * r1 - 1.0, 2.0 * r2 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return vec3_(0., 0., 0.)
         }
+        let (r, phi) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        vec3_(r * phi.cos(), r * phi.sin(), 0.0)
     }
 
     // listing 45
     pub fn near_zero(&self) -> bool {
         let eps = 1e-8;
-        return (self.x.abs() < eps) & (self.y.abs() < eps) & (self.z.abs() < eps)
+        (self.x.abs() < eps) & (self.y.abs() < eps) & (self.z.abs() < eps)
     }
 
     // listing 47
     pub fn reflect(&self, normal: &Vec3) -> Vec3 {
-        return self - &(2.0 * self.dot(normal) * normal);
+        self - &(2.0 * self.dot(normal) * normal)
     }
 
     // listing 53
@@ -330,6 +355,19 @@ pub(crate) fn test_refract() {
     assert_eq!(diff, 0.0)
 }
 
+pub type Point3 = Vec3;
+pub type Color = Vec3;
+
+impl Color {
+    pub fn random<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Self {
+        Color{
+            x: random_unif(rng, min, max),
+            y: random_unif(rng, min, max),
+            z: random_unif(rng, min, max)
+        }
+    }
+}
+
 
 #[cfg(test)]
 pub mod tests {
@@ -368,17 +406,4 @@ pub mod tests {
 
 
 
-}
-
-pub type Point3 = Vec3;
-pub type Color = Vec3;
-
-impl Color {
-    pub fn random(rng: &mut ThreadRng, min: f64, max: f64) -> Self {
-        Color{
-            x: random_unif(rng, min, max),
-            y: random_unif(rng, min, max),
-            z: random_unif(rng, min, max)
-        }
-    }
 }
\ No newline at end of file
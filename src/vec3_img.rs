@@ -1,33 +1,41 @@
 
 use image::Rgb;
+use crate::display::{DisplaySettings, srgb_oetf};
 use crate::vec3::Vec3;
 
-pub type RGB = Rgb<u8>;
+pub type Rgb8 = Rgb<u8>;
+pub type Rgb32 = Rgb<f32>;
 
 impl Vec3 {
     // this corresponds to listing_6
-    pub fn to_rgb(self) -> RGB {
+    pub fn to_rgb(&self) -> Rgb8 {
         color(self.x, self.y, self.z)
     }
 
     // listing 29
-    pub fn to_rgb_sampled(self, samples_per_pixel: i32) -> RGB {
+    pub fn to_rgb_sampled(&self, samples_per_pixel: i32) -> Rgb8 {
         let scale = 1.0 / (samples_per_pixel as f64);
 
         color(self.x * scale, self.y * scale, self.z * scale)
     }
-}
 
-fn color(r: f64, g: f64, b: f64) -> RGB {
-    // listing 35
-    Rgb([(255.999 * r.sqrt()) as u8,
-         (255.999 * g.sqrt()) as u8,
-         (255.999 * b.sqrt()) as u8])
+    // the same average, linear and unclamped, for the float framebuffer
+    pub fn to_rgb32_sampled(&self, samples_per_pixel: i32) -> Rgb32 {
+        let scale = 1.0 / (samples_per_pixel as f64);
+        Rgb([(self.x * scale) as f32, (self.y * scale) as f32, (self.z * scale) as f32])
+    }
+}
 
+fn color(r: f64, g: f64, b: f64) -> Rgb8 {
+    // listing 35 took the square root as gamma 2, this is the real sRGB curve
+    let display = DisplaySettings::default();
+    Rgb([display.quantize(srgb_oetf(r.max(0.0)), 0, 0, 0),
+         display.quantize(srgb_oetf(g.max(0.0)), 0, 0, 1),
+         display.quantize(srgb_oetf(b.max(0.0)), 0, 0, 2)])
 }
 
 
-pub fn color_no_gamma(r: f64, g: f64, b: f64) -> RGB {
+pub fn color_no_gamma(r: f64, g: f64, b: f64) -> Rgb8 {
     // before listing 35
     Rgb([(255.999 * r) as u8,
          (255.999 * g) as u8,